@@ -1,27 +1,92 @@
 use std::borrow::Cow;
 use std::env;
+use std::ffi::OsString;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::thread;
 use std::time::{Duration, Instant};
 
-use chip8emu::{Cpu, CpuState, KeyCode, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8emu::{
+    compile_octo, write_png, write_wav, BinaryTrace, Buzzer, Cpu, CpuError, CpuState, Debugger,
+    GifWriter, KeyCode, Movie, Platform, Profiler, Random, RandomSource, Rewinder, StopReason,
+    TextTrace, TraceFormat, TraceRing, TraceSink, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH,
+    PALETTE,
+};
+use sdl2::audio::{AudioCallback, AudioSpecDesired};
 use sdl2::event::Event;
-use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 
-const SCALE: u16 = 20;
-const DISPLAY_SIZE: usize = (DISPLAY_WIDTH * DISPLAY_HEIGHT) as usize;
-const WINDOW_WIDTH: u32 = (DISPLAY_WIDTH * SCALE) as u32;
-const WINDOW_HEIGHT: u32 = (DISPLAY_HEIGHT * SCALE) as u32;
-const FPS: u32 = 540;
+const SCALE: u16 = 10;
+const WINDOW_WIDTH: u32 = (HIRES_DISPLAY_WIDTH * SCALE) as u32;
+const WINDOW_HEIGHT: u32 = (HIRES_DISPLAY_HEIGHT * SCALE) as u32;
+const FPS: u32 = 60;
 const SLEEP_DURATION: Duration = Duration::from_nanos((10_u32.pow(9) / FPS) as u64);
+// 540 instructions per second.
+const CYCLES_PER_FRAME: u32 = 9;
+
+// A snapshot every 6 frames, 10 per second, for rewinding with Backspace.
+const REWIND_INTERVAL: u32 = 6;
+const REWIND_BUDGET: usize = 32 * 1024 * 1024;
+
+// Quick save slots, selected with F6/F7.
+const SAVE_SLOTS: u8 = 10;
+
+const SAMPLE_RATE: u32 = 44100;
+
+// GIFs are smaller than the window, 512x256.
+const GIF_SCALE: u16 = 4;
+
+// Most instructions that step over or out of a subroutine may run.
+const STEP_CYCLES: u32 = 1_000_000;
+
+// Instructions shown before an error when tracing.
+const TRACE_HISTORY: usize = 32;
+
+// Lines per table of the text profile.
+const PROFILE_LINES: usize = 20;
+
+const ROM_START_ADDR: u16 = 0x200;
 
 static IBM_LOGO: &[u8] = include_bytes!("../../IBM_Logo.ch8");
 
+struct Args {
+    rom_path: Option<OsString>,
+    platform: Platform,
+    seed: Option<u64>,
+    wav_path: Option<OsString>,
+    record_path: Option<OsString>,
+    play_path: Option<OsString>,
+    trace_path: Option<OsString>,
+    trace_format: Option<String>,
+    profile_path: Option<OsString>,
+    coverage_path: Option<OsString>,
+    breakpoints: Vec<u16>,
+    watch_code: bool,
+}
+
+// Plays the beep on the SDL audio thread.
+struct Beeper {
+    buzzer: Buzzer,
+    active: bool,
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.buzzer.fill(self.active, out);
+    }
+}
+
 fn main() {
+    let args = parse_args();
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
@@ -54,17 +119,105 @@ fn main() {
     // `present`. We need to call this every time we want to render a new frame on the window.
     canvas.present();
 
-    let rom_path = env::args_os().nth(1);
-    let bin: Cow<[u8]> = match rom_path {
+    let desired =
+        AudioSpecDesired { freq: Some(SAMPLE_RATE as i32), channels: Some(1), samples: None };
+    let audio_device = sdl_context.audio().and_then(|audio| {
+        audio.open_playback(None, &desired, |spec| Beeper {
+            buzzer: Buzzer::new(spec.freq as u32),
+            active: false,
+        })
+    });
+    let mut audio_device = match audio_device {
+        Ok(device) => {
+            device.resume();
+            Some(device)
+        }
+        Err(e) => {
+            eprintln!("No sound: {}", e);
+            None
+        }
+    };
+    let mut muted = false;
+    // The WAV capture is made frame by frame, independently of the audio device.
+    let mut recorder = args.wav_path.as_ref().map(|_| (Buzzer::new(SAMPLE_RATE), Vec::new()));
+
+    let bin: Cow<[u8]> = match &args.rom_path {
+        Some(path) if Path::new(path).extension().is_some_and(|ext| ext == "8o") => {
+            let source = fs::read_to_string(path).unwrap();
+            match compile_octo(&source) {
+                Ok(rom) => rom.into(),
+                Err(e) => {
+                    eprintln!("{}:{}", Path::new(path).display(), e);
+                    process::exit(1);
+                }
+            }
+        }
         Some(path) => fs::read(path).unwrap().into(),
         None => {
             eprintln!("Opening default IBM_LOGO rom ...");
             IBM_LOGO.into()
         }
     };
-    let mut cpu: Cpu = Cpu::new();
+    // Movies replace the quirks and the seed of the command line with theirs.
+    let mut movie = match (&args.play_path, &args.record_path) {
+        (Some(path), _) => match fs::read(path).map(|bytes| Movie::from_bytes(&bytes)) {
+            Ok(Ok(movie)) => Some(movie),
+            Ok(Err(e)) => {
+                eprintln!("Cannot play {}: {}", path.to_string_lossy(), e);
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Cannot read {}: {}", path.to_string_lossy(), e);
+                process::exit(1);
+            }
+        },
+        (None, Some(_)) => {
+            let seed = args.seed.unwrap_or_else(|| Random::new().state());
+            Some(Movie::new(args.platform.quirks(), seed))
+        }
+        (None, None) => None,
+    };
+    let mut playing = args.play_path.is_some();
+    let mut cpu: Cpu = match (&movie, args.seed) {
+        (Some(movie), _) => movie.cpu(),
+        (None, Some(seed)) => Cpu::with_seed(args.platform.quirks(), seed),
+        (None, None) => Cpu::new(args.platform.quirks()),
+    };
     cpu.load_game(&bin).unwrap();
+    cpu.set_coverage(args.coverage_path.is_some());
+    let mut tracers: Vec<Box<dyn TraceSink>> = Vec::new();
+    let history = args.trace_path.as_ref().map(|path| {
+        let history = TraceRing::new(TRACE_HISTORY);
+        match open_trace(path, args.trace_format.as_deref()) {
+            Ok(sink) => tracers.push(Box::new((history.clone(), sink))),
+            Err(e) => {
+                eprintln!("Cannot write {}: {}", path.to_string_lossy(), e);
+                process::exit(1);
+            }
+        }
+        history
+    });
+    let profiler = args.profile_path.as_ref().map(|_| Profiler::new());
+    if let Some(profiler) = &profiler {
+        tracers.push(Box::new(profiler.clone()));
+    }
+    if !tracers.is_empty() {
+        cpu.set_tracer(Some(Box::new(tracers)));
+    }
+    let mut frame = 0;
+    let mut gif: Option<(PathBuf, Gif)> = None;
 
+    let mut slot = 0;
+    let mut rewinder = Rewinder::new(REWIND_INTERVAL, REWIND_BUDGET);
+    let mut rewinding = false;
+    let mut debugger = Debugger::new();
+    for &pc in &args.breakpoints {
+        debugger.add_breakpoint(pc);
+    }
+    if args.watch_code {
+        debugger.watch_code_writes();
+    }
+    let mut stopped = false;
     let mut event_pump = sdl_context.event_pump().unwrap();
     'running: loop {
         for event in event_pump.poll_iter() {
@@ -72,16 +225,115 @@ fn main() {
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } | Event::Quit { .. } => {
                     break 'running
                 }
-                Event::KeyDown { scancode: Some(sc), repeat: false, .. } => {
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } => {
+                    let path = slot_path(&args, slot);
+                    match fs::write(&path, cpu.save_state()) {
+                        Ok(()) => eprintln!("Saved state to {}", path.display()),
+                        Err(e) => eprintln!("Cannot save state to {}: {}", path.display(), e),
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), repeat: false, .. }
+                    if movie.is_some() =>
+                {
+                    eprintln!("Cannot load a state while a movie is recorded or played");
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), repeat: false, .. } => {
+                    let path = slot_path(&args, slot);
+                    match fs::read(&path).map(|state| cpu.load_state(&state)) {
+                        Ok(Ok(())) => {
+                            eprintln!("Loaded state from {}", path.display());
+                            draw_sprites(&mut canvas, cpu.get_vram(), cpu.resolution());
+                        }
+                        Ok(Err(e)) => eprintln!("Cannot load {}: {}", path.display(), e),
+                        Err(e) => eprintln!("Cannot read {}: {}", path.display(), e),
+                    }
+                }
+                Event::KeyDown { keycode: Some(k @ (Keycode::F6 | Keycode::F7)), .. } => {
+                    slot = match k {
+                        Keycode::F6 => (slot + SAVE_SLOTS - 1) % SAVE_SLOTS,
+                        _ => (slot + 1) % SAVE_SLOTS,
+                    };
+                    eprintln!("Selected save slot {}", slot);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F12), keymod, repeat: false, .. }
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) =>
+                {
+                    match gif.take() {
+                        Some((path, writer)) => finish_gif(&path, writer),
+                        None => match start_gif(&args, &cpu) {
+                            Ok((path, writer)) => {
+                                eprintln!("Recording {}", path.display());
+                                gif = Some((path, writer));
+                            }
+                            Err(e) => eprintln!("Cannot record a GIF: {}", e),
+                        },
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, .. } => {
+                    let path = capture_path(&args, "png");
+                    let scale = SCALE * (HIRES_DISPLAY_WIDTH / cpu.resolution().0);
+                    let written = fs::File::create(&path).and_then(|f| {
+                        let w = io::BufWriter::new(f);
+                        write_png(w, cpu.resolution(), cpu.get_vram(), &PALETTE, scale)
+                    });
+                    match written {
+                        Ok(()) => eprintln!("Saved screenshot to {}", path.display()),
+                        Err(e) => eprintln!("Cannot write {}: {}", path.display(), e),
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::M), repeat: false, .. } => {
+                    muted = !muted;
+                    eprintln!("Sound {}", if muted { "muted" } else { "unmuted" });
+                }
+                Event::KeyDown { keycode: Some(Keycode::F8), repeat: false, .. } => {
+                    stopped = !stopped;
+                    if stopped {
+                        print_state(&cpu);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(k @ (Keycode::F10 | Keycode::F11)), keymod, ..
+                } if stopped => {
+                    let result = match k {
+                        Keycode::F10 => debugger.step(&mut cpu),
+                        _ if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                            debugger.step_out(&mut cpu, STEP_CYCLES)
+                        }
+                        _ => debugger.step_over(&mut cpu, STEP_CYCLES),
+                    };
+                    match result {
+                        Ok(stop) => {
+                            print_stop(stop.as_ref());
+                            print_state(&cpu);
+                            draw_sprites(&mut canvas, cpu.get_vram(), cpu.resolution());
+                        }
+                        Err(e) => {
+                            print_error(&e, &cpu, history.as_ref());
+                            break 'running;
+                        }
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } if movie.is_some() => {}
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => rewinding = true,
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => rewinding = false,
+                Event::KeyDown { scancode: Some(sc), repeat: false, .. } if !playing => {
                     if let Some(kc) = keymap(sc) {
+                        if let Some(movie) = &mut movie {
+                            movie.record(frame, &mut cpu, kc, true);
+                            continue;
+                        }
                         if let CpuState::Paused = cpu.state {
                             cpu.state = CpuState::Running;
                         }
                         cpu.set_key_state(kc, true);
                     }
                 }
-                Event::KeyUp { scancode: Some(sc), repeat: false, .. } => {
+                Event::KeyUp { scancode: Some(sc), repeat: false, .. } if !playing => {
                     if let Some(kc) = keymap(sc) {
+                        if let Some(movie) = &mut movie {
+                            movie.record(frame, &mut cpu, kc, false);
+                            continue;
+                        }
                         // cpu.state = CpuState::Running;
                         cpu.set_key_state(kc, false);
                     }
@@ -93,10 +345,57 @@ fn main() {
         /* The rest of the game loop goes here... */
 
         let start = Instant::now();
-        if let CpuState::Running = cpu.state {
-            if cpu.execute_cycle() {
-                draw_sprites(&mut canvas, cpu.get_vram());
+        if rewinding {
+            // Steps back one snapshot per frame while the key is held.
+            if rewinder.rewind(&mut cpu) {
+                draw_sprites(&mut canvas, cpu.get_vram(), cpu.resolution());
             }
+        } else if let CpuState::Halted = cpu.state {
+            break 'running;
+        } else if !stopped {
+            if let (true, Some(m)) = (playing, &movie) {
+                let played = m.play(frame, &mut cpu);
+                if let Err(e) = &played {
+                    eprintln!("{}", e);
+                }
+                if played.is_err() || frame + 1 >= m.frames() {
+                    // The keyboard takes over from there.
+                    eprintln!("Movie stopped at frame {}", frame);
+                    playing = false;
+                    movie = None;
+                }
+            }
+            frame += 1;
+            match debugger.run_frame(&mut cpu, CYCLES_PER_FRAME) {
+                Ok(None) => {}
+                Ok(Some(stop)) => {
+                    stopped = true;
+                    print_stop(Some(&stop));
+                    print_state(&cpu);
+                }
+                Err(e) => {
+                    print_error(&e, &cpu, history.as_ref());
+                    break 'running;
+                }
+            }
+            draw_sprites(&mut canvas, cpu.get_vram(), cpu.resolution());
+            rewinder.on_frame(&cpu);
+            if let Some((path, writer)) = &mut gif {
+                if !debugger.drawn() {
+                    writer.skip_frame();
+                } else if let Err(e) = writer.add_frame(cpu.resolution(), cpu.get_vram()) {
+                    eprintln!("Cannot write {}: {}", path.display(), e);
+                    gif = None;
+                }
+            }
+            if let Some((buzzer, samples)) = &mut recorder {
+                let start = samples.len();
+                samples.resize(start + (SAMPLE_RATE / FPS) as usize, 0.0);
+                buzzer.fill(cpu.is_sound_active(), &mut samples[start..]);
+            }
+        }
+        if let Some(device) = &mut audio_device {
+            device.lock().active = cpu.is_sound_active() && !muted && !rewinding;
         }
 
         let elapsed = start.elapsed();
@@ -104,19 +403,263 @@ fn main() {
             thread::sleep(dur);
         }
     }
+
+    if let Some((path, writer)) = gif {
+        finish_gif(&path, writer);
+    }
+    if let (Some(path), Some(mut tracer)) = (&args.trace_path, cpu.take_tracer()) {
+        if let Err(e) = tracer.finish() {
+            eprintln!("Cannot write {}: {}", path.to_string_lossy(), e);
+        }
+    }
+    if let (Some(path), Some(profiler)) = (&args.profile_path, &profiler) {
+        let profile = profiler.profile();
+        let report = match Path::new(path).extension().is_some_and(|ext| ext == "json") {
+            true => profile.to_json(),
+            false => profile.report(PROFILE_LINES),
+        };
+        if let Err(e) = fs::write(path, report) {
+            eprintln!("Cannot write {}: {}", path.to_string_lossy(), e);
+        }
+    }
+    if let (Some(path), Some(coverage)) = (&args.coverage_path, cpu.coverage()) {
+        let report = match Path::new(path).extension().is_some_and(|ext| ext == "info") {
+            true => {
+                let name = args.rom_path.as_deref().unwrap_or("IBM_Logo.ch8".as_ref());
+                coverage.lcov(&name.to_string_lossy(), &bin, ROM_START_ADDR)
+            }
+            false => coverage.listing(&bin, ROM_START_ADDR),
+        };
+        if let Err(e) = fs::write(path, report) {
+            eprintln!("Cannot write {}: {}", path.to_string_lossy(), e);
+        }
+    }
+    if let (Some(path), Some(movie)) = (&args.record_path, &movie) {
+        if let Err(e) = fs::write(path, movie.to_bytes()) {
+            eprintln!("Cannot write {}: {}", path.to_string_lossy(), e);
+        }
+    }
+    if let (Some(path), Some((_, samples))) = (&args.wav_path, &recorder) {
+        let written = fs::File::create(path)
+            .and_then(|f| write_wav(io::BufWriter::new(f), SAMPLE_RATE, samples));
+        if let Err(e) = written {
+            eprintln!("Cannot write {}: {}", path.to_string_lossy(), e);
+        }
+    }
+}
+
+fn print_error(e: &CpuError, cpu: &Cpu, history: Option<&TraceRing>) {
+    if let Some(history) = history {
+        eprintln!("Last instructions:");
+        for entry in history.entries() {
+            eprintln!("{:?}", entry);
+        }
+    }
+    eprintln!("error: {}", e);
+    print_state(cpu);
+}
+
+fn print_stop(stop: Option<&StopReason>) {
+    match stop {
+        Some(StopReason::Breakpoint { pc }) => eprintln!("Breakpoint at {:#05X}", pc),
+        Some(StopReason::Watchpoint { addr, write: true }) => {
+            eprintln!("Memory written at {:#05X}", addr)
+        }
+        Some(StopReason::Watchpoint { addr, write: false }) => {
+            eprintln!("Memory read at {:#05X}", addr)
+        }
+        Some(StopReason::CodeWritten { addr, len }) => {
+            eprintln!("Code written at {:#05X}, {} bytes", addr, len)
+        }
+        Some(StopReason::RegisterChanged { x, old, new }) => {
+            eprintln!("V{:X} changed from {:#04X} to {:#04X}", x, old, new)
+        }
+        Some(StopReason::Step) | None => {}
+    }
+}
+
+fn print_state(cpu: &Cpu) {
+    eprintln!(
+        "PC: {:#05X}  I: {:#05X}  stack: {:03X?}\n\
+        V0-VF: {:02X?}",
+        cpu.pc(),
+        cpu.i(),
+        cpu.stack(),
+        cpu.registers(),
+    );
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        rom_path: None,
+        platform: Platform::default(),
+        seed: None,
+        wav_path: None,
+        record_path: None,
+        play_path: None,
+        trace_path: None,
+        trace_format: None,
+        profile_path: None,
+        coverage_path: None,
+        breakpoints: Vec::new(),
+        watch_code: false,
+    };
+    let mut argv = env::args_os().skip(1);
+    while let Some(arg) = argv.next() {
+        if arg == "--platform" {
+            let name = argv.next().unwrap_or_else(|| usage());
+            args.platform = match name.to_str().map(str::parse) {
+                Some(Ok(p)) => p,
+                _ => usage(),
+            };
+        } else if arg == "--seed" {
+            let seed = argv.next().unwrap_or_else(|| usage());
+            args.seed = match seed.to_str().map(str::parse) {
+                Some(Ok(n)) => Some(n),
+                _ => usage(),
+            };
+        } else if arg == "--break" {
+            let addr = argv.next().unwrap_or_else(|| usage());
+            let addr = addr.to_str().map(|s| s.trim_start_matches("0x"));
+            match addr.map(|s| u16::from_str_radix(s, 16)) {
+                Some(Ok(pc)) => args.breakpoints.push(pc),
+                _ => usage(),
+            }
+        } else if arg == "--watch-code" {
+            args.watch_code = true;
+        } else if arg == "--wav" {
+            args.wav_path = Some(argv.next().unwrap_or_else(|| usage()));
+        } else if arg == "--record" {
+            args.record_path = Some(argv.next().unwrap_or_else(|| usage()));
+        } else if arg == "--play" {
+            args.play_path = Some(argv.next().unwrap_or_else(|| usage()));
+        } else if arg == "--trace" {
+            args.trace_path = Some(argv.next().unwrap_or_else(|| usage()));
+        } else if arg == "--profile" {
+            args.profile_path = Some(argv.next().unwrap_or_else(|| usage()));
+        } else if arg == "--coverage" {
+            args.coverage_path = Some(argv.next().unwrap_or_else(|| usage()));
+        } else if arg == "--trace-format" {
+            let format = argv.next().unwrap_or_else(|| usage());
+            let format = format.into_string().unwrap_or_else(|_| usage());
+            if format != "binary" && format.parse::<TraceFormat>().is_err() {
+                usage();
+            }
+            args.trace_format = Some(format);
+        } else if args.rom_path.is_none() {
+            args.rom_path = Some(arg);
+        } else {
+            usage();
+        }
+    }
+    // A movie is either recorded or played back.
+    if args.record_path.is_some() && args.play_path.is_some() {
+        usage();
+    }
+    args
+}
+
+// Savestates are kept next to the ROM, as `<ROM>.state<slot>`.
+fn slot_path(args: &Args, slot: u8) -> PathBuf {
+    let mut path = args.rom_path.clone().unwrap_or_else(|| "IBM_Logo.ch8".into());
+    path.push(format!(".state{}", slot));
+    path.into()
+}
+
+// Captures are kept next to the ROM too, as `<ROM>.<n>.<ext>`, numbered
+// from the first free name.
+fn capture_path(args: &Args, ext: &str) -> PathBuf {
+    let rom_path = args.rom_path.clone().unwrap_or_else(|| "IBM_Logo.ch8".into());
+    (0..)
+        .map(|n| {
+            let mut path = rom_path.clone();
+            path.push(format!(".{}.{}", n, ext));
+            PathBuf::from(path)
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+// Traces to FILE, or to stderr for `-`. The format is checked by `parse_args`.
+fn open_trace(path: &OsString, format: Option<&str>) -> io::Result<Box<dyn TraceSink>> {
+    let w: Box<dyn io::Write + Send> = match path.to_str() {
+        Some("-") => Box::new(io::stderr()),
+        _ => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+    };
+    Ok(match format {
+        Some("binary") => Box::new(BinaryTrace::new(w)),
+        Some(template) => Box::new(TextTrace::new(w, template.parse().unwrap())),
+        None => Box::new(TextTrace::new(w, TraceFormat::default())),
+    })
+}
+
+type Gif = GifWriter<io::BufWriter<fs::File>>;
+
+// Starts a GIF with the current screen.
+fn start_gif(args: &Args, cpu: &Cpu) -> io::Result<(PathBuf, Gif)> {
+    let path = capture_path(args, "gif");
+    let file = io::BufWriter::new(fs::File::create(&path)?);
+    let size = (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT);
+    let mut writer = GifWriter::new(file, size, &PALETTE, GIF_SCALE, FPS)?;
+    writer.add_frame(cpu.resolution(), cpu.get_vram())?;
+    Ok((path, writer))
+}
+
+fn finish_gif(path: &Path, writer: Gif) {
+    match writer.finish().and_then(|mut w| io::Write::flush(&mut w)) {
+        Ok(()) => eprintln!("Saved GIF to {}", path.display()),
+        Err(e) => eprintln!("Cannot write {}: {}", path.display(), e),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: interpreter [--platform cosmac|chip48|superchip|amiga|xochip] [--seed N]\n\
+        \x20                  [--wav FILE] [--record FILE | --play FILE]\n\
+        \x20                  [--trace FILE [--trace-format binary|TEMPLATE]]\n\
+        \x20                  [--profile FILE] [--coverage FILE]\n\
+        \x20                  [--break ADDR]... [--watch-code] [ROM]\n\
+        \n\
+        Opens the IBM logo ROM when no ROM is given. A ROM ending in .8o\n\
+        is compiled from Octo source first.\n\
+        --seed makes the random numbers the same on every run.\n\
+        --wav records the sound to FILE.\n\
+        --record saves the keys pressed to FILE, --play presses them again.\n\
+        A movie replays a session exactly, with its own platform and seed.\n\
+        Loading states and rewinding don't work while it is recorded or played.\n\
+        --trace logs every instruction to FILE, - for stderr, and shows the\n\
+        last ones on errors. --trace-format writes a binary trace, or lines\n\
+        where {{cycle}} {{pc}} {{opcode}} {{kind}} {{diff}} {{v}} and {{i}} are replaced.\n\
+        --profile writes on exit where the time went: the hottest addresses,\n\
+        instructions and subroutines, and the draws per frame. It is JSON for\n\
+        a FILE ending in .json.\n\
+        --coverage writes on exit which bytes of ROM were executed, read and\n\
+        written: an lcov tracefile for a FILE ending in .info, an annotated\n\
+        disassembly otherwise.\n\
+        --break stops at the hexadecimal address ADDR.\n\
+        --watch-code stops when the program writes over its own code.\n\
+        \n\
+        F5/F9 quick save/load the state, F6/F7 select the save slot.\n\
+        Hold Backspace to rewind, M toggles the sound.\n\
+        F12 saves a screenshot, Shift+F12 starts or stops recording a GIF.\n\
+        F8 stops or resumes, then F10 steps, F11 steps over and Shift+F11 steps out."
+    );
+    process::exit(2);
 }
 
-fn draw_sprites(canvas: &mut Canvas<Window>, vram: &[bool; DISPLAY_SIZE]) {
+fn draw_sprites(canvas: &mut Canvas<Window>, vram: &[u8], (width, _height): (u16, u16)) {
+    // Low resolution pixels are drawn twice as big to fill the window.
+    let scale = SCALE * (HIRES_DISPLAY_WIDTH / width);
     for (i, &pixel) in vram.iter().enumerate() {
-        let (x, y) = (i as u16 % DISPLAY_WIDTH, i as u16 / DISPLAY_WIDTH);
+        let (x, y) = (i as u16 % width, i as u16 / width);
         let rect = Rect::new(
-            i32::from(x * SCALE),
-            i32::from(y * SCALE),
-            u32::from(SCALE),
-            u32::from(SCALE),
+            i32::from(x * scale),
+            i32::from(y * scale),
+            u32::from(scale),
+            u32::from(scale),
         );
-        let color = if pixel { Color::GREEN } else { Color::BLACK };
-        canvas.set_draw_color(color);
+        let [r, g, b] = PALETTE[usize::from(pixel & 0b11)];
+        canvas.set_draw_color(Color::RGB(r, g, b));
         canvas.fill_rect(rect).unwrap();
     }
     canvas.present();
@@ -0,0 +1,404 @@
+//! A libretro core running [`Cpu`], for the frontends like RetroArch.
+//!
+//! The screen is sent as XRGB8888 in its current resolution, the beep as
+//! 16-bit stereo at 44.1 kHz, and the 16 keys come from the RetroPad of
+//! the first port, see [`BUTTONS`].
+
+mod ffi;
+
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::ptr;
+use std::slice;
+use std::sync::{Mutex, MutexGuard};
+
+use chip8emu::{
+    compile_octo, Buzzer, Cpu, CpuState, KeyCode, Platform, HIRES_DISPLAY_HEIGHT,
+    HIRES_DISPLAY_WIDTH, PALETTE,
+};
+
+pub use ffi::*;
+
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / 60;
+const DEFAULT_CYCLES_PER_FRAME: u32 = 9;
+// The random numbers are the same on every run, as frontends expect for
+// netplay and replays.
+const SEED: u64 = 0;
+
+/// The key of each RetroPad button: the D-pad on 2, 4, 6 and 8 as most
+/// games move with them, and the action buttons on the keys left.
+pub const BUTTONS: [(c_uint, KeyCode); 16] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, KeyCode::K2),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, KeyCode::K4),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, KeyCode::K6),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, KeyCode::K8),
+    (RETRO_DEVICE_ID_JOYPAD_B, KeyCode::K5),
+    (RETRO_DEVICE_ID_JOYPAD_A, KeyCode::K0),
+    (RETRO_DEVICE_ID_JOYPAD_Y, KeyCode::K1),
+    (RETRO_DEVICE_ID_JOYPAD_X, KeyCode::K3),
+    (RETRO_DEVICE_ID_JOYPAD_L, KeyCode::K7),
+    (RETRO_DEVICE_ID_JOYPAD_R, KeyCode::K9),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, KeyCode::KA),
+    (RETRO_DEVICE_ID_JOYPAD_START, KeyCode::KB),
+    (RETRO_DEVICE_ID_JOYPAD_L2, KeyCode::KC),
+    (RETRO_DEVICE_ID_JOYPAD_R2, KeyCode::KD),
+    (RETRO_DEVICE_ID_JOYPAD_L3, KeyCode::KE),
+    (RETRO_DEVICE_ID_JOYPAD_R3, KeyCode::KF),
+];
+
+const KEY_NAMES: [&CStr; 16] = [
+    c"Key 0", c"Key 1", c"Key 2", c"Key 3", c"Key 4", c"Key 5", c"Key 6", c"Key 7", c"Key 8",
+    c"Key 9", c"Key A", c"Key B", c"Key C", c"Key D", c"Key E", c"Key F",
+];
+
+const PLATFORM_VARIABLE: &CStr = c"chip8_platform";
+const SPEED_VARIABLE: &CStr = c"chip8_speed";
+
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+// Between `retro_load_game` and `retro_unload_game`.
+struct Game {
+    cpu: Cpu,
+    rom: Vec<u8>,
+    cycles: u32,
+    // The buttons down on the last frame, by key.
+    keys: [bool; 16],
+    buzzer: Buzzer,
+    samples: Vec<f32>,
+    audio: Vec<i16>,
+    video: Vec<u32>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+static GAME: Mutex<Option<Game>> = Mutex::new(None);
+
+fn callbacks() -> MutexGuard<'static, Callbacks> {
+    CALLBACKS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn game() -> MutexGuard<'static, Option<Game>> {
+    GAME.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// The frontend may call back into the core, so no lock is held meanwhile.
+fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    let environment = callbacks().environment;
+    match environment {
+        Some(environment) => unsafe { environment(cmd, data) },
+        None => false,
+    }
+}
+
+// The value of a core option, if the frontend knows it.
+fn variable(key: &CStr) -> Option<String> {
+    let mut var = Variable { key: key.as_ptr(), value: ptr::null() };
+    if !environment(RETRO_ENVIRONMENT_GET_VARIABLE, &mut var as *mut _ as *mut c_void)
+        || var.value.is_null()
+    {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr(var.value) }.to_string_lossy().into_owned())
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(cb: EnvironmentFn) {
+    callbacks().environment = Some(cb);
+    let variables = [
+        Variable {
+            key: PLATFORM_VARIABLE.as_ptr(),
+            value: c"Platform (restart); chip48|cosmac|superchip|amiga|xochip".as_ptr(),
+        },
+        Variable {
+            key: SPEED_VARIABLE.as_ptr(),
+            value: c"Instructions per frame; 9|15|20|30|50|100|200|500|1000".as_ptr(),
+        },
+        Variable { key: ptr::null(), value: ptr::null() },
+    ];
+    environment(RETRO_ENVIRONMENT_SET_VARIABLES, variables.as_ptr() as *mut c_void);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(cb: VideoRefreshFn) {
+    callbacks().video_refresh = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_cb: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(cb: AudioSampleBatchFn) {
+    callbacks().audio_sample_batch = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(cb: InputPollFn) {
+    callbacks().input_poll = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(cb: InputStateFn) {
+    callbacks().input_state = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *game() = None;
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: c"chip8emu".as_ptr(),
+        library_version: c"0.1.0".as_ptr(),
+        valid_extensions: c"ch8|c8|sc8|xo8|8o".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: 64,
+            base_height: 32,
+            max_width: c_uint::from(HIRES_DISPLAY_WIDTH),
+            max_height: c_uint::from(HIRES_DISPLAY_HEIGHT),
+            aspect_ratio: 2.0,
+        },
+        timing: SystemTiming { fps: FPS, sample_rate: f64::from(SAMPLE_RATE) },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(game) = &mut *game() {
+        game.cpu = Cpu::with_seed(game.cpu.quirks(), SEED);
+        // The ROM loaded the first time.
+        game.cpu.load_game(&game.rom).unwrap();
+        game.keys = [false; 16];
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let (video_refresh, audio_sample_batch, input_poll, input_state) = {
+        let cb = callbacks();
+        (cb.video_refresh, cb.audio_sample_batch, cb.input_poll, cb.input_state)
+    };
+    let speed = variable(SPEED_VARIABLE).and_then(|s| s.parse().ok());
+    let mut game = game();
+    let game = match &mut *game {
+        Some(game) => game,
+        None => return,
+    };
+
+    if let Some(speed) = speed {
+        game.cycles = speed;
+    }
+    if let (Some(input_poll), Some(input_state)) = (input_poll, input_state) {
+        unsafe { input_poll() };
+        for (id, key) in BUTTONS {
+            let pressed = unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, id) } != 0;
+            if pressed == game.keys[key as usize] {
+                continue;
+            }
+            if let (true, CpuState::Paused) = (pressed, &game.cpu.state) {
+                game.cpu.state = CpuState::Running;
+            }
+            game.cpu.set_key_state(key, pressed);
+            game.keys[key as usize] = pressed;
+        }
+    }
+    // A program that crashes stops, the frontend keeps showing its screen.
+    let _ = game.cpu.run_frame(game.cycles);
+
+    let (width, height) = game.cpu.resolution();
+    game.video.clear();
+    game.video.extend(game.cpu.get_vram().iter().map(|&pixel| {
+        let [r, g, b] = PALETTE[usize::from(pixel & 0b11)];
+        u32::from_be_bytes([0, r, g, b])
+    }));
+    if let Some(video_refresh) = video_refresh {
+        let (width, height) = (c_uint::from(width), c_uint::from(height));
+        let pitch = width as usize * 4;
+        unsafe { video_refresh(game.video.as_ptr() as *const c_void, width, height, pitch) };
+    }
+
+    game.samples.resize(SAMPLES_PER_FRAME, 0.0);
+    game.buzzer.fill(game.cpu.is_sound_active(), &mut game.samples);
+    game.audio.clear();
+    for &sample in &game.samples {
+        let sample = (sample * f32::from(i16::MAX)) as i16;
+        game.audio.extend_from_slice(&[sample, sample]);
+    }
+    if let Some(audio_sample_batch) = audio_sample_batch {
+        unsafe { audio_sample_batch(game.audio.as_ptr(), SAMPLES_PER_FRAME) };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    game().as_ref().map_or(0, |game| game.cpu.save_state().len())
+}
+
+/// # Safety
+///
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let state = match &*game() {
+        Some(game) => game.cpu.save_state(),
+        None => return false,
+    };
+    if size < state.len() {
+        return false;
+    }
+    ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+    true
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let state = slice::from_raw_parts(data as *const u8, size);
+    match &mut *game() {
+        Some(game) => game.cpu.load_state(state).is_ok(),
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+///
+/// `game_info` must point to a `retro_game_info` whose data is the ROM.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game_info: *const GameInfo) -> bool {
+    if game_info.is_null() || (*game_info).data.is_null() {
+        return false;
+    }
+    let info = &*game_info;
+    let data = slice::from_raw_parts(info.data as *const u8, info.size);
+    let is_octo = has_extension(info, b".8o");
+    let rom = match is_octo {
+        true => match std::str::from_utf8(data).ok().map(compile_octo) {
+            Some(Ok(rom)) => rom,
+            _ => return false,
+        },
+        false => data.to_vec(),
+    };
+
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut _ as *mut c_void) {
+        return false;
+    }
+    let descriptors = BUTTONS
+        .iter()
+        .map(|&(id, key)| InputDescriptor {
+            port: 0,
+            device: RETRO_DEVICE_JOYPAD,
+            index: 0,
+            id,
+            description: KEY_NAMES[key as usize].as_ptr(),
+        })
+        .chain([InputDescriptor { port: 0, device: 0, index: 0, id: 0, description: ptr::null() }])
+        .collect::<Vec<_>>();
+    environment(RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS, descriptors.as_ptr() as *mut c_void);
+
+    let platform = variable(PLATFORM_VARIABLE).and_then(|s| s.parse().ok());
+    let platform = platform.unwrap_or(match is_octo || has_extension(info, b".xo8") {
+        true => Platform::XoChip,
+        false => Platform::default(),
+    });
+    let mut cpu = Cpu::with_seed(platform.quirks(), SEED);
+    if cpu.load_game(&rom).is_err() {
+        return false;
+    }
+    *game() = Some(Game {
+        cpu,
+        rom,
+        cycles: DEFAULT_CYCLES_PER_FRAME,
+        keys: [false; 16],
+        buzzer: Buzzer::new(SAMPLE_RATE),
+        samples: Vec::with_capacity(SAMPLES_PER_FRAME),
+        audio: Vec::with_capacity(SAMPLES_PER_FRAME * 2),
+        video: Vec::new(),
+    });
+    true
+}
+
+unsafe fn has_extension(info: &GameInfo, ext: &[u8]) -> bool {
+    !info.path.is_null() && CStr::from_ptr(info.path).to_bytes().ends_with(ext)
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *game() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match (&mut *game(), id) {
+        (Some(game), RETRO_MEMORY_SYSTEM_RAM) => game.cpu.memory_mut().as_mut_ptr() as *mut c_void,
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match (&*game(), id) {
+        (Some(game), RETRO_MEMORY_SYSTEM_RAM) => game.cpu.memory().len(),
+        _ => 0,
+    }
+}
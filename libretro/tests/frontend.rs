@@ -0,0 +1,178 @@
+//! A minimal libretro frontend driving the core like RetroArch would.
+
+use std::ffi::{c_uint, c_void, CStr};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use chip8emu::{assemble, KeyCode};
+use chip8emu_libretro::*;
+
+// Buttons held, bit N being the RetroPad button N.
+static BUTTONS_DOWN: AtomicU16 = AtomicU16::new(0);
+static AUDIO_FRAMES: AtomicUsize = AtomicUsize::new(0);
+static PIXEL_FORMAT: Mutex<Option<c_uint>> = Mutex::new(None);
+static SPEED: Mutex<Option<&CStr>> = Mutex::new(None);
+static SCREEN: Mutex<(c_uint, c_uint, Vec<u32>)> = Mutex::new((0, 0, Vec::new()));
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+            *PIXEL_FORMAT.lock().unwrap() = Some(*(data as *const c_uint));
+            true
+        }
+        RETRO_ENVIRONMENT_GET_VARIABLE => {
+            let var = &mut *(data as *mut Variable);
+            match (CStr::from_ptr(var.key).to_bytes(), *SPEED.lock().unwrap()) {
+                (b"chip8_speed", Some(speed)) => {
+                    var.value = speed.as_ptr();
+                    true
+                }
+                _ => false,
+            }
+        }
+        RETRO_ENVIRONMENT_SET_VARIABLES | RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS => true,
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn video_refresh(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+) {
+    assert_eq!(pitch, width as usize * 4);
+    let pixels = slice::from_raw_parts(data as *const u32, (width * height) as usize);
+    *SCREEN.lock().unwrap() = (width, height, pixels.to_vec());
+}
+
+unsafe extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+unsafe extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+    AUDIO_FRAMES.fetch_add(frames, Ordering::Relaxed);
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+unsafe extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    let down = port == 0 && device == RETRO_DEVICE_JOYPAD;
+    i16::from(down && BUTTONS_DOWN.load(Ordering::Relaxed) & (1 << id) != 0)
+}
+
+fn load(rom: &[u8], path: &CStr) -> bool {
+    let info = GameInfo {
+        path: path.as_ptr(),
+        data: rom.as_ptr() as *const c_void,
+        size: rom.len(),
+        meta: ptr::null(),
+    };
+    unsafe { retro_load_game(&info) }
+}
+
+fn run_frames(n: usize) {
+    for _ in 0..n {
+        retro_run();
+    }
+}
+
+fn lit_pixels() -> usize {
+    SCREEN.lock().unwrap().2.iter().filter(|&&p| p != 0).count()
+}
+
+fn button(key: KeyCode) -> c_uint {
+    BUTTONS.iter().find(|(_, k)| *k == key).unwrap().0
+}
+
+// The core keeps its state in globals, so everything runs in one test.
+#[test]
+fn test_frontend() {
+    assert_eq!(retro_api_version(), RETRO_API_VERSION);
+    retro_set_environment(environment);
+    retro_set_video_refresh(video_refresh);
+    retro_set_audio_sample(audio_sample);
+    retro_set_audio_sample_batch(audio_sample_batch);
+    retro_set_input_poll(input_poll);
+    retro_set_input_state(input_state);
+    retro_init();
+
+    let mut info = SystemInfo {
+        library_name: ptr::null(),
+        library_version: ptr::null(),
+        valid_extensions: ptr::null(),
+        need_fullpath: true,
+        block_extract: true,
+    };
+    unsafe { retro_get_system_info(&mut info) };
+    assert!(!info.need_fullpath);
+    let extensions = unsafe { CStr::from_ptr(info.valid_extensions) };
+    assert!(extensions.to_str().unwrap().split('|').any(|ext| ext == "ch8"));
+
+    // Every key has its own button.
+    let mut ids = BUTTONS.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    let mut keys = BUTTONS.iter().map(|(_, key)| *key as u8).collect::<Vec<_>>();
+    ids.sort();
+    keys.sort();
+    assert_eq!(ids, (0..16).collect::<Vec<_>>());
+    assert_eq!(keys, (0..16).collect::<Vec<_>>());
+
+    // The IBM logo in XRGB8888.
+    assert!(load(include_bytes!("../../IBM_Logo.ch8"), c"IBM_Logo.ch8"));
+    assert_eq!(*PIXEL_FORMAT.lock().unwrap(), Some(RETRO_PIXEL_FORMAT_XRGB8888));
+    run_frames(60);
+    assert_eq!(AUDIO_FRAMES.load(Ordering::Relaxed), 60 * 735);
+    {
+        let (width, height, pixels) = &*SCREEN.lock().unwrap();
+        assert_eq!((*width, *height), (64, 32));
+        assert!(pixels.iter().all(|&p| p == 0 || p == 0x00_00_FF_00));
+    }
+    assert!(lit_pixels() > 200);
+    assert_eq!(retro_get_memory_size(RETRO_MEMORY_SYSTEM_RAM), 4096);
+    retro_unload_game();
+
+    // Draws the digit of the key pressed, then waits for the next one.
+    let rom = assemble(
+        "
+        start:
+            CLS
+            LD V0, K
+            LD F, V0
+            DRW V1, V1, 5
+        held:
+            SKNP V0
+            JP held
+            JP start
+        ",
+    )
+    .unwrap();
+    assert!(load(&rom, c"keys.ch8"));
+    run_frames(5);
+    assert_eq!(lit_pixels(), 0);
+    BUTTONS_DOWN.store(1 << button(KeyCode::K8), Ordering::Relaxed);
+    run_frames(2);
+    // 8 is the only digit with 16 pixels.
+    assert_eq!(lit_pixels(), 16);
+
+    let mut state = vec![0u8; retro_serialize_size()];
+    assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+    BUTTONS_DOWN.store(0, Ordering::Relaxed);
+    run_frames(2);
+    BUTTONS_DOWN.store(1 << button(KeyCode::K1), Ordering::Relaxed);
+    *SPEED.lock().unwrap() = Some(c"1000");
+    run_frames(2);
+    assert_eq!(lit_pixels(), 8);
+    assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) });
+    assert!(!unsafe { retro_unserialize(state.as_ptr() as *const c_void, 10) });
+    BUTTONS_DOWN.store(1 << button(KeyCode::K8), Ordering::Relaxed);
+    run_frames(1);
+    assert_eq!(lit_pixels(), 16);
+
+    retro_reset();
+    BUTTONS_DOWN.store(0, Ordering::Relaxed);
+    run_frames(1);
+    assert_eq!(lit_pixels(), 0);
+    retro_unload_game();
+    retro_deinit();
+}
@@ -18,5 +18,5 @@ macro_rules! gen_boxed_array {
     };
 }
 
-gen_boxed_array!(boxed_zeroed_memory, u8, crate::memory::RAM_SIZE as usize);
-gen_boxed_array!(boxed_zeroed_display, bool, crate::display::DISPLAY_SIZE);
+gen_boxed_array!(boxed_zeroed_memory, u8, crate::memory::EXTENDED_RAM_SIZE);
+gen_boxed_array!(boxed_zeroed_display, u8, crate::display::DISPLAY_SIZE);
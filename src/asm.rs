@@ -0,0 +1,559 @@
+//! Assembler for the mnemonics that the `Debug` impl of `OpcodeKind` prints.
+//!
+//! ```text
+//! ; Comments start with a semicolon.
+//! SPEED = 3               ; constant
+//! start:                  ; label
+//!     LD I, sprite
+//!     LD V0, SPEED + 1
+//!     DRW V0, V1, 0x2
+//!     JMP start
+//! sprite:
+//!     db 0b11110000, 0x90
+//!     dw 0x1234
+//! include "other.s"       ; only with `assemble_file`
+//! ```
+//!
+//! Mnemonics and register names are case insensitive, `JP` is the same as
+//! `JMP`, and `SHR`/`SHL` take one or two registers. Numbers are decimal,
+//! `0x` hexadecimal or `0b` binary, and can be added or subtracted.
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::opcode::{Opcode, OpcodeKind};
+
+const ROM_START_ADDR: u16 = 0x200;
+// Symbols are constants that may refer to other constants, but not forever.
+const MAX_SYMBOL_DEPTH: u8 = 32;
+const MAX_INCLUDE_DEPTH: u8 = 16;
+
+/// Where an error was found and why. Lines and columns start at 1.
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: AsmErrorKind,
+}
+
+pub enum AsmErrorKind {
+    Syntax(&'static str),
+    UnknownMnemonic(String),
+    InvalidOperands(String),
+    InvalidNumber(String),
+    UndefinedSymbol(String),
+    DuplicateSymbol(String),
+    RecursiveSymbol(String),
+    OutOfRange { value: i64, min: i64, max: i64 },
+    ProgramTooBig,
+    Include { path: String, error: io::Error },
+}
+
+/// Assembles `source` into a ROM loaded at 0x200. `include` isn't allowed.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    read_lines("<input>", source, None, 0, &mut lines)?;
+    Assembler::default().run(&lines)
+}
+
+/// Assembles the file at `path`, whose `include`s are relative to the
+/// including file.
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+    let name = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|error| AsmError {
+        file: name.clone(),
+        line: 0,
+        column: 0,
+        kind: AsmErrorKind::Include { path: name.clone(), error },
+    })?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut lines = Vec::new();
+    read_lines(&name, &source, Some(dir), 0, &mut lines)?;
+    Assembler::default().run(&lines)
+}
+
+struct Line {
+    file: String,
+    number: usize,
+    text: String,
+}
+
+// Position of an error, to make an `AsmError` from.
+#[derive(Clone, Copy)]
+struct Loc<'a> {
+    line: &'a Line,
+    column: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    loc: Loc<'a>,
+}
+
+enum Symbol<'a> {
+    Label(u16),
+    Const(Token<'a>),
+}
+
+struct Statement<'a> {
+    addr: u16,
+    mnemonic: Token<'a>,
+    operands: Vec<Token<'a>>,
+}
+
+enum Operand<'a> {
+    V(u8),
+    Range(u8, u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Audio,
+    Pitch,
+    Long(Token<'a>),
+    Value(Token<'a>),
+}
+
+#[derive(Default)]
+struct Assembler<'a> {
+    symbols: HashMap<String, Symbol<'a>>,
+}
+
+// Reads the lines of `source`, with the included files in place.
+fn read_lines(
+    file: &str,
+    source: &str,
+    dir: Option<&Path>,
+    depth: u8,
+    out: &mut Vec<Line>,
+) -> Result<(), AsmError> {
+    for (i, text) in source.lines().enumerate() {
+        let line = Line { file: file.to_string(), number: i + 1, text: text.to_string() };
+        let code = strip_comment(text).trim_start();
+        let is_include = code.get(..7).is_some_and(|s| s.eq_ignore_ascii_case("include"))
+            && code[7..].starts_with(char::is_whitespace);
+        if !is_include {
+            out.push(line);
+            continue;
+        }
+        let column = text.len() - text.trim_start().len() + 1;
+        let error = |kind| AsmError { file: file.to_string(), line: i + 1, column, kind };
+        let path = code[7..].trim();
+        let path = match path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+            Some(path) => path,
+            None => return Err(error(AsmErrorKind::Syntax("expected a quoted path"))),
+        };
+        let dir = match dir {
+            Some(dir) if depth < MAX_INCLUDE_DEPTH => dir,
+            Some(_) => return Err(error(AsmErrorKind::Syntax("too many nested includes"))),
+            None => return Err(error(AsmErrorKind::Syntax("include needs a file"))),
+        };
+        let full = dir.join(path);
+        let source = fs::read_to_string(&full).map_err(|e| {
+            error(AsmErrorKind::Include { path: full.display().to_string(), error: e })
+        })?;
+        let parent = full.parent().map(Path::to_path_buf).unwrap_or_else(PathBuf::new);
+        read_lines(&full.display().to_string(), &source, Some(&parent), depth + 1, out)?;
+    }
+    Ok(())
+}
+
+fn strip_comment(text: &str) -> &str {
+    text.split(';').next().unwrap_or("")
+}
+
+impl<'a> Assembler<'a> {
+    fn run(mut self, lines: &'a [Line]) -> Result<Vec<u8>, AsmError> {
+        // First pass: the address of every label.
+        let mut statements = Vec::new();
+        let mut addr = u32::from(ROM_START_ADDR);
+        for line in lines {
+            if let Some(statement) = self.parse_line(line, addr as u16)? {
+                addr += u32::from(statement_size(&statement));
+                if addr > 0x10000 {
+                    return Err(statement.mnemonic.loc.error(AsmErrorKind::ProgramTooBig));
+                }
+                statements.push(statement);
+            }
+        }
+
+        // Second pass: the bytes.
+        let mut out = Vec::new();
+        for statement in &statements {
+            self.encode(statement, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    // Defines the labels and constants of the line, and returns what is
+    // left to assemble.
+    fn parse_line(&mut self, line: &'a Line, addr: u16) -> Result<Option<Statement<'a>>, AsmError> {
+        let code = strip_comment(&line.text);
+        let mut rest = Token { text: code, loc: Loc { line, column: 1 } }.trim();
+        while let Some(colon) = rest.text.find(':') {
+            let (name, after) = rest.split_at(colon);
+            let name = name.trim();
+            if !is_identifier(name.text) {
+                break;
+            }
+            self.define(name, Symbol::Label(addr))?;
+            rest = after.skip(1).trim();
+        }
+        if rest.text.is_empty() {
+            return Ok(None);
+        }
+
+        let end = rest.text.find(char::is_whitespace).unwrap_or(rest.text.len());
+        let (mnemonic, operands) = rest.split_at(end);
+        let operands = operands.trim();
+        if let Some(value) = operands.text.strip_prefix('=') {
+            let value = operands.skip(operands.text.len() - value.len()).trim();
+            if !is_identifier(mnemonic.text) {
+                return Err(mnemonic.loc.error(AsmErrorKind::Syntax("invalid constant name")));
+            }
+            self.define(mnemonic, Symbol::Const(value))?;
+            return Ok(None);
+        }
+        // `SHR V0 {, V1}` is how the optional register is printed.
+        let mut list = Vec::new();
+        if !operands.text.is_empty() {
+            let mut start = 0;
+            for part in operands.text.split(',') {
+                let token = operands.skip(start).take(part.len()).trim();
+                let token = token.strip_braces();
+                if token.text.is_empty() {
+                    return Err(token.loc.error(AsmErrorKind::Syntax("missing operand")));
+                }
+                list.push(token);
+                start += part.len() + 1;
+            }
+        }
+        Ok(Some(Statement { addr, mnemonic, operands: list }))
+    }
+
+    fn define(&mut self, name: Token<'a>, symbol: Symbol<'a>) -> Result<(), AsmError> {
+        if parse_operand(name).is_some_and(|op| !matches!(op, Operand::Value(_))) {
+            return Err(name.loc.error(AsmErrorKind::Syntax("reserved name")));
+        }
+        let key = name.text.to_string();
+        if self.symbols.contains_key(&key) {
+            return Err(name.loc.error(AsmErrorKind::DuplicateSymbol(key)));
+        }
+        self.symbols.insert(key, symbol);
+        Ok(())
+    }
+
+    fn encode(&self, statement: &Statement<'a>, out: &mut Vec<u8>) -> Result<(), AsmError> {
+        use OpcodeKind::*;
+        use Operand::*;
+
+        let mnemonic = statement.mnemonic.text.to_ascii_uppercase();
+        match mnemonic.as_str() {
+            "DB" => {
+                for &token in &statement.operands {
+                    out.push(self.value(token, -128, 0xFF)? as u8);
+                }
+                return Ok(());
+            }
+            "DW" => {
+                for &token in &statement.operands {
+                    out.extend_from_slice(
+                        &(self.value(token, -0x8000, 0xFFFF)? as u16).to_be_bytes(),
+                    );
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let operands = statement
+            .operands
+            .iter()
+            .map(|&token| {
+                parse_operand(token)
+                    .ok_or_else(|| token.loc.error(AsmErrorKind::Syntax("invalid operand")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let addr = |token| self.value(token, 0, 0xFFF).map(|v| v as u16);
+        let byte = |token| self.value(token, -128, 0xFF).map(|v| v as u8);
+        let nibble = |token| self.value(token, 0, 0xF).map(|v| v as u8);
+
+        let kind = match (mnemonic.as_str(), operands.as_slice()) {
+            ("CLS", []) => Cls,
+            ("RET", []) => Ret,
+            ("JMP" | "JP", [Value(a)]) => JpAddr { addr: addr(*a)? },
+            ("JMP" | "JP", [V(x), Value(a)]) => {
+                let addr = addr(*a)?;
+                // The register is part of the address, V0 on the COSMAC VIP.
+                if *x != 0 && u16::from(*x) != addr >> 8 {
+                    return Err(a.loc.error(AsmErrorKind::InvalidOperands(mnemonic)));
+                }
+                JpVxAddr { x: (addr >> 8) as u8, addr }
+            }
+            ("CALL", [Value(a)]) => Call { addr: addr(*a)? },
+            ("SE" | "SNE", [V(x), V(y)]) => SkipVxVy { eq: mnemonic == "SE", x: *x, y: *y },
+            ("SE" | "SNE", [V(x), Value(b)]) => {
+                SkipVxByte { eq: mnemonic == "SE", x: *x, byte: byte(*b)? }
+            }
+            ("LD", [V(x), V(y)]) => LoadVxVy { x: *x, y: *y },
+            ("LD", [V(x), Value(b)]) => LoadVxByte { x: *x, byte: byte(*b)? },
+            ("LD", [V(x), Dt]) => LoadDT { x: *x },
+            ("LD", [Dt, V(x)]) => StoreDT { x: *x },
+            ("LD", [St, V(x)]) => StoreST { x: *x },
+            ("LD", [V(x), K]) => LoadK { x: *x },
+            ("LD", [I, Value(a)]) => LoadI { addr: addr(*a)? },
+            ("LD", [I, Long(a)]) => LoadILong { addr: self.value(*a, 0, 0xFFFF)? as u16 },
+            ("LD", [B, V(x)]) => LoadBcd { x: *x },
+            ("LD", [IndirectI, V(x)]) => PushRegs { x: *x },
+            ("LD", [V(x), IndirectI]) => PopRegs { x: *x },
+            ("LD", [F, V(x)]) => LoadFont { x: *x },
+            ("LD", [Hf, V(x)]) => LoadBigFont { x: *x },
+            ("LD", [R, V(x)]) => StoreFlags { x: *x },
+            ("LD", [V(x), R]) => LoadFlags { x: *x },
+            ("LD", [IndirectI, Range(x, y)]) => SaveRange { x: *x, y: *y },
+            ("LD", [Range(x, y), IndirectI]) => LoadRange { x: *x, y: *y },
+            ("LD", [Audio, IndirectI]) => LoadAudio,
+            ("LD", [Pitch, V(x)]) => LoadPitch { x: *x },
+            ("ADD", [V(x), V(y)]) => Add { x: *x, y: *y },
+            ("ADD", [V(x), Value(b)]) => AddVxByte { x: *x, byte: byte(*b)? },
+            ("ADD", [I, V(x)]) => AddIVx { x: *x },
+            ("OR", [V(x), V(y)]) => Or { x: *x, y: *y },
+            ("AND", [V(x), V(y)]) => And { x: *x, y: *y },
+            ("XOR", [V(x), V(y)]) => Xor { x: *x, y: *y },
+            ("SUB", [V(x), V(y)]) => Subtract { x_y: true, x: *x, y: *y },
+            ("SUBN", [V(x), V(y)]) => Subtract { x_y: false, x: *x, y: *y },
+            ("SHR", [V(x)]) => ShiftRight { x: *x, y: *x },
+            ("SHR", [V(x), V(y)]) => ShiftRight { x: *x, y: *y },
+            ("SHL", [V(x)]) => ShiftLeft { x: *x, y: *x },
+            ("SHL", [V(x), V(y)]) => ShiftLeft { x: *x, y: *y },
+            ("RND", [V(x), Value(b)]) => Random { x: *x, byte: byte(*b)? },
+            ("SKP", [V(x)]) => SkipIfKey { eq: true, x: *x },
+            ("SKNP", [V(x)]) => SkipIfKey { eq: false, x: *x },
+            ("DRW", [V(x), V(y), Value(n)]) => Draw { x: *x, y: *y, n: nibble(*n)? },
+            ("SCD", [Value(n)]) => ScrollDown { n: nibble(*n)? },
+            ("SCU", [Value(n)]) => ScrollUp { n: nibble(*n)? },
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => LowRes,
+            ("HIGH", []) => HighRes,
+            ("PLANE", [Value(n)]) => Plane { n: nibble(*n)? },
+            (
+                "CLS" | "RET" | "JMP" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR" | "AND"
+                | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "SKP" | "SKNP" | "DRW" | "SCD"
+                | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "PLANE",
+                _,
+            ) => {
+                let loc = statement.operands.first().map_or(statement.mnemonic.loc, |t| t.loc);
+                return Err(loc.error(AsmErrorKind::InvalidOperands(mnemonic)));
+            }
+            _ => {
+                let name = statement.mnemonic.text.to_string();
+                return Err(statement.mnemonic.loc.error(AsmErrorKind::UnknownMnemonic(name)));
+            }
+        };
+        let opcode = Opcode::encode(kind);
+        out.extend_from_slice(&opcode.as_u16().to_be_bytes());
+        if opcode.size() == 4 {
+            out.extend_from_slice(&opcode.operand().to_be_bytes());
+        }
+        debug_assert_eq!(
+            out.len(),
+            usize::from(statement.addr - ROM_START_ADDR) + usize::from(opcode.size())
+        );
+        Ok(())
+    }
+
+    // Evaluates the expression in `token`, which must be in `min..=max`.
+    fn value(&self, token: Token<'a>, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.eval(token, 0)?;
+        if value < min || value > max {
+            return Err(token.loc.error(AsmErrorKind::OutOfRange { value, min, max }));
+        }
+        Ok(value)
+    }
+
+    // Sums the `+` and `-` separated terms of `token`.
+    fn eval(&self, token: Token<'a>, depth: u8) -> Result<i64, AsmError> {
+        let mut total = 0i64;
+        let mut sign = 1;
+        let mut rest = token.trim();
+        loop {
+            if let Some(stripped) = rest.text.strip_prefix('-') {
+                sign = -sign;
+                rest = rest.skip(rest.text.len() - stripped.len()).trim();
+                continue;
+            }
+            let end = rest.text.find(['+', '-']).unwrap_or(rest.text.len());
+            let (term, after) = rest.split_at(end);
+            let term = term.trim();
+            total = self
+                .term(term, depth)?
+                .checked_mul(sign)
+                .and_then(|value| total.checked_add(value))
+                .ok_or_else(|| term.loc.error(AsmErrorKind::Syntax("expression overflows")))?;
+            match after.text.chars().next() {
+                Some('+') => sign = 1,
+                Some('-') => sign = -1,
+                _ => return Ok(total),
+            }
+            rest = after.skip(1).trim();
+        }
+    }
+
+    fn term(&self, token: Token<'a>, depth: u8) -> Result<i64, AsmError> {
+        let text = token.text;
+        if text.is_empty() {
+            return Err(token.loc.error(AsmErrorKind::Syntax("expected a value")));
+        }
+        if text.starts_with(|c: char| c.is_ascii_digit()) {
+            let parsed = match text.get(..2) {
+                Some("0x" | "0X") => i64::from_str_radix(&text[2..], 16),
+                Some("0b" | "0B") => i64::from_str_radix(&text[2..], 2),
+                _ => text.parse(),
+            };
+            return parsed
+                .map_err(|_| token.loc.error(AsmErrorKind::InvalidNumber(text.to_string())));
+        }
+        match self.symbols.get(text) {
+            Some(Symbol::Label(addr)) => Ok(i64::from(*addr)),
+            Some(Symbol::Const(_)) if depth >= MAX_SYMBOL_DEPTH => {
+                Err(token.loc.error(AsmErrorKind::RecursiveSymbol(text.to_string())))
+            }
+            Some(Symbol::Const(value)) => self.eval(*value, depth + 1),
+            None => Err(token.loc.error(AsmErrorKind::UndefinedSymbol(text.to_string()))),
+        }
+    }
+}
+
+fn statement_size(statement: &Statement<'_>) -> u16 {
+    let count = statement.operands.len() as u16;
+    match statement.mnemonic.text.to_ascii_uppercase().as_str() {
+        "DB" => count,
+        "DW" => 2 * count,
+        _ => match statement.operands.get(1).copied().and_then(parse_operand) {
+            Some(Operand::Long(_)) => 4,
+            _ => 2,
+        },
+    }
+}
+
+fn parse_operand(token: Token<'_>) -> Option<Operand<'_>> {
+    let upper = token.text.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::Hf,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        "AUDIO" => Operand::Audio,
+        "PITCH" => Operand::Pitch,
+        _ => {
+            if let Some(x) = register(&upper) {
+                Operand::V(x)
+            } else if let Some((x, y)) = upper
+                .split_once('-')
+                .and_then(|(x, y)| Some((register(x.trim())?, register(y.trim())?)))
+            {
+                Operand::Range(x, y)
+            } else if upper.starts_with("LONG") && upper[4..].starts_with(char::is_whitespace) {
+                Operand::Long(token.skip(4).trim())
+            } else if upper.starts_with(|c: char| c.is_ascii_alphanumeric() || "_.-".contains(c)) {
+                Operand::Value(token)
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(operand)
+}
+
+fn register(s: &str) -> Option<u8> {
+    match s.as_bytes() {
+        [b'V', x] => (*x as char).to_digit(16).map(|x| x as u8),
+        _ => None,
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+impl<'a> Token<'a> {
+    fn trim(self) -> Self {
+        let start = self.text.len() - self.text.trim_start().len();
+        Token { text: self.text.trim(), loc: self.loc.advance(start) }
+    }
+
+    fn skip(self, n: usize) -> Self {
+        Token { text: &self.text[n..], loc: self.loc.advance(n) }
+    }
+
+    fn take(self, n: usize) -> Self {
+        Token { text: &self.text[..n], loc: self.loc }
+    }
+
+    fn split_at(self, n: usize) -> (Self, Self) {
+        (self.take(n), self.skip(n))
+    }
+
+    fn strip_braces(self) -> Self {
+        let is_brace = |c: char| c == '{' || c == '}' || c.is_whitespace();
+        let start = self.text.len() - self.text.trim_start_matches(is_brace).len();
+        let token = self.skip(start);
+        token.take(token.text.trim_end_matches(is_brace).len())
+    }
+}
+
+impl<'a> Loc<'a> {
+    fn advance(self, n: usize) -> Self {
+        Loc { line: self.line, column: self.column + n }
+    }
+
+    fn error(self, kind: AsmErrorKind) -> AsmError {
+        AsmError { file: self.line.file.clone(), line: self.line.number, column: self.column, kind }
+    }
+}
+
+impl Error for AsmError {}
+impl fmt::Debug for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: ", self.file, self.line, self.column)?;
+        match &self.kind {
+            AsmErrorKind::Syntax(what) => f.write_str(what),
+            AsmErrorKind::UnknownMnemonic(name) => write!(f, "unknown mnemonic {}", name),
+            AsmErrorKind::InvalidOperands(name) => write!(f, "invalid operands for {}", name),
+            AsmErrorKind::InvalidNumber(s) => write!(f, "invalid number {}", s),
+            AsmErrorKind::UndefinedSymbol(name) => write!(f, "undefined symbol {}", name),
+            AsmErrorKind::DuplicateSymbol(name) => write!(f, "{} is already defined", name),
+            AsmErrorKind::RecursiveSymbol(name) => write!(f, "{} is defined in a loop", name),
+            AsmErrorKind::OutOfRange { value, min, max } => {
+                write!(f, "{} is out of range {}..={}", value, min, max)
+            }
+            AsmErrorKind::ProgramTooBig => f.write_str("program is bigger than the memory"),
+            AsmErrorKind::Include { path, error } => write!(f, "cannot read {}: {}", path, error),
+        }
+    }
+}
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
@@ -0,0 +1,106 @@
+use super::*;
+use crate::disasm::analyse;
+
+#[test]
+fn test_assemble() {
+    let source = "
+        SPEED = 3          ; a constant
+        start: LD I, sprite
+            ld v0, SPEED + 1
+            DRW V0, V1, 0x2
+            SHR V2 {, V3}
+            SHL V4
+            JP start
+        sprite:
+            db 0b11110000, 0x90, -1
+            dw 0x1234
+        end:
+            LD I, LONG end - 1
+    ";
+    assert_eq!(
+        assemble(source).unwrap(),
+        [
+            0xA2, 0x0C, 0x60, 0x04, 0xD0, 0x12, 0x82, 0x36, 0x84, 0x4E, 0x12, 0x00, 0xF0, 0x90,
+            0xFF, 0x12, 0x34, 0xF0, 0x00, 0x02, 0x10
+        ]
+    );
+}
+
+#[test]
+fn test_roundtrip_mnemonics() {
+    for word in 0..=0xFFFFu16 {
+        let opcode = Opcode::with_operand(word, 0xABCD);
+        let kind = match opcode.decode() {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let bytes = assemble(&format!("{:?}", kind)).unwrap();
+        let mut expected = word.to_be_bytes().to_vec();
+        if opcode.size() == 4 {
+            expected.extend_from_slice(&[0xAB, 0xCD]);
+        }
+        assert_eq!(bytes, expected, "{:?}", kind);
+    }
+}
+
+#[test]
+fn test_reassemble_listing() {
+    let rom = include_bytes!("../../IBM_Logo.ch8");
+    let listing = analyse(rom, 0x200).listing();
+    // Keep the labels and the mnemonics, not the addresses and the words.
+    let source = listing
+        .lines()
+        .map(|line| match line.ends_with(':') {
+            true => line,
+            false => line.split("  ").filter(|s| !s.is_empty()).nth(2).unwrap_or(""),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert_eq!(assemble(&source).unwrap(), rom);
+}
+
+#[test]
+fn test_errors() {
+    let error = |source| {
+        let e = assemble(source).unwrap_err();
+        (e.line, e.column, e.to_string())
+    };
+    assert_eq!(error("CLS\n  FOO V0"), (2, 3, "<input>:2:3: unknown mnemonic FOO".to_string()));
+    assert_eq!(error("  LD V0,  0x100").1, 11);
+    assert!(matches!(
+        assemble("LD V0, 0x100").unwrap_err().kind,
+        AsmErrorKind::OutOfRange { value: 0x100, .. }
+    ));
+    assert!(matches!(assemble("JP nowhere").unwrap_err().kind, AsmErrorKind::UndefinedSymbol(_)));
+    assert!(matches!(assemble("a:\na:").unwrap_err().kind, AsmErrorKind::DuplicateSymbol(_)));
+    assert!(matches!(
+        assemble("p = q\nq = p\nJP p").unwrap_err().kind,
+        AsmErrorKind::RecursiveSymbol(_)
+    ));
+    assert!(matches!(assemble("ADD V0, DT").unwrap_err().kind, AsmErrorKind::InvalidOperands(_)));
+    assert!(matches!(assemble("JP V1, 0x300").unwrap_err().kind, AsmErrorKind::InvalidOperands(_)));
+    assert!(matches!(assemble("db 0x1g").unwrap_err().kind, AsmErrorKind::InvalidNumber(_)));
+    assert!(matches!(assemble("include \"x.s\"").unwrap_err().kind, AsmErrorKind::Syntax(_)));
+    assert!(matches!(assemble("includé x").unwrap_err().kind, AsmErrorKind::UnknownMnemonic(_)));
+    assert_eq!(
+        error("db 0x7FFFFFFFFFFFFFFF + 1"),
+        (1, 25, "<input>:1:25: expression overflows".to_string())
+    );
+}
+
+#[test]
+fn test_include() {
+    let dir = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("main.s"), "CALL draw\ninclude \"lib/draw.s\"\n").unwrap();
+    fs::write(dir.join("lib/draw.s"), "draw:\n  include \"sprite.s\"\n  RET\n").unwrap();
+    fs::write(dir.join("lib/sprite.s"), "  LD I, 0x300\n  bogus\n").unwrap();
+    let e = assemble_file(&dir.join("main.s")).unwrap_err();
+    assert!(e.file.ends_with("sprite.s"));
+    assert_eq!((e.line, e.column), (2, 3));
+
+    fs::write(dir.join("lib/sprite.s"), "  LD I, 0x300\n").unwrap();
+    let rom = assemble_file(&dir.join("main.s"));
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(rom.unwrap(), [0x22, 0x02, 0xA3, 0x00, 0x00, 0xEE]);
+}
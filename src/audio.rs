@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod tests;
+
+use std::io::{self, Write};
+
+const DEFAULT_FREQUENCY: f32 = 440.0;
+const DEFAULT_VOLUME: f32 = 0.25;
+// Time for the volume to go from silence to full or back, avoiding
+// the clicks of a wave starting or stopping abruptly.
+const RAMP_SECONDS: f32 = 0.005;
+
+/// Square wave generator for the beep made while the sound timer is active.
+pub struct Buzzer {
+    sample_rate: u32,
+    frequency: f32,
+    volume: f32,
+    // Position in the current period, from 0 to 1.
+    phase: f32,
+    // Envelope, from 0 (silent) to 1 (full volume).
+    level: f32,
+}
+
+impl Buzzer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            frequency: DEFAULT_FREQUENCY,
+            volume: DEFAULT_VOLUME,
+            phase: 0.0,
+            level: 0.0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    /// Pitch of the beep, in Hz.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency.max(0.0);
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Amplitude of the wave, from 0 to 1.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// Fills `out` with the next samples, fading in or out depending on
+    /// whether the sound is `active`, see [`Cpu::is_sound_active`].
+    ///
+    /// [`Cpu::is_sound_active`]: crate::Cpu::is_sound_active
+    pub fn fill(&mut self, active: bool, out: &mut [f32]) {
+        let rate = self.sample_rate as f32;
+        let ramp = 1.0 / (RAMP_SECONDS * rate);
+        let target = if active { 1.0 } else { 0.0 };
+        for sample in out {
+            self.level = if self.level < target {
+                (self.level + ramp).min(target)
+            } else {
+                (self.level - ramp).max(target)
+            };
+            if self.level == 0.0 {
+                // Restart the wave at the beginning of a period.
+                self.phase = 0.0;
+                *sample = 0.0;
+                continue;
+            }
+            let square = if self.phase < 0.5 { 1.0 } else { -1.0 };
+            *sample = square * self.volume * self.level;
+            self.phase = (self.phase + self.frequency / rate).fract();
+        }
+    }
+}
+
+/// Writes mono `samples` from -1 to 1 as a 16-bit PCM WAV file.
+pub fn write_wav<W: Write>(mut w: W, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+    const CHANNELS: u16 = 1;
+    const BITS: u16 = 16;
+    let block_align = CHANNELS * BITS / 8;
+    let data_size = samples.len() as u32 * u32::from(block_align);
+
+    w.write_all(b"RIFF")?;
+    w.write_all(&(36 + data_size).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    // PCM
+    w.write_all(&1u16.to_le_bytes())?;
+    w.write_all(&CHANNELS.to_le_bytes())?;
+    w.write_all(&sample_rate.to_le_bytes())?;
+    w.write_all(&(sample_rate * u32::from(block_align)).to_le_bytes())?;
+    w.write_all(&block_align.to_le_bytes())?;
+    w.write_all(&BITS.to_le_bytes())?;
+    w.write_all(b"data")?;
+    w.write_all(&data_size.to_le_bytes())?;
+    for &s in samples {
+        let s = (s.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+        w.write_all(&s.to_le_bytes())?;
+    }
+    w.flush()
+}
@@ -0,0 +1,54 @@
+use super::*;
+
+const RATE: u32 = 8000;
+
+#[test]
+fn test_silent_when_inactive() {
+    let mut buzzer = Buzzer::new(RATE);
+    let mut out = [1.0; 256];
+    buzzer.fill(false, &mut out);
+    assert!(out.iter().all(|&s| s == 0.0));
+}
+
+#[test]
+fn test_square_wave() {
+    let mut buzzer = Buzzer::new(RATE);
+    buzzer.set_frequency(1000.0);
+    buzzer.set_volume(0.5);
+    let mut out = [0.0; 800];
+    buzzer.fill(true, &mut out);
+    // Past the fade in, 4 samples up then 4 down.
+    let settled = &out[400..416];
+    assert_eq!(settled, [0.5, 0.5, 0.5, 0.5, -0.5, -0.5, -0.5, -0.5].repeat(2));
+}
+
+#[test]
+fn test_no_clicks() {
+    let mut buzzer = Buzzer::new(RATE);
+    buzzer.set_volume(1.0);
+    let mut out = vec![0.0; 3000];
+    buzzer.fill(true, &mut out[..1000]);
+    buzzer.fill(false, &mut out[1000..2000]);
+    buzzer.fill(true, &mut out[2000..]);
+    // The wave flips sign, but its amplitude never jumps.
+    let max_step = 1.0 / (RAMP_SECONDS * RATE as f32) + 1e-6;
+    let mut previous = 0.0f32;
+    for (i, &s) in out.iter().enumerate() {
+        assert!((s.abs() - previous.abs()).abs() <= max_step, "click at sample {}", i);
+        previous = s;
+    }
+    assert_eq!(out[1999], 0.0);
+    assert_eq!(out[999].abs(), 1.0);
+}
+
+#[test]
+fn test_wav() {
+    let mut wav = Vec::new();
+    write_wav(&mut wav, RATE, &[0.0, 1.0, -1.0]).unwrap();
+    assert_eq!(wav.len(), 44 + 6);
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), RATE);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(wav[44..], [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80]);
+}
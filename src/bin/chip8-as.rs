@@ -0,0 +1,52 @@
+//! Assembles a source file into a ROM.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use chip8emu::assemble_file;
+
+fn main() {
+    let mut source_path: Option<PathBuf> = None;
+    let mut out_path: Option<PathBuf> = None;
+    let mut argv = env::args_os().skip(1);
+    while let Some(arg) = argv.next() {
+        if arg == "-o" {
+            out_path = Some(argv.next().unwrap_or_else(|| usage()).into());
+        } else if source_path.is_none() {
+            source_path = Some(arg.into());
+        } else {
+            usage();
+        }
+    }
+    let source_path = source_path.unwrap_or_else(|| usage());
+    let out_path = out_path.unwrap_or_else(|| source_path.with_extension("ch8"));
+    if out_path == source_path || same_file(&out_path, &source_path) {
+        eprintln!("Refusing to overwrite {} with its own ROM, use -o", source_path.display());
+        process::exit(2);
+    }
+
+    let rom = assemble_file(&source_path).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    });
+    if let Err(e) = fs::write(&out_path, rom) {
+        eprintln!("Cannot write {}: {}", out_path.display(), e);
+        process::exit(1);
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: chip8-as SOURCE [-o ROM]\n\
+        \n\
+        Assembles SOURCE into ROM, which is SOURCE with the .ch8 extension\n\
+        by default."
+    );
+    process::exit(2);
+}
@@ -0,0 +1,117 @@
+//! Prints the disassembly listing of a ROM.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+use chip8emu::{analyse, disassemble, Instruction, OpcodeKind};
+
+const ROM_START_ADDR: u16 = 0x200;
+
+fn main() {
+    let mut rom_path: Option<OsString> = None;
+    let mut base = ROM_START_ADDR;
+    let (mut linear, mut dot) = (false, false);
+    let mut argv = env::args_os().skip(1);
+    while let Some(arg) = argv.next() {
+        if arg == "--base" {
+            let addr = argv.next().unwrap_or_else(|| usage());
+            let addr = addr.to_str().map(|s| s.trim_start_matches("0x"));
+            base = addr.and_then(|s| u16::from_str_radix(s, 16).ok()).unwrap_or_else(|| usage());
+        } else if arg == "--linear" {
+            linear = true;
+        } else if arg == "--dot" {
+            dot = true;
+        } else if rom_path.is_none() {
+            rom_path = Some(arg);
+        } else {
+            usage();
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| usage());
+    let rom = fs::read(&rom_path).unwrap_or_else(|e| {
+        eprintln!("Cannot read {}: {}", rom_path.to_string_lossy(), e);
+        process::exit(1);
+    });
+    if usize::from(base) + rom.len() > 0x10000 {
+        eprintln!("The ROM doesn't fit in memory at {:#X}", base);
+        process::exit(1);
+    }
+
+    let printed = if linear {
+        print_linear(&rom, base)
+    } else {
+        let analysis = analyse(&rom, base);
+        let text = if dot { analysis.to_dot() } else { analysis.listing() };
+        io::stdout().write_all(text.as_bytes())
+    };
+    if let Err(e) = printed {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+// Every word is decoded, data included.
+fn print_linear(rom: &[u8], base: u16) -> io::Result<()> {
+    let listing = disassemble(rom, base).collect::<Vec<_>>();
+    let end = u32::from(base) + rom.len() as u32;
+    // Only the targets inside the ROM get a label.
+    let labels = listing
+        .iter()
+        .filter_map(target)
+        .filter(|&addr| addr >= base && u32::from(addr) < end)
+        .collect::<BTreeSet<_>>();
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    listing.iter().try_for_each(|i| {
+        if labels.contains(&i.addr) {
+            writeln!(out, "{}:", label(i.addr))?;
+        }
+        let text = match (i.kind, target(i)) {
+            (Some(OpcodeKind::JpAddr { .. }), Some(addr)) if labels.contains(&addr) => {
+                format!("JMP {}", label(addr))
+            }
+            (Some(OpcodeKind::JpVxAddr { x, .. }), Some(addr)) if labels.contains(&addr) => {
+                format!("JMP V{:X}, {}", x, label(addr))
+            }
+            (Some(OpcodeKind::Call { .. }), Some(addr)) if labels.contains(&addr) => {
+                format!("CALL {}", label(addr))
+            }
+            _ => i.text.clone(),
+        };
+        match i.size {
+            1 => writeln!(out, "    {:03X}  {:02X}    {}", i.addr, i.word, text),
+            _ => writeln!(out, "    {:03X}  {:04X}  {}", i.addr, i.word, text),
+        }
+    })?;
+    out.flush()
+}
+
+// Where a jump or a call goes.
+fn target(i: &Instruction) -> Option<u16> {
+    match i.kind? {
+        OpcodeKind::JpAddr { addr } | OpcodeKind::JpVxAddr { addr, .. } => Some(addr),
+        OpcodeKind::Call { addr } => Some(addr),
+        _ => None,
+    }
+}
+
+fn label(addr: u16) -> String {
+    format!("L_{:03X}", addr)
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: chip8-dis [--base ADDR] [--linear | --dot] ROM\n\
+        \n\
+        Prints the instructions of ROM, loaded at the hexadecimal address\n\
+        ADDR, 200 by default. Only what the program can reach is code,\n\
+        unless --linear decodes every word. --dot prints the control-flow\n\
+        graph for Graphviz instead."
+    );
+    process::exit(2);
+}
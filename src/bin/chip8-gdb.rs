@@ -0,0 +1,64 @@
+//! Runs a ROM behind a GDB remote serial protocol server.
+//!
+//! In GDB: `target remote localhost:1234`.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::net::TcpListener;
+use std::process;
+
+use chip8emu::{Cpu, GdbStub, Platform};
+
+const DEFAULT_PORT: u16 = 1234;
+
+fn main() {
+    let mut rom_path: Option<OsString> = None;
+    let mut platform = Platform::default();
+    let mut port = DEFAULT_PORT;
+    let mut argv = env::args_os().skip(1);
+    while let Some(arg) = argv.next() {
+        if arg == "--platform" {
+            let name = argv.next().unwrap_or_else(|| usage());
+            platform = name.to_str().and_then(|s| s.parse().ok()).unwrap_or_else(|| usage());
+        } else if arg == "--port" {
+            let n = argv.next().unwrap_or_else(|| usage());
+            port = n.to_str().and_then(|s| s.parse().ok()).unwrap_or_else(|| usage());
+        } else if rom_path.is_none() {
+            rom_path = Some(arg);
+        } else {
+            usage();
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| usage());
+
+    let rom = fs::read(&rom_path).unwrap_or_else(|e| {
+        eprintln!("Cannot read {}: {}", rom_path.to_string_lossy(), e);
+        process::exit(1);
+    });
+    let mut cpu = Cpu::new(platform.quirks());
+    if let Err(e) = cpu.load_game(&rom) {
+        eprintln!("Cannot load {}: {}", rom_path.to_string_lossy(), e);
+        process::exit(1);
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|e| {
+        eprintln!("Cannot listen on port {}: {}", port, e);
+        process::exit(1);
+    });
+    eprintln!("Waiting for GDB on localhost:{} ...", port);
+    let result = listener.accept().and_then(|(stream, _)| GdbStub::new(stream)?.serve(&mut cpu));
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: chip8-gdb [--platform cosmac|chip48|superchip|amiga|xochip] [--port N] ROM\n\
+        \n\
+        Waits for GDB on localhost, port 1234 by default."
+    );
+    process::exit(2);
+}
@@ -0,0 +1,244 @@
+//! Runs a ROM without a window, then prints the screen and the registers.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use chip8emu::{
+    ascii_art, compile_octo, write_pbm, write_png, Cpu, CpuState, KeyCode, Platform, PALETTE,
+};
+
+const DEFAULT_FRAMES: u64 = 60;
+// 540 instructions per second, like the interpreter.
+const DEFAULT_CYCLES_PER_FRAME: u32 = 9;
+const ROM_START_ADDR: u16 = 0x200;
+
+struct Args {
+    rom_path: OsString,
+    platform: Platform,
+    seed: u64,
+    frames: u64,
+    cycles: Option<u64>,
+    speed: u32,
+    keys_path: Option<OsString>,
+    out_path: Option<PathBuf>,
+    coverage_path: Option<PathBuf>,
+}
+
+// Presses or releases `key` at the start of `frame`.
+struct KeyEvent {
+    frame: u64,
+    key: KeyCode,
+    pressed: bool,
+}
+
+fn main() {
+    let args = parse_args();
+    let rom = read_rom(Path::new(&args.rom_path)).unwrap_or_else(|e| {
+        eprintln!("Cannot read {}: {}", args.rom_path.to_string_lossy(), e);
+        process::exit(1);
+    });
+    let mut events = match &args.keys_path {
+        Some(path) => read_keys(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Cannot read {}: {}", path.to_string_lossy(), e);
+            process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+    events.sort_by_key(|e| e.frame);
+
+    let mut cpu = Cpu::with_seed(args.platform.quirks(), args.seed);
+    if let Err(e) = cpu.load_game(&rom) {
+        eprintln!("Cannot load {}: {}", args.rom_path.to_string_lossy(), e);
+        process::exit(1);
+    }
+    cpu.set_coverage(args.coverage_path.is_some());
+
+    let mut events = events.into_iter().peekable();
+    let mut cycles_left = args.cycles.unwrap_or(u64::MAX);
+    let mut frame = 0;
+    let mut failed = false;
+    while cycles_left > 0 && (args.cycles.is_some() || frame < args.frames) {
+        while let Some(event) = events.next_if(|e| e.frame <= frame) {
+            if let (true, CpuState::Paused) = (event.pressed, &cpu.state) {
+                cpu.state = CpuState::Running;
+            }
+            cpu.set_key_state(event.key, event.pressed);
+        }
+        let cycles = cycles_left.min(u64::from(args.speed)) as u32;
+        if let Err(e) = cpu.run_frame(cycles) {
+            eprintln!("error: {}", e);
+            failed = true;
+            break;
+        }
+        if let CpuState::Halted = cpu.state {
+            break;
+        }
+        cycles_left -= u64::from(cycles);
+        frame += 1;
+    }
+
+    let written = match &args.out_path {
+        Some(path) => write_screen(path, &cpu),
+        None => io::stdout().write_all(ascii_art(cpu.resolution(), cpu.get_vram()).as_bytes()),
+    };
+    if let Err(e) = written {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+    if let Some(path) = &args.coverage_path {
+        if let Err(e) = write_coverage(path, &args.rom_path, &rom, &cpu) {
+            eprintln!("Cannot write {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+    println!(
+        "frames: {}\n\
+        PC: {:#05X}  I: {:#05X}  stack: {:03X?}\n\
+        V0-VF: {:02X?}",
+        frame,
+        cpu.pc(),
+        cpu.i(),
+        cpu.stack(),
+        cpu.registers(),
+    );
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn read_rom(path: &Path) -> io::Result<Vec<u8>> {
+    if path.extension().is_none_or(|ext| ext != "8o") {
+        return fs::read(path);
+    }
+    let source = fs::read_to_string(path)?;
+    compile_octo(&source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// One event per line: the frame, the hexadecimal key and `down` or `up`.
+fn read_keys(path: &Path) -> io::Result<Vec<KeyEvent>> {
+    let mut events = Vec::new();
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let fields = line.split('#').next().unwrap_or("").split_whitespace();
+        let fields = fields.collect::<Vec<_>>();
+        if fields.is_empty() {
+            continue;
+        }
+        let event = match fields[..] {
+            [frame, key, state] => (|| {
+                let frame = frame.parse().ok()?;
+                let key =
+                    u8::from_str_radix(key, 16).ok().and_then(|k| KeyCode::try_from(k).ok())?;
+                let pressed = match state {
+                    "down" => true,
+                    "up" => false,
+                    _ => return None,
+                };
+                Some(KeyEvent { frame, key, pressed })
+            })(),
+            _ => None,
+        };
+        match event {
+            Some(event) => events.push(event),
+            None => {
+                let msg = format!("line {}: expected FRAME KEY down|up", i + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+        }
+    }
+    Ok(events)
+}
+
+fn write_screen(path: &Path, cpu: &Cpu) -> io::Result<()> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let size = cpu.resolution();
+    match ext.to_ascii_lowercase().as_str() {
+        "pbm" => write_pbm(BufWriter::new(File::create(path)?), size, cpu.get_vram()),
+        "png" => write_png(BufWriter::new(File::create(path)?), size, cpu.get_vram(), &PALETTE, 1),
+        "txt" => fs::write(path, ascii_art(size, cpu.get_vram())),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "expected a .pbm, .png or .txt file")),
+    }
+}
+
+// An lcov tracefile for .info files, the annotated disassembly otherwise.
+fn write_coverage(path: &Path, rom_path: &OsString, rom: &[u8], cpu: &Cpu) -> io::Result<()> {
+    let coverage = cpu.coverage().unwrap();
+    let report = match path.extension().is_some_and(|ext| ext == "info") {
+        true => coverage.lcov(&rom_path.to_string_lossy(), rom, ROM_START_ADDR),
+        false => coverage.listing(rom, ROM_START_ADDR),
+    };
+    fs::write(path, report)
+}
+
+fn parse_args() -> Args {
+    let mut rom_path = None;
+    let mut args = Args {
+        rom_path: OsString::new(),
+        platform: Platform::default(),
+        seed: 0,
+        frames: DEFAULT_FRAMES,
+        cycles: None,
+        speed: DEFAULT_CYCLES_PER_FRAME,
+        keys_path: None,
+        out_path: None,
+        coverage_path: None,
+    };
+    let mut argv = env::args_os().skip(1);
+    while let Some(arg) = argv.next() {
+        let mut value = || argv.next().unwrap_or_else(|| usage());
+        if arg == "--platform" {
+            args.platform = parse(&value());
+        } else if arg == "--seed" {
+            args.seed = parse(&value());
+        } else if arg == "--frames" {
+            args.frames = parse(&value());
+        } else if arg == "--cycles" {
+            args.cycles = Some(parse(&value()));
+        } else if arg == "--speed" {
+            args.speed = parse(&value());
+            if args.speed == 0 {
+                usage();
+            }
+        } else if arg == "--keys" {
+            args.keys_path = Some(value());
+        } else if arg == "--coverage" {
+            args.coverage_path = Some(value().into());
+        } else if arg == "-o" {
+            args.out_path = Some(value().into());
+        } else if rom_path.is_none() {
+            rom_path = Some(arg);
+        } else {
+            usage();
+        }
+    }
+    args.rom_path = rom_path.unwrap_or_else(|| usage());
+    args
+}
+
+fn parse<T: std::str::FromStr>(s: &OsString) -> T {
+    s.to_str().and_then(|s| s.parse().ok()).unwrap_or_else(|| usage())
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: chip8-headless [--platform cosmac|chip48|superchip|amiga|xochip] [--seed N]\n\
+        \x20                     [--frames N | --cycles N] [--speed N] [--keys FILE]\n\
+        \x20                     [--coverage FILE] [-o FILE] ROM\n\
+        \n\
+        Runs ROM for 60 frames of 9 instructions, or as many as --frames\n\
+        and --speed say, or for --cycles instructions in total. --speed is\n\
+        at least 1. A ROM ending in .8o is compiled from Octo source first.\n\
+        The random numbers come from --seed, 0 by default.\n\
+        \n\
+        --keys reads lines of `FRAME KEY down|up`, KEY being hexadecimal.\n\
+        --coverage writes which bytes of ROM were executed, read and written:\n\
+        an lcov tracefile for a FILE ending in .info, an annotated\n\
+        disassembly otherwise.\n\
+        -o writes the screen to a .pbm, .png or .txt file instead of\n\
+        printing it. The registers are printed last."
+    );
+    process::exit(2);
+}
@@ -0,0 +1,197 @@
+//! Which bytes of memory a program executed, read and wrote.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::disasm::{analyse, disassemble, Analysis, Instruction};
+use crate::memory::EXTENDED_RAM_SIZE;
+
+// How a byte was used, one bit each.
+pub(crate) const EXECUTED: u8 = 1;
+pub(crate) const READ: u8 = 2;
+pub(crate) const WRITTEN: u8 = 4;
+// Written after being executed.
+pub(crate) const MODIFIED: u8 = 8;
+
+/// How every byte of memory was used since
+/// [`Cpu::set_coverage`](crate::Cpu::set_coverage) enabled it.
+///
+/// Bytes are executed when fetched as an instruction, read when loaded
+/// through I by `DRW`, `LD Vx, [I]` and the like, and written through I by
+/// `LD [I], Vx` and `LD B, Vx`. Bytes are modified when written after
+/// being executed, by self-modifying code.
+#[derive(Clone)]
+pub struct Coverage {
+    flags: Box<[u8]>,
+}
+
+enum Line {
+    Code(Instruction),
+    Data { addr: u16, byte: u8 },
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self { flags: vec![0; EXTENDED_RAM_SIZE].into_boxed_slice() }
+    }
+}
+
+impl Coverage {
+    pub(crate) fn mark(&mut self, range: Range<usize>, access: u8) {
+        self.flags[range].iter_mut().for_each(|flags| *flags |= access);
+    }
+
+    pub(crate) fn flags(&self, addr: usize) -> u8 {
+        self.flags[addr]
+    }
+
+    pub(crate) fn set_flags(&mut self, addr: usize, flags: u8) {
+        self.flags[addr] = flags;
+    }
+
+    pub fn executed(&self, addr: u16) -> bool {
+        self.flags[usize::from(addr)] & EXECUTED != 0
+    }
+
+    pub fn read(&self, addr: u16) -> bool {
+        self.flags[usize::from(addr)] & READ != 0
+    }
+
+    pub fn written(&self, addr: u16) -> bool {
+        self.flags[usize::from(addr)] & WRITTEN != 0
+    }
+
+    pub fn modified(&self, addr: u16) -> bool {
+        self.flags[usize::from(addr)] & MODIFIED != 0
+    }
+
+    /// Disassembly of `rom`, loaded at `base_addr`, where every line starts
+    /// with how it was used: `X` for executed, `R` for read, `W` for
+    /// written and `M` for modified, `-` otherwise.
+    ///
+    /// Code is what [`analyse`] finds, along with anything executed.
+    pub fn listing(&self, rom: &[u8], base_addr: u16) -> String {
+        let analysis = analyse(rom, base_addr);
+        let lines = self.lines(&analysis, rom, base_addr);
+        let (code, executed) = self.count_code(&lines);
+        let data = lines.iter().filter(|l| matches!(l, Line::Data { .. })).count();
+        let read = self.count(rom, base_addr, READ);
+        let written = self.count(rom, base_addr, WRITTEN);
+        let modified = self.count(rom, base_addr, MODIFIED);
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "; {}/{} instructions executed ({:.1}%)",
+            executed,
+            code,
+            percent(executed, code)
+        );
+        let _ =
+            writeln!(out, "; {} data bytes, {} bytes read, {} bytes written", data, read, written);
+        let _ = writeln!(out, "; {} bytes of code modified", modified);
+        for line in &lines {
+            let addr = match line {
+                Line::Code(i) => i.addr,
+                Line::Data { addr, .. } => *addr,
+            };
+            if let Some(label) = analysis.label(addr) {
+                let _ = writeln!(out, "{}:", label);
+            }
+            let _ = match line {
+                Line::Code(i) => writeln!(
+                    out,
+                    "{}  {:03X}  {:04X}  {}",
+                    self.marks(i.addr, i.size),
+                    i.addr,
+                    i.word,
+                    i.text
+                ),
+                Line::Data { addr, byte } => writeln!(
+                    out,
+                    "{}  {:03X}  {:02X}    db {:#010b}",
+                    self.marks(*addr, 1),
+                    addr,
+                    byte,
+                    byte
+                ),
+            };
+        }
+        out
+    }
+
+    /// An [lcov] tracefile record for `rom`, loaded at `base_addr`, which
+    /// `genhtml` and most coverage tools read.
+    ///
+    /// Lines are the addresses of the instructions, in decimal, and their
+    /// hit count is 1 when executed.
+    ///
+    /// [lcov]: https://manpages.debian.org/unstable/lcov/geninfo.1.en.html#TRACEFILE_FORMAT
+    pub fn lcov(&self, name: &str, rom: &[u8], base_addr: u16) -> String {
+        let lines = self.lines(&analyse(rom, base_addr), rom, base_addr);
+        let mut out = format!("TN:\nSF:{}\n", name);
+        for line in &lines {
+            if let Line::Code(i) = line {
+                let _ = writeln!(out, "DA:{},{}", i.addr, u8::from(self.executed(i.addr)));
+            }
+        }
+        let (code, executed) = self.count_code(&lines);
+        let _ = writeln!(out, "LF:{}\nLH:{}\nend_of_record", code, executed);
+        out
+    }
+
+    // Code is whatever the control flow reaches or the program ran.
+    fn lines(&self, analysis: &Analysis, rom: &[u8], base_addr: u16) -> Vec<Line> {
+        let code = analysis.instructions().map(|i| i.addr).collect::<HashSet<_>>();
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < rom.len() {
+            let addr = base_addr.wrapping_add(offset as u16);
+            let decoded = disassemble(&rom[offset..], addr).next().filter(|i| i.kind.is_some());
+            let is_code = code.contains(&addr) || self.executed(addr);
+            match decoded {
+                Some(i) if is_code => {
+                    offset += usize::from(i.size);
+                    lines.push(Line::Code(i));
+                }
+                _ => {
+                    lines.push(Line::Data { addr, byte: rom[offset] });
+                    offset += 1;
+                }
+            }
+        }
+        lines
+    }
+
+    fn count_code(&self, lines: &[Line]) -> (usize, usize) {
+        let code = lines.iter().filter_map(|l| match l {
+            Line::Code(i) => Some(i.addr),
+            Line::Data { .. } => None,
+        });
+        code.fold((0, 0), |(n, x), addr| (n + 1, x + usize::from(self.executed(addr))))
+    }
+
+    fn count(&self, rom: &[u8], base_addr: u16, access: u8) -> usize {
+        let start = usize::from(base_addr);
+        let end = (start + rom.len()).min(self.flags.len());
+        self.flags[start..end].iter().filter(|&&flags| flags & access != 0).count()
+    }
+
+    fn marks(&self, addr: u16, size: u16) -> String {
+        let start = usize::from(addr);
+        let bytes = self.flags.get(start..start + usize::from(size)).unwrap_or_default();
+        let flags = bytes.iter().fold(0, |all, flags| all | flags);
+        [(EXECUTED, 'X'), (READ, 'R'), (WRITTEN, 'W'), (MODIFIED, 'M')]
+            .iter()
+            .map(|&(access, mark)| if flags & access != 0 { mark } else { '-' })
+            .collect()
+    }
+}
+
+fn percent(n: usize, total: usize) -> f64 {
+    100.0 * n as f64 / total.max(1) as f64
+}
@@ -0,0 +1,110 @@
+use crate::asm::assemble;
+use crate::cpu::Cpu;
+use crate::quirks::Platform;
+
+// Draws a sprite, stores digits, and never clears the screen.
+const SOURCE: &str = "
+        LD I, sprite
+        DRW V0, V0, 2
+        LD I, digits
+        LD B, V1
+        SE V0, 1
+        JP end
+        CLS
+    end:
+        JP end
+    sprite:
+        db 0xF0, 0x90
+    digits:
+        db 0, 0, 0
+";
+
+fn run(cycles: usize) -> (Cpu, Vec<u8>) {
+    let rom = assemble(SOURCE).unwrap();
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&rom).unwrap();
+    cpu.set_coverage(true);
+    for _ in 0..cycles {
+        cpu.execute_cycle().unwrap();
+    }
+    (cpu, rom)
+}
+
+#[test]
+fn test_coverage() {
+    let (mut cpu, _) = run(8);
+    let coverage = cpu.coverage().unwrap();
+    let executed = (0x200..0x216).filter(|&a| coverage.executed(a)).collect::<Vec<_>>();
+    let mut expected = (0x200..0x20C).collect::<Vec<_>>();
+    expected.extend([0x20E, 0x20F]);
+    assert_eq!(executed, expected);
+    let read = (0..0x1000).filter(|&a| coverage.read(a)).collect::<Vec<_>>();
+    assert_eq!(read, [0x210, 0x211]);
+    let written = (0..0x1000).filter(|&a| coverage.written(a)).collect::<Vec<_>>();
+    assert_eq!(written, [0x212, 0x213, 0x214]);
+
+    // Kept across resets, until disabled.
+    cpu.reset();
+    assert!(cpu.coverage().unwrap().executed(0x200));
+    cpu.set_coverage(false);
+    assert!(cpu.coverage().is_none());
+    cpu.set_coverage(true);
+    assert!(!cpu.coverage().unwrap().executed(0x200));
+
+    assert!(Cpu::new(Platform::Chip48.quirks()).coverage().is_none());
+}
+
+#[test]
+fn test_listing() {
+    let (cpu, rom) = run(8);
+    assert_eq!(
+        cpu.coverage().unwrap().listing(&rom, 0x200),
+        "; 7/8 instructions executed (87.5%)\n\
+         ; 5 data bytes, 2 bytes read, 3 bytes written\n\
+         ; 0 bytes of code modified\n\
+         X---  200  A210  LD I, 0x210\n\
+         X---  202  D002  DRW V0, V0, 0x2\n\
+         X---  204  A212  LD I, 0x212\n\
+         X---  206  F133  LD B, V1\n\
+         X---  208  3001  SE V0, 0x1\n\
+         X---  20A  120E  JMP 0x20E\n\
+         L_20C:\n\
+         ----  20C  00E0  CLS\n\
+         L_20E:\n\
+         X---  20E  120E  JMP 0x20E\n\
+         data_210:\n\
+         -R--  210  F0    db 0b11110000\n\
+         -R--  211  90    db 0b10010000\n\
+         data_212:\n\
+         --W-  212  00    db 0b00000000\n\
+         --W-  213  00    db 0b00000000\n\
+         --W-  214  00    db 0b00000000\n"
+    );
+}
+
+#[test]
+fn test_lcov() {
+    let (cpu, rom) = run(8);
+    let lcov = cpu.coverage().unwrap().lcov("game.ch8", &rom, 0x200);
+    assert!(lcov.starts_with("TN:\nSF:game.ch8\nDA:512,1\nDA:514,1\n"));
+    assert!(lcov.contains("\nDA:524,0\nDA:526,1\n"));
+    assert!(lcov.ends_with("\nDA:526,1\nLF:8\nLH:7\nend_of_record\n"));
+}
+
+#[test]
+fn test_modified_code() {
+    // LD I, 0x204; LD V0, 0x72; LD V1, 0x5; LD [I], V1; JP 0x204
+    let rom = [0xA2, 0x04, 0x60, 0x72, 0x61, 0x05, 0xF1, 0x55, 0x12, 0x04];
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&rom).unwrap();
+    cpu.set_coverage(true);
+    for _ in 0..4 {
+        cpu.execute_cycle().unwrap();
+    }
+    let coverage = cpu.coverage().unwrap();
+    assert!(coverage.modified(0x204) && coverage.modified(0x205));
+    assert!(!coverage.modified(0x206));
+    let listing = coverage.listing(&rom, 0x200);
+    assert!(listing.contains("; 2 bytes of code modified\n"));
+    assert!(listing.contains("\nX-WM  204  6105  LD V1, 0x5\n"));
+}
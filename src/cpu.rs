@@ -1,19 +1,69 @@
-use std::mem::size_of;
+mod savestate;
+#[cfg(test)]
+mod tests;
 
-use nanorand::{Rng, WyRand};
+use std::error::Error;
+use std::fmt;
+use std::mem::size_of;
+use std::ops::Range;
 
-use super::display::{Display, DISPLAY_SIZE};
+use super::coverage::Coverage;
+use super::display::Display;
 use super::keypad::{KeyCode, KeyState};
 use super::memory::Memory;
 use super::opcode::OpcodeKind;
+use super::quirks::Quirks;
+use super::random::{Random, RandomSource};
 use super::register::Registers;
 use super::stack::Stack;
 use super::timer::{DelayTimer, SoundTimer};
+use super::trace::{TraceEntry, TraceSink};
 
+pub use savestate::StateError;
+
+#[derive(Clone, Copy)]
 pub enum CpuState {
     Running,
+    /// Runs a single instruction, then pauses.
     Step,
+    /// Waiting for a key, or stopped by the frontend.
     Paused,
+    /// The program ran `EXIT` (SUPER-CHIP `00FD`).
+    Halted,
+}
+
+/// Why the emulated program can't go on.
+///
+/// The `Cpu` is left as it was before the faulting instruction,
+/// with the program counter pointing to that instruction.
+pub enum CpuError {
+    InvalidOpcode { pc: u16, opcode: u16 },
+    StackOverflow,
+    StackUnderflow,
+    PcOutOfRange { addr: u16 },
+    MemoryOutOfBounds { addr: u16, len: usize },
+}
+
+/// Self-modifying code: an instruction wrote over bytes that ran as
+/// instructions since they were last written.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CodeWrite {
+    /// Address of the writing instruction.
+    pub pc: u16,
+    /// First byte of code overwritten.
+    pub addr: u16,
+    /// Bytes from `addr` to the last byte of code overwritten.
+    pub len: u16,
+}
+
+pub(crate) struct MemoryAccess {
+    pub range: Range<usize>,
+    pub write: bool,
+}
+
+struct AudioPattern {
+    pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
 }
 
 pub struct Cpu {
@@ -25,23 +75,56 @@ pub struct Cpu {
     // Peripherals
     keypad: KeyState,
     display: Display,
-    randgen: WyRand,
+    randgen: Box<dyn RandomSource>,
+    // SUPER-CHIP RPL user flags
+    flags: [u8; FLAGS_SIZE],
+    // XO-CHIP audio pattern buffer
+    audio: AudioPattern,
     pub state: CpuState,
     should_draw: bool,
+    quirks: Quirks,
+    // Instructions run since creation or the last reset
+    cycles: u64,
+    tracer: Option<Box<dyn TraceSink>>,
+    // Code overwritten by the last instruction
+    code_write: Option<CodeWrite>,
 }
 
 const _: &str = match size_of::<Cpu>() {
-    #[cfg(target_vendor = "apple")]
-    128 => "",
-    #[cfg(not(target_vendor = "apple"))]
-    136 => "",
-    x => ["size of Cpu != 136"][x],
+    224 => "",
+    x => ["size of Cpu != 224"][x],
 };
 
+const FLAGS_SIZE: usize = 16;
+const AUDIO_PATTERN_SIZE: usize = 16;
+// Pitch at which the audio pattern plays at 4000 samples per second.
+const DEFAULT_PITCH: u8 = 64;
+const SCROLL_PIXELS: u8 = 4;
+// `Dxy0` draws a 16x16 sprite, 2 bytes per row.
+const LARGE_SPRITE_SIZE: u8 = 32;
+
+impl AudioPattern {
+    const fn new() -> Self {
+        Self { pattern: [0; AUDIO_PATTERN_SIZE], pitch: DEFAULT_PITCH }
+    }
+}
+
 impl Cpu {
-    pub fn new() -> Self {
+    /// The random numbers of `CXNN` are seeded from the system entropy.
+    pub fn new(quirks: Quirks) -> Self {
+        Self::with_random(quirks, Box::new(Random::new()))
+    }
+
+    /// Same as [`Cpu::new`], but `CXNN` generates the same numbers on
+    /// every run for a given `seed`.
+    pub fn with_seed(quirks: Quirks, seed: u64) -> Self {
+        Self::with_random(quirks, Box::new(Random::with_seed(seed)))
+    }
+
+    /// Same as [`Cpu::new`], with `CXNN` taking its numbers from `randgen`.
+    pub fn with_random(quirks: Quirks, randgen: Box<dyn RandomSource>) -> Self {
         Self {
-            memory: Memory::new(),
+            memory: Memory::new(quirks.extended_memory),
             v: Registers::zero(),
             stack: Stack::new(),
             delay_timer: DelayTimer::new(),
@@ -49,11 +132,26 @@ impl Cpu {
             display: Display::new(),
             keypad: KeyState::new(),
             state: CpuState::Running,
-            randgen: WyRand::new(),
+            randgen,
+            flags: [0; FLAGS_SIZE],
+            audio: AudioPattern::new(),
             should_draw: true,
+            quirks,
+            cycles: 0,
+            tracer: None,
+            code_write: None,
         }
     }
 
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.memory.set_extended(quirks.extended_memory);
+    }
+
     // #[cfg(FALSE)]
     pub fn reset(&mut self) {
         self.memory.reset();
@@ -63,64 +161,265 @@ impl Cpu {
         self.sound_timer.reset();
         self.display.reset();
         self.keypad.reset();
+        self.flags = [0; FLAGS_SIZE];
+        self.audio = AudioPattern::new();
         self.state = CpuState::Running;
         self.should_draw = true;
+        self.cycles = 0;
+        self.code_write = None;
     }
 
     pub fn load_game(&mut self, text: &[u8]) -> Result<(), crate::LoadError> {
         self.memory.load_program(text)
     }
 
-    pub fn execute_cycle(&mut self) -> bool {
+    /// Runs the instruction at the program counter.
+    ///
+    /// Nothing runs while the `Cpu` is `Paused` or `Halted`. In the `Step`
+    /// state, a single instruction runs and the `Cpu` is then `Paused`.
+    pub fn execute_cycle(&mut self) -> Result<bool, CpuError> {
+        let (pc, state) = (self.memory.pc, self.state);
+        match state {
+            CpuState::Running => {}
+            CpuState::Step => self.state = CpuState::Paused,
+            CpuState::Paused | CpuState::Halted => return Ok(false),
+        }
+        let drawn = self.step().inspect_err(|_| {
+            self.memory.pc = pc;
+            self.state = state;
+        })?;
+        self.cycles += 1;
+        Ok(drawn)
+    }
+
+    /// How many instructions ran since the `Cpu` was created or reset.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Sends every instruction run from now on to `tracer`, or stops
+    /// tracing with `None`.
+    pub fn set_tracer(&mut self, tracer: Option<Box<dyn TraceSink>>) {
+        self.tracer = tracer;
+    }
+
+    /// Stops tracing, giving back the sink to [`finish`](TraceSink::finish) it.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn TraceSink>> {
+        self.tracer.take()
+    }
+
+    /// Code that the last instruction wrote over, if any.
+    pub fn code_write(&self) -> Option<CodeWrite> {
+        self.code_write
+    }
+
+    fn register_array(&self) -> [u8; 16] {
+        self.v[..=0xF].try_into().unwrap()
+    }
+
+    /// The instruction at the program counter, if it is a valid one.
+    pub(crate) fn next_instruction(&self) -> Option<OpcodeKind> {
+        self.memory.peek().ok()?.decode()
+    }
+
+    /// Memory that the next instruction reads or writes, instruction
+    /// fetches aside.
+    pub(crate) fn next_memory_access(&self) -> Option<MemoryAccess> {
+        use OpcodeKind::*;
+
+        let planes = self.display.selected_planes();
+        let (len, write) = match self.next_instruction()? {
+            LoadBcd { .. } => (3, true),
+            PushRegs { x } => (x + 1, true),
+            PopRegs { x } => (x + 1, false),
+            Draw { n: 0, .. } => (LARGE_SPRITE_SIZE * planes, false),
+            Draw { n, .. } => (n * planes, false),
+            SaveRange { x, y } => (x.abs_diff(y) + 1, true),
+            LoadRange { x, y } => (x.abs_diff(y) + 1, false),
+            LoadAudio => (AUDIO_PATTERN_SIZE as u8, false),
+            _ => return None,
+        };
+        let start = usize::from(self.memory.i.as_u16());
+        Some(MemoryAccess { range: start..start + usize::from(len), write })
+    }
+
+    fn step(&mut self) -> Result<bool, CpuError> {
         self.should_draw = false;
-        let opcode = self.memory.fetch();
-        let kind = opcode.decode();
-        #[cfg(FALSE)]
-        eprintln!(
-            "0x{:04X} - {:?}\n\
-            {:?}",
-            self.memory.pc.as_u16(),
-            &kind,
-            &self.v,
-        );
-        self.execute(kind);
-        // Update timers
-        // self.delay_timer.decrease();
+        self.code_write = None;
+        let pc = self.memory.pc.as_u16();
+        let marks = self.memory.fetch_marks();
+        let opcode = self.memory.fetch()?;
+        let kind = match opcode.decode() {
+            Some(kind) => kind,
+            None => {
+                self.memory.restore_fetch_marks(&marks);
+                return Err(CpuError::InvalidOpcode { pc, opcode: opcode.as_u16() });
+            }
+        };
+        let before = self.tracer.as_ref().map(|_| (self.register_array(), self.memory.i.as_u16()));
+        if let Err(e) = self.execute(kind) {
+            self.memory.restore_fetch_marks(&marks);
+            return Err(e);
+        }
+        self.code_write =
+            self.memory.take_code_write().map(|(addr, len)| CodeWrite { pc, addr, len });
+        let Some((v_before, i_before)) = before else {
+            return Ok(self.should_draw);
+        };
+        let entry = TraceEntry {
+            cycle: self.cycles,
+            pc,
+            opcode,
+            kind,
+            v_before,
+            v_after: self.register_array(),
+            i_before,
+            i_after: self.memory.i.as_u16(),
+        };
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace(&entry);
+            if let Some(write) = &self.code_write {
+                tracer.code_write(write);
+            }
+        }
+        Ok(self.should_draw)
+    }
+
+    /// Counts both timers down. To be called 60 times per second.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer.decrease();
         self.sound_timer.decrease();
-        self.should_draw
+        if let Some(tracer) = &mut self.tracer {
+            tracer.frame();
+        }
+    }
+
+    /// Emulates a 60 Hz frame: up to `cycles` instructions, then a timer tick.
+    ///
+    /// Instructions only run while the `Cpu` is `Running`, so the frame ends
+    /// early when the program waits for a key or exits. The timers still
+    /// tick in that case. Returns whether the screen changed.
+    pub fn run_frame(&mut self, cycles: u32) -> Result<bool, CpuError> {
+        let mut drawn = false;
+        for _ in 0..cycles {
+            if !matches!(self.state, CpuState::Running) {
+                break;
+            }
+            drawn |= self.execute_cycle()?;
+        }
+        self.tick_timers();
+        Ok(drawn)
     }
 
     pub fn set_key_state(&mut self, kc: KeyCode, pressed: bool) {
         self.keypad[kc] = pressed;
     }
 
-    pub fn get_vram(&self) -> &[bool; DISPLAY_SIZE] {
+    /// Pixels of the screen, row by row, in the current resolution.
+    ///
+    /// Each pixel is a colour index from 0 to 3, bit 0 and bit 1 being
+    /// respectively set by the first and second XO-CHIP bitplanes.
+    /// CHIP-8 and SUPER-CHIP programs only produce 0 and 1.
+    pub fn get_vram(&self) -> &[u8] {
         self.display.get_buf()
     }
 
-    fn execute(&mut self, kind: OpcodeKind) {
+    /// Whether a beep should be heard, as the sound timer is counting down.
+    pub fn is_sound_active(&self) -> bool {
+        self.sound_timer.value() > 0
+    }
+
+    /// The 128 1-bit samples that XO-CHIP programs load with `F002`,
+    /// played in a loop while the sound timer is active.
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        &self.audio.pattern
+    }
+
+    /// Playback rate of the audio pattern in samples per second,
+    /// as set by the XO-CHIP `Fx3A` instruction.
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((f32::from(self.audio.pitch) - 64.0) / 48.0)
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.memory.pc.as_u16()
+    }
+
+    pub fn i(&self) -> u16 {
+        self.memory.i.as_u16()
+    }
+
+    /// Moves the program counter to `addr`, without checking it.
+    pub fn set_pc(&mut self, addr: u16) {
+        self.memory.pc.store(addr);
+    }
+
+    pub fn set_i(&mut self, addr: u16) {
+        self.memory.i.store(addr);
+    }
+
+    /// V0 to VF.
+    pub fn registers(&self) -> &[u8] {
+        &self.v[..=0xF]
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8] {
+        &mut self.v[..=0xF]
+    }
+
+    /// Return addresses of the subroutines being run, innermost last.
+    pub fn stack(&self) -> &[u16] {
+        self.stack.as_slice()
+    }
+
+    /// Starts or stops recording which bytes of memory are executed, read
+    /// and written.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.memory.set_coverage(enabled);
+    }
+
+    /// What was recorded since [`Cpu::set_coverage`] enabled it.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.memory.coverage()
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.memory.as_mut_slice()
+    }
+
+    /// Current `(width, height)` of the screen, which SUPER-CHIP programs
+    /// can switch between 64x32 and 128x64.
+    pub fn resolution(&self) -> (u16, u16) {
+        (self.display.width(), self.display.height())
+    }
+
+    fn execute(&mut self, kind: OpcodeKind) -> Result<(), CpuError> {
         use OpcodeKind::*;
         match kind {
             /* Jump */
-            JpAddr { addr } => self.memory.pc.set(addr),
-            #[cfg(feature = "original")]
-            JpVxAddr { addr } => self.memory.pc.set(addr.wrapping_add(self.v[0].into())),
-            #[cfg(not(feature = "original"))]
-            JpVxAddr { x, addr } => self.memory.pc.set(addr.wrapping_add(self.v[x].into())),
+            JpAddr { addr } => self.memory.jump(addr)?,
+            JpVxAddr { x, addr } => {
+                let x = if self.quirks.jump_uses_vx { x } else { 0 };
+                self.memory.jump(addr.wrapping_add(self.v[x].into()))?;
+            }
 
             /* Subroutines */
-            Ret => self.call_back(),
-            Call { addr } => self.call_to(addr),
+            Ret => self.call_back()?,
+            Call { addr } => self.call_to(addr)?,
 
             /* Conditional branching */
             SkipVxByte { eq, x, byte } => match (eq, self.v[x] == byte) {
-                (true, true) => self.memory.pc.skip_next(),
-                (false, false) => self.memory.pc.skip_next(),
+                (true, true) => self.memory.skip_next(),
+                (false, false) => self.memory.skip_next(),
                 _ => {}
             },
             SkipVxVy { eq, x, y } => match (eq, self.v[x] == self.v[y]) {
-                (true, true) => self.memory.pc.skip_next(),
-                (false, false) => self.memory.pc.skip_next(),
+                (true, true) => self.memory.skip_next(),
+                (false, false) => self.memory.skip_next(),
                 _ => {}
             },
 
@@ -160,12 +459,7 @@ impl Cpu {
             /* SHIFT operations */
             ShiftRight { x, y } => {
                 let (shifted, bit) = {
-                    let y = if cfg!(feature = "original") {
-                        self.v[y]
-                    } else {
-                        let _ = y;
-                        self.v[x]
-                    };
+                    let y = if self.quirks.shift_uses_vy { self.v[y] } else { self.v[x] };
                     (y >> 1, y & 0x1)
                 };
                 self.v.set_vf(bit);
@@ -173,12 +467,7 @@ impl Cpu {
             }
             ShiftLeft { x, y } => {
                 let (shifted, bit) = {
-                    let y = if cfg!(feature = "original") {
-                        self.v[y]
-                    } else {
-                        let _ = y;
-                        self.v[x]
-                    };
+                    let y = if self.quirks.shift_uses_vy { self.v[y] } else { self.v[x] };
                     (y << 1, (y & 0b1000_0000) >> 7)
                 };
                 self.v.set_vf(bit);
@@ -186,10 +475,10 @@ impl Cpu {
             }
 
             /* Random */
-            Random { x, byte } => self.v[x] = self.randgen.generate::<u8>() & byte,
+            Random { x, byte } => self.v[x] = self.randgen.generate() & byte,
 
             /* Timers */
-            LoadDT { x } => self.v[x] = self.delay_timer.load(),
+            LoadDT { x } => self.v[x] = self.delay_timer.value(),
             StoreDT { x } => self.delay_timer.store(self.v[x]),
             StoreST { x } => self.sound_timer.store(self.v[x]),
 
@@ -201,58 +490,139 @@ impl Cpu {
                 }
             },
             SkipIfKey { eq, x } => match (eq, self.keypad.key_down(self.v[x])) {
-                (true, true) => self.memory.pc.skip_next(),
-                (false, false) => self.memory.pc.skip_next(),
+                (true, true) => self.memory.skip_next(),
+                (false, false) => self.memory.skip_next(),
                 _ => {}
             },
 
             /* The I Register for graphics */
             LoadI { addr } => self.memory.i.store(addr),
-            AddIVx { x: _x } => {
-                #[cfg(feature = "amiga")]
-                {
-                    let f = self.memory.i.add_assign(self.v[x]);
-                    self.v.set_vf(u8::from(f));
+            AddIVx { x } => {
+                let size = self.memory.size();
+                let overflowed = self.memory.i.add_assign(self.v[x], size);
+                if self.quirks.add_i_sets_vf {
+                    self.v.set_vf(u8::from(overflowed));
                 }
             }
-            LoadBcd { x } => self.memory.store_bcd(self.v[x]),
-            PushRegs { x } => self.regs_dump(x),
-            PopRegs { x } => self.regs_load(x),
+            LoadBcd { x } => self.memory.store_bcd(self.v[x])?,
+            PushRegs { x } => self.regs_dump(x)?,
+            PopRegs { x } => self.regs_load(x)?,
 
             /* Graphics */
             Cls => self.cls(),
-            Draw { x, y, n } => self.draw((self.v[x], self.v[y]), n),
-            LoadFont { x } => self.memory.i.set_to_builtin_fonts_addr(self.v[x]),
+            Draw { x, y, n } => self.draw((self.v[x], self.v[y]), n)?,
+            LoadFont { x } => {
+                self.memory.i.set_to_builtin_fonts_addr(self.v[x], self.quirks.font_index_masked)
+            }
+
+            /* SUPER-CHIP */
+            ScrollDown { n } => {
+                self.display.scroll_down(n);
+                self.should_draw = true;
+            }
+            ScrollRight => {
+                self.display.scroll_right(SCROLL_PIXELS);
+                self.should_draw = true;
+            }
+            ScrollLeft => {
+                self.display.scroll_left(SCROLL_PIXELS);
+                self.should_draw = true;
+            }
+            Exit => self.state = CpuState::Halted,
+            LowRes => {
+                self.display.set_hires(false);
+                self.should_draw = true;
+            }
+            HighRes => {
+                self.display.set_hires(true);
+                self.should_draw = true;
+            }
+            LoadBigFont { x } => self
+                .memory
+                .i
+                .set_to_builtin_big_fonts_addr(self.v[x], self.quirks.font_index_masked),
+            StoreFlags { x } => {
+                let n = usize::from(x);
+                self.flags[..=n].copy_from_slice(&self.v[..=x]);
+            }
+            LoadFlags { x } => {
+                let n = usize::from(x);
+                self.v[..=x].copy_from_slice(&self.flags[..=n]);
+            }
+
+            /* XO-CHIP */
+            ScrollUp { n } => {
+                self.display.scroll_up(n);
+                self.should_draw = true;
+            }
+            LoadILong { addr } => self.memory.i.store(addr),
+            SaveRange { x, y } => self.range_dump(x, y)?,
+            LoadRange { x, y } => self.range_load(x, y)?,
+            Plane { n } => self.display.select_planes(n),
+            LoadAudio => {
+                let pattern = self.memory.read_bytes_from_i(AUDIO_PATTERN_SIZE as u8)?;
+                self.audio.pattern.copy_from_slice(pattern);
+            }
+            LoadPitch { x } => self.audio.pitch = self.v[x],
         }
+        Ok(())
     }
 
-    fn regs_dump(&mut self, n: u8) {
+    fn regs_dump(&mut self, n: u8) -> Result<(), CpuError> {
         let up_to_vx = &self.v[..=n];
-        self.memory.save_bytes_to_i(up_to_vx);
-        #[cfg(feature = "original")]
-        let _ = self.memory.i.add_assign(n + 1);
+        self.memory.save_bytes_to_i(up_to_vx)?;
+        if self.quirks.load_store_increments_i {
+            let size = self.memory.size();
+            let _ = self.memory.i.add_assign(n + 1, size);
+        }
+        Ok(())
     }
 
-    fn regs_load(&mut self, n: u8) {
-        let dump = self.memory.read_bytes_from_i(n + 1);
+    fn regs_load(&mut self, n: u8) -> Result<(), CpuError> {
+        let dump = self.memory.read_bytes_from_i(n + 1)?;
         self.v[..=n].copy_from_slice(dump);
-        #[cfg(feature = "original")]
-        let _ = self.memory.i.add_assign(n + 1);
+        if self.quirks.load_store_increments_i {
+            let size = self.memory.size();
+            let _ = self.memory.i.add_assign(n + 1, size);
+        }
+        Ok(())
     }
 
-    fn call_to(&mut self, addr: u16) {
-        if let None = self.stack.push(self.memory.pc.as_u16()) {
-            panic!("stack overflow");
+    // Saves VX to VY inclusive, in that order, which is backwards when X > Y.
+    fn range_dump(&mut self, x: u8, y: u8) -> Result<(), CpuError> {
+        let mut regs = self.v[..=x.max(y)][usize::from(x.min(y))..].to_vec();
+        if x > y {
+            regs.reverse();
         }
-        self.memory.pc.set(addr);
+        self.memory.save_bytes_to_i(&regs)
     }
 
-    fn call_back(&mut self) {
+    fn range_load(&mut self, x: u8, y: u8) -> Result<(), CpuError> {
+        let mut regs = self.memory.read_bytes_from_i(x.abs_diff(y) + 1)?.to_vec();
+        if x > y {
+            regs.reverse();
+        }
+        self.v[..=x.max(y)][usize::from(x.min(y))..].copy_from_slice(&regs);
+        Ok(())
+    }
+
+    fn call_to(&mut self, addr: u16) -> Result<(), CpuError> {
+        if self.stack.push(self.memory.pc.as_u16()).is_none() {
+            return Err(CpuError::StackOverflow);
+        }
+        self.memory.jump(addr).inspect_err(|_| {
+            let _ = self.stack.pop();
+        })
+    }
+
+    fn call_back(&mut self) -> Result<(), CpuError> {
         let addr = match self.stack.pop() {
             Some(n) => n,
-            None => panic!("stack is empty: cannot pop from stack"),
+            None => return Err(CpuError::StackUnderflow),
         };
-        self.memory.pc.set(addr);
+        self.memory.jump(addr).inspect_err(|_| {
+            let _ = self.stack.push(addr);
+        })
     }
 
     fn cls(&mut self) {
@@ -260,10 +630,43 @@ impl Cpu {
         self.display.clear_screen();
     }
 
-    fn draw(&mut self, (x, y): (u8, u8), n: u8) {
-        let sprites = self.memory.read_bytes_from_i(n);
-        let overlapped = u8::from(self.display.draw((x, y), sprites));
+    fn draw(&mut self, (x, y): (u8, u8), n: u8) -> Result<(), CpuError> {
+        let wrap = self.quirks.wrap_sprites;
+        let planes = self.display.selected_planes();
+        let overlapped = if n == 0 {
+            let sprites = self.memory.read_bytes_from_i(LARGE_SPRITE_SIZE * planes)?;
+            self.display.draw_large((x, y), sprites, wrap)
+        } else {
+            let sprites = self.memory.read_bytes_from_i(n * planes)?;
+            self.display.draw((x, y), sprites, wrap)
+        };
+        let overlapped = u8::from(overlapped);
         self.v.set_vf(overlapped);
         self.should_draw = true;
+        Ok(())
+    }
+}
+
+impl Error for CpuError {}
+impl fmt::Debug for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::InvalidOpcode { pc, opcode } => {
+                write!(f, "invalid opcode {:04X} at {:#05X}", opcode, pc)
+            }
+            CpuError::StackOverflow => f.write_str("stack overflow"),
+            CpuError::StackUnderflow => f.write_str("stack is empty: cannot pop from stack"),
+            CpuError::PcOutOfRange { addr } => {
+                write!(f, "program counter out of range: {:#05X}", addr)
+            }
+            CpuError::MemoryOutOfBounds { addr, len } => {
+                write!(f, "memory access out of bounds: {} bytes at {:#05X}", len, addr)
+            }
+        }
+    }
+}
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
     }
 }
@@ -0,0 +1,200 @@
+//! Savestates: the complete state of a [`Cpu`] as bytes.
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use super::{Cpu, CpuState, FLAGS_SIZE};
+use crate::display::DISPLAY_SIZE;
+use crate::keypad::KeyCode;
+use crate::memory::{EXTENDED_RAM_SIZE, RAM_SIZE};
+use crate::num::{ReadError, Reader};
+use crate::quirks::Quirks;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+const VERSION: u16 = 1;
+const REGISTERS: usize = 16;
+const STACK_SIZE: usize = 16;
+const KEYS: u8 = 16;
+
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl Cpu {
+    /// Serializes the whole state of the emulator.
+    ///
+    /// # Format
+    ///
+    /// All the numbers are little endian.
+    ///
+    /// | Size          | Content                                                 |
+    /// |---------------|---------------------------------------------------------|
+    /// | 4             | Magic header `C8SS`                                     |
+    /// | 2             | Format version, currently 1                             |
+    /// | 1             | Quirks, one bit each, in the order of `Quirks` fields   |
+    /// | 1             | `CpuState`: 0 running, 1 step, 2 paused, 3 halted       |
+    /// | 2             | Program counter                                         |
+    /// | 2             | I                                                       |
+    /// | 16            | V0 to VF                                                |
+    /// | 1             | Stack length                                            |
+    /// | 2 * 16        | Stack, unused entries are zero                          |
+    /// | 1             | Delay timer                                             |
+    /// | 1             | Sound timer                                             |
+    /// | 2             | Keypad, bit N set when key N is down                    |
+    /// | 8             | Random number generator state                           |
+    /// | 16            | SUPER-CHIP RPL user flags                               |
+    /// | 16            | XO-CHIP audio pattern                                   |
+    /// | 1             | XO-CHIP audio pitch                                     |
+    /// | 1             | Display mode: 0 low resolution, 1 high resolution       |
+    /// | 1             | Selected bitplanes                                      |
+    /// | 128 * 64      | Video memory, one byte per pixel, row by row, always    |
+    /// |               | the size of the high resolution screen                  |
+    /// | 4             | Memory size N, 4096 or 65536                            |
+    /// | N             | Memory                                                  |
+    pub fn save_state(&self) -> Vec<u8> {
+        let ram = self.memory.as_slice();
+        let mut out = Vec::with_capacity(128 + DISPLAY_SIZE + ram.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.push(self.quirks.to_bits());
+        out.push(match self.state {
+            CpuState::Running => 0,
+            CpuState::Step => 1,
+            CpuState::Paused => 2,
+            CpuState::Halted => 3,
+        });
+        out.extend_from_slice(&self.memory.pc.as_u16().to_le_bytes());
+        out.extend_from_slice(&self.memory.i.as_u16().to_le_bytes());
+        out.extend_from_slice(&self.v[..=0xF]);
+        let stack = self.stack.as_slice();
+        out.push(stack.len() as u8);
+        for i in 0..STACK_SIZE {
+            out.extend_from_slice(&stack.get(i).copied().unwrap_or(0).to_le_bytes());
+        }
+        out.push(self.delay_timer.value());
+        out.push(self.sound_timer.value());
+        let keys = (0..KEYS).filter(|&k| self.keypad.key_down(k)).fold(0u16, |a, k| a | 1 << k);
+        out.extend_from_slice(&keys.to_le_bytes());
+        out.extend_from_slice(&self.randgen.state().to_le_bytes());
+        out.extend_from_slice(&self.flags);
+        out.extend_from_slice(&self.audio.pattern);
+        out.push(self.audio.pitch);
+        out.push(u8::from(self.display.is_hires()));
+        out.push(self.display.planes());
+        out.extend_from_slice(self.display.vram());
+        out.extend_from_slice(&(ram.len() as u32).to_le_bytes());
+        out.extend_from_slice(ram);
+        out
+    }
+
+    /// Restores a state made by [`Cpu::save_state`].
+    ///
+    /// On error, the `Cpu` is left untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader::new(state);
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        match r.u16_le()? {
+            VERSION => {}
+            v => return Err(StateError::UnsupportedVersion(v)),
+        }
+        let quirks = Quirks::from_bits(r.u8()?);
+        let cpu_state = match r.u8()? {
+            0 => CpuState::Running,
+            1 => CpuState::Step,
+            2 => CpuState::Paused,
+            3 => CpuState::Halted,
+            _ => return Err(StateError::Invalid("cpu state")),
+        };
+        let pc = r.u16_le()?;
+        let i = r.u16_le()?;
+        let v = r.take(REGISTERS)?;
+        let stack_len = usize::from(r.u8()?);
+        if stack_len > STACK_SIZE {
+            return Err(StateError::Invalid("stack length"));
+        }
+        let mut stack = [0; STACK_SIZE];
+        for item in stack.iter_mut() {
+            *item = r.u16_le()?;
+        }
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let keys = r.u16_le()?;
+        let seed = r.u64_le()?;
+        let flags = r.take(FLAGS_SIZE)?;
+        let pattern = r.take(self.audio.pattern.len())?;
+        let pitch = r.u8()?;
+        let hires = match r.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(StateError::Invalid("display mode")),
+        };
+        let planes = r.u8()?;
+        let vram = r.take(DISPLAY_SIZE)?;
+        let ram_size = r.u32_le()? as usize;
+        let expected = if quirks.extended_memory { EXTENDED_RAM_SIZE } else { RAM_SIZE.into() };
+        if ram_size != expected {
+            return Err(StateError::Invalid("memory size"));
+        }
+        let ram = r.take(ram_size)?;
+        if !r.bytes.is_empty() {
+            return Err(StateError::Invalid("trailing bytes"));
+        }
+
+        // Everything has been read, nothing can fail from here.
+        self.set_quirks(quirks);
+        self.state = cpu_state;
+        self.memory.restore(pc, i, ram);
+        self.v[..=0xF].copy_from_slice(v);
+        self.stack.reset();
+        for &addr in &stack[..stack_len] {
+            let _ = self.stack.push(addr);
+        }
+        self.delay_timer.store(delay_timer);
+        self.sound_timer.set(sound_timer);
+        for k in 0..KEYS {
+            let kc = KeyCode::try_from(k).unwrap();
+            self.keypad[kc] = keys & (1 << k) != 0;
+        }
+        self.randgen.set_state(seed);
+        self.flags.copy_from_slice(flags);
+        self.audio.pattern.copy_from_slice(pattern);
+        self.audio.pitch = pitch;
+        self.display.restore(vram, hires, planes);
+        self.should_draw = true;
+        Ok(())
+    }
+}
+
+impl From<ReadError> for StateError {
+    fn from(e: ReadError) -> Self {
+        match e {
+            ReadError::Truncated => StateError::Truncated,
+            ReadError::Invalid(what) => StateError::Invalid(what),
+        }
+    }
+}
+
+impl Error for StateError {}
+impl fmt::Debug for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => f.write_str("not a savestate: bad magic header"),
+            StateError::UnsupportedVersion(v) => {
+                write!(f, "unsupported savestate version: {} != {}", v, VERSION)
+            }
+            StateError::Truncated => f.write_str("savestate is truncated"),
+            StateError::Invalid(what) => write!(f, "invalid savestate: bad {}", what),
+        }
+    }
+}
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
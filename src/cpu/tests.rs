@@ -0,0 +1,318 @@
+use super::*;
+use crate::quirks::Platform;
+
+fn run(platform: Platform, rom: &[u8], cycles: usize) -> Cpu {
+    let mut cpu = Cpu::new(platform.quirks());
+    cpu.load_game(rom).unwrap();
+    for _ in 0..cycles {
+        cpu.execute_cycle().unwrap();
+    }
+    cpu
+}
+
+#[test]
+fn test_shift_quirk() {
+    // LD V0, 0x1; LD V1, 0x4; SHR V0 {, V1}
+    let rom = [0x60, 0x01, 0x61, 0x04, 0x80, 0x16];
+    let cpu = run(Platform::Cosmac, &rom, 3);
+    assert_eq!(cpu.v[0], 0x2);
+    assert_eq!(cpu.v[0xF], 0);
+
+    let cpu = run(Platform::Chip48, &rom, 3);
+    assert_eq!(cpu.v[0], 0x0);
+    assert_eq!(cpu.v[0xF], 1);
+}
+
+#[test]
+fn test_load_store_quirk() {
+    // LD I, 0x300; LD [I], V2
+    let rom = [0xA3, 0x00, 0xF2, 0x55];
+    let cpu = run(Platform::Cosmac, &rom, 2);
+    assert_eq!(cpu.memory.i.as_u16(), 0x303);
+
+    let cpu = run(Platform::SuperChip, &rom, 2);
+    assert_eq!(cpu.memory.i.as_u16(), 0x300);
+}
+
+#[test]
+fn test_jump_quirk() {
+    // LD V0, 0x2; LD V3, 0x4; JP V0, 0x300
+    let rom = [0x60, 0x02, 0x63, 0x04, 0xB3, 0x00];
+    let cpu = run(Platform::Cosmac, &rom, 3);
+    assert_eq!(cpu.memory.pc.as_u16(), 0x302);
+
+    let cpu = run(Platform::Chip48, &rom, 3);
+    assert_eq!(cpu.memory.pc.as_u16(), 0x304);
+}
+
+#[test]
+fn test_add_i_quirk() {
+    // LD I, 0xFFF; LD V0, 0x1; ADD I, V0
+    let rom = [0xAF, 0xFF, 0x60, 0x01, 0xF0, 0x1E];
+    let cpu = run(Platform::Amiga, &rom, 3);
+    assert_eq!(cpu.v[0xF], 1);
+
+    let cpu = run(Platform::Chip48, &rom, 3);
+    assert_eq!(cpu.v[0xF], 0);
+
+    // 0x1000 is still addressable with 64 KiB, 0x10000 isn't.
+    let quirks = Quirks { extended_memory: true, ..Platform::Amiga.quirks() };
+    let mut cpu = Cpu::new(quirks);
+    cpu.load_game(&rom).unwrap();
+    for _ in 0..3 {
+        cpu.execute_cycle().unwrap();
+    }
+    assert_eq!((cpu.i(), cpu.v[0xF]), (0x1000, 0));
+    cpu.set_i(0xFFFF);
+    cpu.memory.pc.store(0x204);
+    cpu.execute_cycle().unwrap();
+    assert_eq!((cpu.i(), cpu.v[0xF]), (0, 1));
+}
+
+#[test]
+fn test_superchip_flags_and_resolution() {
+    // HIGH; LD V0, 0x12; LD V1, 0x34; LD R, V1; LD V0, 0x0; LD V1, 0x0; LD V1, R; EXIT
+    let rom = [
+        0x00, 0xFF, 0x60, 0x12, 0x61, 0x34, 0xF1, 0x75, 0x60, 0x00, 0x61, 0x00, 0xF1, 0x85, 0x00,
+        0xFD,
+    ];
+    let cpu = run(Platform::SuperChip, &rom, 8);
+    assert_eq!(cpu.resolution(), (128, 64));
+    assert_eq!(&cpu.v[..=1], &[0x12, 0x34]);
+    assert!(matches!(cpu.state, CpuState::Halted));
+}
+
+#[test]
+fn test_xochip_long_load_and_skip() {
+    // LD V0, 0x1; SE V0, 0x1; LD I, LONG 0xABCD; LD V1, 0x2
+    let rom = [0x60, 0x01, 0x30, 0x01, 0xF0, 0x00, 0xAB, 0xCD, 0x61, 0x02];
+    let cpu = run(Platform::XoChip, &rom, 3);
+    assert_ne!(cpu.memory.i.as_u16(), 0xABCD);
+    assert_eq!(cpu.v[1], 0x2);
+
+    // LD I, LONG 0xABCD; LD V1, 0x2
+    let cpu = run(Platform::XoChip, &rom[4..], 2);
+    assert_eq!(cpu.memory.i.as_u16(), 0xABCD);
+    assert_eq!(cpu.v[1], 0x2);
+}
+
+#[test]
+fn test_xochip_register_ranges() {
+    // LD V1, 0x11; LD V2, 0x22; LD V3, 0x33; LD I, 0x300; LD [I], V3-V1; LD V0-V2, [I]
+    let rom = [0x61, 0x11, 0x62, 0x22, 0x63, 0x33, 0xA3, 0x00, 0x53, 0x12, 0x50, 0x23];
+    let cpu = run(Platform::XoChip, &rom, 6);
+    assert_eq!(cpu.memory()[0x300..0x303], [0x33, 0x22, 0x11]);
+    assert_eq!(&cpu.v[..=3], &[0x33, 0x22, 0x11, 0x33]);
+    assert_eq!(cpu.memory.i.as_u16(), 0x300);
+}
+
+#[test]
+fn test_invalid_opcode() {
+    // LD V0, 0x1; 0xFFFF
+    let rom = [0x60, 0x01, 0xFF, 0xFF];
+    let mut cpu = run(Platform::Chip48, &rom, 1);
+    let e = cpu.execute_cycle().unwrap_err();
+    assert!(matches!(e, CpuError::InvalidOpcode { pc: 0x202, opcode: 0xFFFF }));
+    // The faulting instruction is still the next one to run.
+    assert_eq!(cpu.pc(), 0x202);
+    assert_eq!(cpu.registers()[0], 0x1);
+    // SYS addr
+    cpu.load_game(&[0x60, 0x01, 0x01, 0x23]).unwrap();
+    assert!(matches!(cpu.execute_cycle(), Err(CpuError::InvalidOpcode { .. })));
+}
+
+#[test]
+fn test_stack_errors() {
+    // RET
+    let mut cpu = run(Platform::Chip48, &[0x00, 0xEE], 0);
+    assert!(matches!(cpu.execute_cycle(), Err(CpuError::StackUnderflow)));
+    assert_eq!(cpu.pc(), 0x200);
+
+    // CALL 0x200
+    let mut cpu = run(Platform::Chip48, &[0x22, 0x00], 16);
+    assert_eq!(cpu.stack().len(), 16);
+    assert!(matches!(cpu.execute_cycle(), Err(CpuError::StackOverflow)));
+    assert_eq!(cpu.stack().len(), 16);
+}
+
+#[test]
+fn test_out_of_range() {
+    // JP 0x100
+    let mut cpu = run(Platform::Chip48, &[0x11, 0x00], 0);
+    assert!(matches!(cpu.execute_cycle(), Err(CpuError::PcOutOfRange { addr: 0x100 })));
+    assert_eq!(cpu.pc(), 0x200);
+
+    // LD I, 0xFFE; LD [I], V3
+    let mut cpu = run(Platform::Chip48, &[0xAF, 0xFE, 0xF3, 0x55], 1);
+    let e = cpu.execute_cycle().unwrap_err();
+    assert!(matches!(e, CpuError::MemoryOutOfBounds { addr: 0xFFE, len: 4 }));
+    assert_eq!(cpu.memory()[0xFFE..], [0, 0]);
+}
+
+#[test]
+fn test_savestate_roundtrip() {
+    // RND V0, 0xFF; LD I, 0x300; LD [I], V0; CALL 0x200
+    let rom = [0xC0, 0xFF, 0xA3, 0x00, 0xF0, 0x55, 0x22, 0x00];
+    let mut cpu = run(Platform::XoChip, &rom, 5);
+    cpu.set_key_state(KeyCode::K7, true);
+    let state = cpu.save_state();
+    assert_eq!(&state[..4], b"C8SS");
+
+    for _ in 0..8 {
+        cpu.execute_cycle().unwrap();
+    }
+    let expected = (cpu.registers().to_vec(), cpu.stack().to_vec(), cpu.memory()[0x300]);
+
+    let mut other = Cpu::new(Platform::Cosmac.quirks());
+    other.load_state(&state).unwrap();
+    assert_eq!(other.quirks(), Platform::XoChip.quirks());
+    assert_eq!(other.save_state(), state);
+    assert!(other.keypad.key_down(7));
+    for _ in 0..8 {
+        other.execute_cycle().unwrap();
+    }
+    let got = (other.registers().to_vec(), other.stack().to_vec(), other.memory()[0x300]);
+    assert_eq!(got, expected);
+}
+
+#[test]
+fn test_bad_savestate() {
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    let mut state = cpu.save_state();
+    assert!(matches!(cpu.load_state(b"nope"), Err(StateError::BadMagic)));
+    assert!(matches!(cpu.load_state(&state[..100]), Err(StateError::Truncated)));
+    state[4] = 0xFF;
+    assert!(matches!(cpu.load_state(&state), Err(StateError::UnsupportedVersion(0xFF))));
+}
+
+#[test]
+fn test_seeded_random() {
+    // RND V0, 0xFF; RND V1, 0xFF; RND V2, 0xFF
+    let rom = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF];
+    let run_seeded = |seed| {
+        let mut cpu = Cpu::with_seed(Platform::Chip48.quirks(), seed);
+        cpu.load_game(&rom).unwrap();
+        for _ in 0..3 {
+            cpu.execute_cycle().unwrap();
+        }
+        cpu.registers()[..3].to_vec()
+    };
+    assert_eq!(run_seeded(42), run_seeded(42));
+    assert_ne!(run_seeded(42), run_seeded(43));
+}
+
+#[test]
+fn test_scripted_random() {
+    struct Script(std::vec::IntoIter<u8>);
+    impl RandomSource for Script {
+        fn generate(&mut self) -> u8 {
+            self.0.next().unwrap()
+        }
+    }
+
+    // RND V0, 0xFF; RND V1, 0x0F
+    let rom = [0xC0, 0xFF, 0xC1, 0x0F];
+    let script = Script(vec![0xAB, 0xCD].into_iter());
+    let mut cpu = Cpu::with_random(Platform::Chip48.quirks(), Box::new(script));
+    cpu.load_game(&rom).unwrap();
+    cpu.execute_cycle().unwrap();
+    cpu.execute_cycle().unwrap();
+    assert_eq!(cpu.registers()[..2], [0xAB, 0x0D]);
+}
+
+#[test]
+fn test_frame_timers() {
+    // LD V0, 0x3; LD DT, V0; LD ST, V0; LD V1, DT; JP 0x206
+    let rom = [0x60, 0x03, 0xF0, 0x15, 0xF0, 0x18, 0xF1, 0x07, 0x12, 0x06];
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&rom).unwrap();
+    cpu.run_frame(4).unwrap();
+    assert_eq!(cpu.registers()[1], 3);
+    assert_eq!((cpu.delay_timer.value(), cpu.sound_timer.value()), (2, 2));
+
+    // Instructions alone don't count the timers down.
+    for _ in 0..100 {
+        cpu.execute_cycle().unwrap();
+    }
+    assert_eq!((cpu.delay_timer.value(), cpu.sound_timer.value()), (2, 2));
+    assert!(cpu.is_sound_active());
+    cpu.run_frame(10).unwrap();
+    cpu.run_frame(10).unwrap();
+    cpu.run_frame(10).unwrap();
+    assert_eq!((cpu.delay_timer.value(), cpu.sound_timer.value()), (0, 0));
+    assert!(!cpu.is_sound_active());
+}
+
+#[test]
+fn test_frame_stops_on_key_wait() {
+    // LD V0, K; ADD V1, 0x1
+    let rom = [0xF0, 0x0A, 0x71, 0x01];
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&rom).unwrap();
+    cpu.run_frame(9).unwrap();
+    assert!(matches!(cpu.state, CpuState::Paused));
+    assert_eq!(cpu.pc(), 0x202);
+    assert_eq!(cpu.registers()[1], 0);
+}
+
+#[test]
+fn test_step_state() {
+    // ADD V0, 0x1; JP 0x200
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    cpu.state = CpuState::Step;
+    cpu.execute_cycle().unwrap();
+    cpu.execute_cycle().unwrap();
+    assert!(matches!(cpu.state, CpuState::Paused));
+    assert_eq!((cpu.pc(), cpu.registers()[0]), (0x202, 1));
+}
+
+#[test]
+fn test_code_write() {
+    // LD I, 0x204; LD V0, 0x72; LD V1, 0x5; LD [I], V1; JP 0x204
+    // The first LD [I], V1 turns LD V1, 0x5 into ADD V2, 0x5.
+    let rom = [0xA2, 0x04, 0x60, 0x72, 0x61, 0x05, 0xF1, 0x55, 0x12, 0x04];
+    let mut cpu = run(Platform::Chip48, &rom, 3);
+    assert_eq!(cpu.code_write(), None);
+    cpu.execute_cycle().unwrap();
+    assert_eq!(cpu.code_write(), Some(CodeWrite { pc: 0x206, addr: 0x204, len: 2 }));
+    cpu.execute_cycle().unwrap();
+    assert_eq!(cpu.code_write(), None);
+    cpu.execute_cycle().unwrap();
+    assert_eq!(cpu.v[2], 0x5);
+    // Written over again once it ran again.
+    cpu.execute_cycle().unwrap();
+    assert_eq!(cpu.code_write(), Some(CodeWrite { pc: 0x206, addr: 0x204, len: 2 }));
+
+    // LD I, 0x200; LD B, V0: the 3 digits overwrite the first instruction
+    // and half of the second.
+    let cpu = run(Platform::Chip48, &[0xA2, 0x00, 0xF0, 0x33], 2);
+    assert_eq!(cpu.code_write(), Some(CodeWrite { pc: 0x202, addr: 0x200, len: 3 }));
+    // LD I, 0x300; LD B, V0
+    let mut cpu = run(Platform::Chip48, &[0xA3, 0x00, 0xF0, 0x33], 2);
+    assert_eq!(cpu.code_write(), None);
+    cpu.reset();
+    assert_eq!(cpu.code_write(), None);
+}
+
+#[test]
+fn test_fault_leaves_no_marks() {
+    // LD I, 0x202; LD V0, 0x1; LD [I], V0; RET
+    let rom = [0xA2, 0x02, 0x60, 0x01, 0xF0, 0x55, 0x00, 0xEE];
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&rom).unwrap();
+    cpu.set_coverage(true);
+    for _ in 0..3 {
+        cpu.execute_cycle().unwrap();
+    }
+    assert!(cpu.code_write().is_some());
+    assert!(matches!(cpu.execute_cycle(), Err(CpuError::StackUnderflow)));
+    assert_eq!(cpu.code_write(), None);
+    assert_eq!(cpu.pc(), 0x206);
+    assert!(!cpu.coverage().unwrap().executed(0x206));
+    // Nor does it count as code to overwrite.
+    cpu.set_i(0x206);
+    cpu.set_pc(0x204);
+    cpu.execute_cycle().unwrap();
+    assert_eq!(cpu.code_write(), None);
+}
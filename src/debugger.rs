@@ -0,0 +1,331 @@
+#[cfg(test)]
+mod tests;
+
+use std::str::FromStr;
+
+use crate::cpu::{CodeWrite, Cpu, CpuError, CpuState};
+use crate::opcode::OpcodeKind;
+
+/// Breakpoints and watchpoints around a [`Cpu`].
+///
+/// The program runs through [`Debugger::run`] and the stepping methods
+/// instead of `Cpu::execute_cycle`, which stop and tell why when a
+/// breakpoint or a watchpoint is hit.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    memory_watches: Vec<MemoryWatch>,
+    // Bit N set when VN is watched.
+    register_watches: u16,
+    watch_code: bool,
+    // Where the last run stopped on a breakpoint, which must not
+    // stop the next run before it even starts.
+    resume_pc: Option<u16>,
+    // Whether the screen changed during the last `run_frame`.
+    drawn: bool,
+}
+
+/// Why the program was stopped.
+pub enum StopReason {
+    /// The program counter reached a breakpoint, whose instruction hasn't run yet.
+    Breakpoint { pc: u16 },
+    /// The last instruction accessed watched memory, starting at `addr`.
+    Watchpoint { addr: u16, write: bool },
+    /// The last instruction changed a watched register.
+    RegisterChanged { x: u8, old: u8, new: u8 },
+    /// The last instruction wrote over code, see [`CodeWrite`].
+    CodeWritten { addr: u16, len: u16 },
+    /// A step, step over or step out is done.
+    Step,
+}
+
+/// Which memory accesses a watchpoint stops on.
+#[derive(Clone, Copy)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Condition of a breakpoint, like `V3 == 0x10` or `V3==0x10`.
+///
+/// The left-hand side is a register, `I` or `PC`; the right-hand side is a
+/// decimal or `0x` prefixed hexadecimal number. The comparisons are `==`,
+/// `!=`, `<`, `<=`, `>` and `>=`, with or without spaces around them.
+pub struct Condition {
+    lhs: Operand,
+    cmp: Comparison,
+    rhs: u16,
+}
+
+enum Operand {
+    V(u8),
+    I,
+    Pc,
+}
+
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+struct Breakpoint {
+    pc: u16,
+    condition: Option<Condition>,
+}
+
+struct MemoryWatch {
+    start: usize,
+    end: usize,
+    access: Access,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            memory_watches: Vec::new(),
+            register_watches: 0,
+            watch_code: false,
+            resume_pc: None,
+            drawn: false,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.push(Breakpoint { pc, condition: None });
+    }
+
+    /// Stops at `pc` only when `condition` holds.
+    pub fn add_conditional_breakpoint(&mut self, pc: u16, condition: Condition) {
+        self.breakpoints.push(Breakpoint { pc, condition: Some(condition) });
+    }
+
+    /// Removes all the breakpoints at `pc`.
+    pub fn remove_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.retain(|bp| bp.pc != pc);
+    }
+
+    /// Stops after an instruction accesses memory from `addr` to `addr + len`.
+    pub fn watch_memory(&mut self, addr: u16, len: u16, access: Access) {
+        let start = usize::from(addr);
+        self.memory_watches.push(MemoryWatch { start, end: start + usize::from(len), access });
+    }
+
+    /// Removes the memory watchpoints starting at `addr`.
+    pub fn unwatch_memory(&mut self, addr: u16) {
+        self.memory_watches.retain(|w| w.start != usize::from(addr));
+    }
+
+    /// Stops after an instruction changes `Vx`.
+    pub fn watch_register(&mut self, x: u8) {
+        self.register_watches |= 1 << (x & 0xF);
+    }
+
+    pub fn unwatch_register(&mut self, x: u8) {
+        self.register_watches &= !(1 << (x & 0xF));
+    }
+
+    /// Stops after an instruction writes over code that already ran.
+    pub fn watch_code_writes(&mut self) {
+        self.watch_code = true;
+    }
+
+    pub fn unwatch_code_writes(&mut self) {
+        self.watch_code = false;
+    }
+
+    /// Removes all the breakpoints and watchpoints.
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.memory_watches.clear();
+        self.register_watches = 0;
+        self.watch_code = false;
+    }
+
+    /// Runs up to `cycles` instructions, stopping early on a breakpoint or a
+    /// watchpoint. Returns `None` when all the cycles ran, or when the `Cpu`
+    /// isn't running anymore.
+    pub fn run(&mut self, cpu: &mut Cpu, cycles: u32) -> Result<Option<StopReason>, CpuError> {
+        self.run_until(cpu, cycles, |_| false)
+    }
+
+    /// Same as `Cpu::run_frame`, but the timers don't tick when the
+    /// frame stops early. [`Debugger::drawn`] then tells whether the
+    /// screen changed.
+    pub fn run_frame(
+        &mut self,
+        cpu: &mut Cpu,
+        cycles: u32,
+    ) -> Result<Option<StopReason>, CpuError> {
+        self.drawn = false;
+        let stop = self.run(cpu, cycles)?;
+        if stop.is_none() {
+            cpu.tick_timers();
+        }
+        Ok(stop)
+    }
+
+    /// Whether the screen changed during the last [`Debugger::run_frame`],
+    /// up to where it stopped.
+    pub fn drawn(&self) -> bool {
+        self.drawn
+    }
+
+    /// Runs a single instruction, even one waiting at a breakpoint.
+    pub fn step(&mut self, cpu: &mut Cpu) -> Result<Option<StopReason>, CpuError> {
+        self.resume_pc = Some(cpu.pc());
+        self.run_until(cpu, 1, |_| true)
+    }
+
+    /// Same as [`Debugger::step`], but a subroutine call runs until it
+    /// returns, for up to `cycles` instructions.
+    pub fn step_over(
+        &mut self,
+        cpu: &mut Cpu,
+        cycles: u32,
+    ) -> Result<Option<StopReason>, CpuError> {
+        if let Some(OpcodeKind::Call { .. }) = cpu.next_instruction() {
+            let (depth, next) = (cpu.stack().len(), cpu.pc().wrapping_add(2));
+            self.resume_pc = Some(cpu.pc());
+            self.run_until(cpu, cycles, |cpu| cpu.stack().len() == depth && cpu.pc() == next)
+        } else {
+            self.step(cpu)
+        }
+    }
+
+    /// Runs until the current subroutine returns, for up to `cycles`
+    /// instructions.
+    pub fn step_out(&mut self, cpu: &mut Cpu, cycles: u32) -> Result<Option<StopReason>, CpuError> {
+        let depth = cpu.stack().len();
+        self.resume_pc = Some(cpu.pc());
+        self.run_until(cpu, cycles, |cpu| cpu.stack().len() < depth)
+    }
+
+    fn run_until<F>(
+        &mut self,
+        cpu: &mut Cpu,
+        cycles: u32,
+        done: F,
+    ) -> Result<Option<StopReason>, CpuError>
+    where
+        F: Fn(&Cpu) -> bool,
+    {
+        for _ in 0..cycles {
+            if !matches!(cpu.state, CpuState::Running | CpuState::Step) {
+                break;
+            }
+            let pc = cpu.pc();
+            if self.resume_pc.take() != Some(pc) && self.breakpoint_hit(cpu) {
+                self.resume_pc = Some(pc);
+                return Ok(Some(StopReason::Breakpoint { pc }));
+            }
+            if let Some(stop) = self.execute(cpu)? {
+                return Ok(Some(stop));
+            }
+            if done(cpu) {
+                return Ok(Some(StopReason::Step));
+            }
+        }
+        Ok(None)
+    }
+
+    fn breakpoint_hit(&self, cpu: &Cpu) -> bool {
+        let pc = cpu.pc();
+        self.breakpoints
+            .iter()
+            .filter(|bp| bp.pc == pc)
+            .any(|bp| bp.condition.as_ref().is_none_or(|c| c.matches(cpu)))
+    }
+
+    // Runs one instruction, and checks the watchpoints against what it did.
+    fn execute(&mut self, cpu: &mut Cpu) -> Result<Option<StopReason>, CpuError> {
+        let access = cpu.next_memory_access();
+        let mut before = [0; 16];
+        before.copy_from_slice(&cpu.registers()[..16]);
+        self.drawn |= cpu.execute_cycle()?;
+
+        if let Some(access) = access {
+            for w in &self.memory_watches {
+                let watched = match w.access {
+                    Access::Read => !access.write,
+                    Access::Write => access.write,
+                    Access::ReadWrite => true,
+                };
+                if watched && access.range.start < w.end && w.start < access.range.end {
+                    let addr = access.range.start.max(w.start) as u16;
+                    return Ok(Some(StopReason::Watchpoint { addr, write: access.write }));
+                }
+            }
+        }
+        if let (true, Some(CodeWrite { addr, len, .. })) = (self.watch_code, cpu.code_write()) {
+            return Ok(Some(StopReason::CodeWritten { addr, len }));
+        }
+        for (x, (&old, &new)) in before.iter().zip(cpu.registers()).enumerate() {
+            if self.register_watches & (1 << x) != 0 && old != new {
+                return Ok(Some(StopReason::RegisterChanged { x: x as u8, old, new }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, cpu: &Cpu) -> bool {
+        let lhs = match self.lhs {
+            Operand::V(x) => u16::from(cpu.registers()[usize::from(x)]),
+            Operand::I => cpu.i(),
+            Operand::Pc => cpu.pc(),
+        };
+        match self.cmp {
+            Comparison::Eq => lhs == self.rhs,
+            Comparison::Ne => lhs != self.rhs,
+            Comparison::Lt => lhs < self.rhs,
+            Comparison::Le => lhs <= self.rhs,
+            Comparison::Gt => lhs > self.rhs,
+            Comparison::Ge => lhs >= self.rhs,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_cmp = |c: char| "=!<>".contains(c);
+        let start = s.find(is_cmp).ok_or(())?;
+        let end = s[start..].find(|c| !is_cmp(c)).map_or(s.len(), |n| start + n);
+        let (lhs, cmp, rhs) = (s[..start].trim(), &s[start..end], s[end..].trim());
+        let lhs = match lhs.to_ascii_uppercase().as_str() {
+            "I" => Operand::I,
+            "PC" => Operand::Pc,
+            v => match v.strip_prefix('V') {
+                Some(x) if x.len() == 1 => Operand::V(u8::from_str_radix(x, 16).map_err(|_| ())?),
+                _ => return Err(()),
+            },
+        };
+        let cmp = match cmp {
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            _ => return Err(()),
+        };
+        let rhs = match rhs.strip_prefix("0x").or_else(|| rhs.strip_prefix("0X")) {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => rhs.parse(),
+        };
+        Ok(Condition { lhs, cmp, rhs: rhs.map_err(|_| ())? })
+    }
+}
@@ -0,0 +1,113 @@
+use super::*;
+use crate::quirks::Platform;
+
+fn cpu(rom: &[u8]) -> Cpu {
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(rom).unwrap();
+    cpu
+}
+
+// ADD V0, 0x1; JP 0x200
+const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+#[test]
+fn test_breakpoint() {
+    let mut cpu = cpu(&COUNTER);
+    let mut debugger = Debugger::new();
+    debugger.add_breakpoint(0x202);
+    let stop = debugger.run(&mut cpu, 100).unwrap();
+    assert!(matches!(stop, Some(StopReason::Breakpoint { pc: 0x202 })));
+    assert_eq!(cpu.registers()[0], 1);
+
+    // Resuming doesn't stop on the same breakpoint right away.
+    let stop = debugger.run(&mut cpu, 100).unwrap();
+    assert!(matches!(stop, Some(StopReason::Breakpoint { pc: 0x202 })));
+    assert_eq!(cpu.registers()[0], 2);
+
+    debugger.remove_breakpoint(0x202);
+    assert!(debugger.run(&mut cpu, 100).unwrap().is_none());
+    assert_eq!(cpu.registers()[0], 52);
+}
+
+#[test]
+fn test_drawn() {
+    // CLS; JP 0x202
+    let mut cpu = cpu(&[0x00, 0xE0, 0x12, 0x02]);
+    let mut debugger = Debugger::new();
+    assert!(debugger.run_frame(&mut cpu, 10).unwrap().is_none());
+    assert!(debugger.drawn());
+    debugger.run_frame(&mut cpu, 10).unwrap();
+    assert!(!debugger.drawn());
+}
+
+#[test]
+fn test_conditional_breakpoint() {
+    let mut cpu = cpu(&COUNTER);
+    let mut debugger = Debugger::new();
+    debugger.add_conditional_breakpoint(0x200, "V0 == 0x10".parse().unwrap());
+    let stop = debugger.run(&mut cpu, 100).unwrap();
+    assert!(matches!(stop, Some(StopReason::Breakpoint { pc: 0x200 })));
+    assert_eq!(cpu.registers()[0], 0x10);
+
+    assert!("V0 = 1".parse::<Condition>().is_err());
+    assert!("VG == 1".parse::<Condition>().is_err());
+    assert!("I >= 0x300 1".parse::<Condition>().is_err());
+    assert!("pc != 512".parse::<Condition>().is_ok());
+    assert!("V3==0x10".parse::<Condition>().is_ok());
+    assert!("I<0x300".parse::<Condition>().is_ok());
+    assert!("V0 =! 1".parse::<Condition>().is_err());
+    assert!("V0 1".parse::<Condition>().is_err());
+    assert!("== 1".parse::<Condition>().is_err());
+}
+
+#[test]
+fn test_watchpoints() {
+    // LD I, 0x300; LD V1, 0x2; LD [I], V1; LD V2, [I]
+    let rom = [0xA3, 0x00, 0x61, 0x02, 0xF1, 0x55, 0xF2, 0x65];
+    let mut cpu = cpu(&rom);
+    let mut debugger = Debugger::new();
+    debugger.watch_memory(0x301, 1, Access::Read);
+    debugger.watch_register(1);
+
+    let stop = debugger.run(&mut cpu, 10).unwrap();
+    assert!(matches!(stop, Some(StopReason::RegisterChanged { x: 1, old: 0, new: 2 })));
+    // The write doesn't match a read watchpoint.
+    let stop = debugger.run(&mut cpu, 10).unwrap();
+    assert!(matches!(stop, Some(StopReason::Watchpoint { addr: 0x301, write: false })));
+    assert_eq!(cpu.pc(), 0x208);
+}
+
+#[test]
+fn test_step_over_and_out() {
+    // CALL 0x206; JP 0x202; LD V0, 0x1 (unused); ADD V1, 0x1; ADD V1, 0x1; RET
+    let rom = [0x22, 0x06, 0x12, 0x02, 0x60, 0x01, 0x71, 0x01, 0x71, 0x01, 0x00, 0xEE];
+    let mut cpu = cpu(&rom);
+    let mut debugger = Debugger::new();
+    let stop = debugger.step_over(&mut cpu, 100).unwrap();
+    assert!(matches!(stop, Some(StopReason::Step)));
+    assert_eq!((cpu.pc(), cpu.registers()[1]), (0x202, 2));
+
+    let mut cpu = self::cpu(&rom);
+    debugger.step(&mut cpu).unwrap();
+    debugger.step(&mut cpu).unwrap();
+    assert_eq!((cpu.pc(), cpu.stack().len()), (0x208, 1));
+    let stop = debugger.step_out(&mut cpu, 100).unwrap();
+    assert!(matches!(stop, Some(StopReason::Step)));
+    assert_eq!((cpu.pc(), cpu.registers()[1]), (0x202, 2));
+}
+
+#[test]
+fn test_code_write_watch() {
+    // LD I, 0x202; LD V0, 0x1; LD [I], V0; JP 0x204
+    let rom = [0xA2, 0x02, 0x60, 0x01, 0xF0, 0x55, 0x12, 0x04];
+    let mut cpu = cpu(&rom);
+    let mut debugger = Debugger::new();
+    debugger.watch_code_writes();
+    let stop = debugger.run(&mut cpu, 10).unwrap();
+    assert!(matches!(stop, Some(StopReason::CodeWritten { addr: 0x202, len: 1 })));
+    assert_eq!(cpu.pc(), 0x206);
+
+    debugger.unwatch_code_writes();
+    let mut cpu = self::cpu(&rom);
+    assert!(debugger.run(&mut cpu, 10).unwrap().is_none());
+}
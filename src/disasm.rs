@@ -0,0 +1,70 @@
+mod flow;
+#[cfg(test)]
+mod tests;
+
+use crate::opcode::{Opcode, OpcodeKind, LONG_INSTRUCTION_PREFIX};
+
+pub use flow::{analyse, Analysis};
+
+/// One line of a disassembly listing.
+pub struct Instruction {
+    pub addr: u16,
+    /// First word, or the lone byte at the end of odd-sized data.
+    pub word: u16,
+    /// Size in bytes: 4 for `F000 NNNN`, 1 for a lone byte, 2 otherwise.
+    pub size: u16,
+    /// `None` for data that isn't a known instruction.
+    pub kind: Option<OpcodeKind>,
+    /// The mnemonic, or `DW`/`DB` followed by the data.
+    pub text: String,
+}
+
+/// Decodes `bytes` loaded at `base_addr`, word after word.
+///
+/// Words that aren't instructions, like sprites, come out as data. Nothing
+/// tells code and data apart, so data may also look like instructions.
+pub fn disassemble(bytes: &[u8], base_addr: u16) -> impl Iterator<Item = Instruction> + '_ {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let rest = bytes.get(offset..).filter(|rest| !rest.is_empty())?;
+        let addr = base_addr.wrapping_add(offset as u16);
+        let instruction = match *rest {
+            [byte] => Instruction {
+                addr,
+                word: u16::from(byte),
+                size: 1,
+                kind: None,
+                text: format!("DB {:#04X}", byte),
+            },
+            [hi, lo, ref tail @ ..] => {
+                let word = u16::from_be_bytes([hi, lo]);
+                let opcode = match tail {
+                    [a, b, ..] if word == LONG_INSTRUCTION_PREFIX => {
+                        Opcode::with_operand(word, u16::from_be_bytes([*a, *b]))
+                    }
+                    _ => Opcode::new(word),
+                };
+                match opcode.decode() {
+                    // A `F000` without its operand is data.
+                    Some(kind) if rest.len() >= usize::from(opcode.size()) => Instruction {
+                        addr,
+                        word,
+                        size: opcode.size(),
+                        kind: Some(kind),
+                        text: format!("{:?}", kind),
+                    },
+                    _ => Instruction {
+                        addr,
+                        word,
+                        size: 2,
+                        kind: None,
+                        text: format!("DW {:#06X}", word),
+                    },
+                }
+            }
+            [] => return None,
+        };
+        offset += usize::from(instruction.size);
+        Some(instruction)
+    })
+}
@@ -0,0 +1,268 @@
+//! Recursive-descent disassembly: only what the program can reach is code.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use super::{disassemble, Instruction};
+use crate::opcode::OpcodeKind;
+
+/// Code and data of a ROM, told apart by following the control flow from
+/// its first instruction.
+///
+/// Jumps, calls, skips and returns are followed. `JP V0, addr` is followed
+/// to `addr` only, the offset being unknown. Addresses loaded into I are
+/// data, unless the flow reaches them. Bytes that are neither reached nor
+/// referenced are data too.
+pub struct Analysis {
+    base: u16,
+    bytes: Vec<u8>,
+    code: BTreeMap<u16, Instruction>,
+    // Bit set for every byte that belongs to an instruction.
+    is_code: Vec<bool>,
+    labels: BTreeMap<u16, Label>,
+    edges: BTreeSet<(u16, u16, Edge)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Data,
+    Jump,
+    Subroutine,
+}
+
+// The edges go from an instruction to the ones that may run after it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Edge {
+    Next,
+    Jump,
+    Call,
+    Skip,
+}
+
+/// Follows the control flow of `bytes`, loaded and starting at `base_addr`.
+///
+/// Addresses don't wrap around: bytes past 0xFFFF are left out.
+pub fn analyse(bytes: &[u8], base_addr: u16) -> Analysis {
+    let bytes = &bytes[..bytes.len().min(0x10000 - usize::from(base_addr))];
+    let mut analysis = Analysis {
+        base: base_addr,
+        bytes: bytes.to_vec(),
+        code: BTreeMap::new(),
+        is_code: vec![false; bytes.len()],
+        labels: BTreeMap::new(),
+        edges: BTreeSet::new(),
+    };
+    let mut todo = vec![base_addr];
+    let mut data_refs = Vec::new();
+    while let Some(addr) = todo.pop() {
+        if analysis.code.contains_key(&addr) {
+            continue;
+        }
+        let i = match analysis.decode(addr) {
+            Some(i) => i,
+            None => continue,
+        };
+        let next = addr.wrapping_add(i.size);
+        let mut successors = Vec::new();
+        match i.kind {
+            Some(OpcodeKind::JpAddr { addr }) | Some(OpcodeKind::JpVxAddr { addr, .. }) => {
+                successors.push((addr, Edge::Jump, Some(Label::Jump)));
+            }
+            Some(OpcodeKind::Call { addr }) => {
+                successors.push((addr, Edge::Call, Some(Label::Subroutine)));
+                successors.push((next, Edge::Next, None));
+            }
+            Some(OpcodeKind::Ret) | Some(OpcodeKind::Exit) => {}
+            Some(
+                OpcodeKind::SkipVxByte { .. }
+                | OpcodeKind::SkipVxVy { .. }
+                | OpcodeKind::SkipIfKey { .. },
+            ) => {
+                let skipped = analysis.decode(next).map_or(2, |i| i.size);
+                successors.push((next, Edge::Next, None));
+                successors.push((next.wrapping_add(skipped), Edge::Skip, Some(Label::Jump)));
+            }
+            Some(OpcodeKind::LoadI { addr }) | Some(OpcodeKind::LoadILong { addr }) => {
+                data_refs.push(addr);
+                successors.push((next, Edge::Next, None));
+            }
+            _ => successors.push((next, Edge::Next, None)),
+        }
+        for offset in 0..usize::from(i.size) {
+            analysis.is_code[usize::from(addr - base_addr) + offset] = true;
+        }
+        analysis.code.insert(addr, i);
+        for (target, edge, label) in successors {
+            if !analysis.contains(target) {
+                continue;
+            }
+            if let Some(label) = label {
+                analysis.add_label(target, label);
+            }
+            analysis.edges.insert((addr, target, edge));
+            todo.push(target);
+        }
+    }
+    for addr in data_refs {
+        if analysis.contains(addr) && !analysis.is_code_at(addr) {
+            analysis.add_label(addr, Label::Data);
+        }
+    }
+    analysis
+}
+
+impl Analysis {
+    /// Whether `addr` is part of an instruction.
+    pub fn is_code_at(&self, addr: u16) -> bool {
+        self.offset(addr).is_some_and(|offset| self.is_code[offset])
+    }
+
+    /// Name given to `addr`, if it is a target of a jump, a call or `LD I`.
+    pub fn label(&self, addr: u16) -> Option<String> {
+        let prefix = match self.labels.get(&addr)? {
+            Label::Data => "data",
+            Label::Jump => "L",
+            Label::Subroutine => "sub",
+        };
+        Some(format!("{}_{:03X}", prefix, addr))
+    }
+
+    /// Instructions reached by the control flow, in address order.
+    pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.code.values()
+    }
+
+    /// Listing where the targets are labelled and data bytes are drawn as
+    /// sprite rows.
+    pub fn listing(&self) -> String {
+        let mut out = String::new();
+        let mut offset = 0;
+        while offset < self.bytes.len() {
+            let addr = self.base.wrapping_add(offset as u16);
+            if let Some(label) = self.label(addr) {
+                let _ = writeln!(out, "{}:", label);
+            }
+            if let Some(i) = self.code.get(&addr) {
+                let _ = writeln!(out, "    {:03X}  {:04X}  {}", addr, i.word, self.text(i));
+                offset += usize::from(i.size);
+            } else {
+                let byte = self.bytes[offset];
+                let bitmap = (0..8).rev().map(|b| if byte & (1 << b) != 0 { '#' } else { '.' });
+                let _ = writeln!(
+                    out,
+                    "    {:03X}  {:02X}    db {:#010b}  ; {}",
+                    addr,
+                    byte,
+                    byte,
+                    bitmap.collect::<String>()
+                );
+                offset += 1;
+            }
+        }
+        out
+    }
+
+    /// Control-flow graph of the basic blocks, in the Graphviz DOT language.
+    ///
+    /// Calls are dashed and skips dotted.
+    pub fn to_dot(&self) -> String {
+        let leaders = self.leaders();
+        let block_of = |addr: u16| leaders.range(..=addr).next_back().copied().unwrap_or(addr);
+        let mut out =
+            String::from("digraph chip8 {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for &leader in &leaders {
+            let mut text = format!("{}:\\l", self.block_name(leader));
+            let block = self
+                .code
+                .range(leader..)
+                .take_while(|(&addr, _)| addr == leader || !leaders.contains(&addr));
+            for (addr, i) in block {
+                let _ = write!(text, "{:03X}  {}\\l", addr, self.text(i).replace('"', "\\\""));
+            }
+            let _ = writeln!(out, "    \"{}\" [label=\"{}\"];", self.block_name(leader), text);
+        }
+        let mut edges = BTreeSet::new();
+        for &(from, to, edge) in &self.edges {
+            // Fall-through inside a block isn't an edge of the graph.
+            if edge == Edge::Next && !leaders.contains(&to) {
+                continue;
+            }
+            edges.insert((block_of(from), to, edge));
+        }
+        for (from, to, edge) in edges {
+            let style = match edge {
+                Edge::Call => " [style=dashed]",
+                Edge::Skip => " [style=dotted]",
+                Edge::Next | Edge::Jump => "",
+            };
+            let _ = writeln!(
+                out,
+                "    \"{}\" -> \"{}\"{};",
+                self.block_name(from),
+                self.block_name(to),
+                style
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    // First instructions of the basic blocks.
+    fn leaders(&self) -> BTreeSet<u16> {
+        let mut leaders = BTreeSet::new();
+        if self.code.contains_key(&self.base) {
+            leaders.insert(self.base);
+        }
+        for &(from, to, edge) in &self.edges {
+            if edge != Edge::Next {
+                leaders.insert(to);
+            }
+            // A block ends on any instruction that doesn't just go on.
+            let branches = self.edges.range((from, 0, Edge::Next)..=(from, u16::MAX, Edge::Skip));
+            if edge == Edge::Next && branches.count() > 1 {
+                leaders.insert(to);
+            }
+        }
+        leaders
+    }
+
+    fn block_name(&self, addr: u16) -> String {
+        self.label(addr).unwrap_or_else(|| format!("L_{:03X}", addr))
+    }
+
+    // The mnemonic, with the targets replaced by their label.
+    fn text(&self, i: &Instruction) -> String {
+        let kind = match i.kind {
+            Some(kind) => kind,
+            None => return i.text.clone(),
+        };
+        let label = |addr| self.label(addr).unwrap_or_else(|| format!("{:#X}", addr));
+        match kind {
+            OpcodeKind::JpAddr { addr } => format!("JMP {}", label(addr)),
+            OpcodeKind::JpVxAddr { x, addr } => format!("JMP V{:X}, {}", x, label(addr)),
+            OpcodeKind::Call { addr } => format!("CALL {}", label(addr)),
+            OpcodeKind::LoadI { addr } => format!("LD I, {}", label(addr)),
+            OpcodeKind::LoadILong { addr } => format!("LD I, LONG {}", label(addr)),
+            _ => i.text.clone(),
+        }
+    }
+
+    fn add_label(&mut self, addr: u16, label: Label) {
+        let entry = self.labels.entry(addr).or_insert(label);
+        *entry = (*entry).max(label);
+    }
+
+    fn decode(&self, addr: u16) -> Option<Instruction> {
+        let offset = self.offset(addr)?;
+        disassemble(&self.bytes[offset..], addr).next().filter(|i| i.kind.is_some())
+    }
+
+    fn contains(&self, addr: u16) -> bool {
+        self.offset(addr).is_some()
+    }
+
+    fn offset(&self, addr: u16) -> Option<usize> {
+        let offset = usize::from(addr.checked_sub(self.base)?);
+        (offset < self.bytes.len()).then_some(offset)
+    }
+}
@@ -0,0 +1,84 @@
+use super::*;
+
+#[test]
+fn test_disassemble() {
+    // CLS; LD I, LONG 0x1234; SYS 0x123; JP 0x200; a lone byte
+    let bytes = [0x00, 0xE0, 0xF0, 0x00, 0x12, 0x34, 0x01, 0x23, 0x12, 0x00, 0xAB];
+    let listing = disassemble(&bytes, 0x200).collect::<Vec<_>>();
+    let lines = listing.iter().map(|i| (i.addr, i.word, i.size)).collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            (0x200, 0x00E0, 2),
+            (0x202, 0xF000, 4),
+            (0x206, 0x0123, 2),
+            (0x208, 0x1200, 2),
+            (0x20A, 0xAB, 1)
+        ]
+    );
+    let text = listing.iter().map(|i| i.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(text, ["CLS", "LD I, LONG 0x1234", "DW 0x0123", "JMP 0x200", "DB 0xAB"]);
+    assert!(matches!(listing[3].kind, Some(OpcodeKind::JpAddr { addr: 0x200 })));
+    assert!(listing[2].kind.is_none());
+}
+
+#[test]
+fn test_truncated_long_instruction() {
+    let listing = disassemble(&[0xF0, 0x00, 0x12], 0x200).collect::<Vec<_>>();
+    let text = listing.iter().map(|i| i.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(text, ["DW 0xF000", "DB 0x12"]);
+}
+
+#[test]
+fn test_analyse() {
+    // LD I, 0x20C; CALL 0x208; SE V0, 0x1; JP 0x204;
+    // DRW V0, V0, 0x2; RET; a 2 rows sprite
+    let bytes =
+        [0xA2, 0x0C, 0x22, 0x08, 0x30, 0x01, 0x12, 0x04, 0xD0, 0x02, 0x00, 0xEE, 0xF0, 0x90];
+    let analysis = analyse(&bytes, 0x200);
+    assert!((0x200..0x20C).all(|addr| analysis.is_code_at(addr)));
+    assert!(!analysis.is_code_at(0x20C));
+    assert_eq!(analysis.label(0x204).as_deref(), Some("L_204"));
+    assert_eq!(analysis.label(0x208).as_deref(), Some("sub_208"));
+    assert_eq!(analysis.label(0x20C).as_deref(), Some("data_20C"));
+    assert_eq!(
+        analysis.listing(),
+        "    200  A20C  LD I, data_20C\n\
+        \x20   202  2208  CALL sub_208\n\
+        L_204:\n\
+        \x20   204  3001  SE V0, 0x1\n\
+        \x20   206  1204  JMP L_204\n\
+        sub_208:\n\
+        \x20   208  D002  DRW V0, V0, 0x2\n\
+        \x20   20A  00EE  ret\n\
+        data_20C:\n\
+        \x20   20C  F0    db 0b11110000  ; ####....\n\
+        \x20   20D  90    db 0b10010000  ; #..#....\n"
+    );
+
+    let dot = analysis.to_dot();
+    assert!(dot.starts_with("digraph chip8 {"));
+    assert!(dot.contains("\"L_200\" -> \"sub_208\" [style=dashed];"));
+    assert!(dot.contains("\"L_200\" -> \"L_204\";"));
+    assert!(dot.contains("\"L_204\" -> \"sub_208\" [style=dotted];"));
+    assert!(dot.contains("\"L_206\" -> \"L_204\";"));
+}
+
+#[test]
+fn test_analyse_stops_at_data() {
+    // JP V0, 0x204; 0x0123 isn't an instruction; LD V0, 0x1
+    let analysis = analyse(&[0xB2, 0x04, 0x01, 0x23, 0x60, 0x01], 0x200);
+    assert!(!analysis.is_code_at(0x202));
+    assert!(analysis.is_code_at(0x204));
+    assert_eq!(analysis.instructions().count(), 2);
+}
+
+#[test]
+fn test_analyse_at_end_of_memory() {
+    // LD V0, 0x1 over and over, the last 8 bytes past 0xFFFF.
+    let analysis = analyse(&[0x60, 0x01].repeat(12), 0xFFF0);
+    assert_eq!(analysis.instructions().count(), 8);
+    let listing = analysis.listing();
+    assert_eq!(listing.lines().count(), 8);
+    assert!(listing.ends_with("    FFFE  6001  LD V0, 0x1\n"));
+}
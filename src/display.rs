@@ -4,27 +4,91 @@ mod tests;
 use crate::alloc::boxed_zeroed_display;
 use crate::num::BitIter;
 
-pub const WIDTH: u16 = if cfg!(feature = "chip48") { 128 } else { 64 };
-pub const HEIGHT: u16 = if cfg!(feature = "chip48") { 64 } else { 32 };
+pub const WIDTH: u16 = 64;
+pub const HEIGHT: u16 = 32;
+pub const HIRES_WIDTH: u16 = 128;
+pub const HIRES_HEIGHT: u16 = 64;
 
-pub(crate) const DISPLAY_SIZE: usize = (WIDTH * HEIGHT) as usize;
+pub(crate) const DISPLAY_SIZE: usize = (HIRES_WIDTH * HIRES_HEIGHT) as usize;
 const PIXELS_WIDE: u16 = 8;
+const LARGE_PIXELS_WIDE: u16 = 16;
+const PLANES: [u8; 2] = [0b01, 0b10];
 
+/// Each pixel holds one bit per bitplane, XO-CHIP having two of them.
+/// Plain CHIP-8 programs only ever draw to the first plane.
 pub struct Display {
-    vram: Box<[bool; DISPLAY_SIZE]>,
+    vram: Box<[u8; DISPLAY_SIZE]>,
+    hires: bool,
+    planes: u8,
 }
 
 impl Display {
     pub fn new() -> Self {
-        Self { vram: boxed_zeroed_display() }
+        Self { vram: boxed_zeroed_display(), hires: false, planes: PLANES[0] }
     }
 
     pub fn reset(&mut self) {
-        self.vram.fill(false);
+        self.vram.fill(0);
+        self.hires = false;
+        self.planes = PLANES[0];
     }
 
-    pub(crate) fn get_buf(&self) -> &[bool; DISPLAY_SIZE] {
-        &self.vram
+    /// Whole video memory, regardless of the resolution.
+    pub(crate) fn vram(&self) -> &[u8] {
+        &self.vram[..]
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Restores the state saved from `vram`, `is_hires` and `planes`.
+    pub fn restore(&mut self, vram: &[u8], hires: bool, planes: u8) {
+        self.vram.copy_from_slice(vram);
+        self.hires = hires;
+        self.select_planes(planes);
+    }
+
+    /// Pixels of the current resolution, row by row.
+    pub(crate) fn get_buf(&self) -> &[u8] {
+        &self.vram[..usize::from(self.width() * self.height())]
+    }
+
+    pub fn width(&self) -> u16 {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            WIDTH
+        }
+    }
+
+    pub fn height(&self) -> u16 {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            HEIGHT
+        }
+    }
+
+    /// Switches between the 64x32 and 128x64 modes. The screen is cleared.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.vram.fill(0);
+    }
+
+    /// Selects the bitplanes that drawing, clearing and scrolling affect.
+    pub fn select_planes(&mut self, mask: u8) {
+        self.planes = mask & (PLANES[0] | PLANES[1]);
+    }
+
+    /// How many bitplanes are selected, i.e. how many sprites a draw
+    /// instruction reads from memory.
+    pub fn selected_planes(&self) -> u8 {
+        self.planes.count_ones() as u8
     }
 
     // Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels
@@ -33,22 +97,123 @@ impl Display {
     // the execution of this instruction. As described above, VF is set to 1
     // if any screen pixels are flipped from set to unset when
     // the sprite is drawn, and to 0 if that doesn't happen.
+    // With `wrap` set, the parts of the sprite going past the edges of
+    // the screen reappear on the opposite side instead of being clipped.
+    // When several bitplanes are selected, `sprites` holds one sprite
+    // per plane, one after another.
     #[must_use]
-    pub fn draw(&mut self, (x, y): (u8, u8), sprites: &[u8]) -> bool {
+    pub fn draw(&mut self, (x, y): (u8, u8), sprites: &[u8], wrap: bool) -> bool {
         let mut collision = false;
-        let (x, y) = (u16::from(x) % WIDTH, u16::from(y) % HEIGHT);
-        let x_end = (x + PIXELS_WIDE).min(WIDTH);
-        for (y, &b) in (y..HEIGHT).zip(sprites.iter()) {
-            for (x, b) in (x..x_end).zip(BitIter::new(b)) {
-                let coord = usize::from(x + y * WIDTH);
-                collision |= self.vram[coord] & b;
-                self.vram[coord] ^= b;
+        for (plane, sprites) in self.split_planes(sprites, 1) {
+            let rows = sprites.iter().map(|&b| u16::from(b) << 8);
+            collision |= self.blit((x, y), rows, PIXELS_WIDE, plane, wrap);
+        }
+        collision
+    }
+
+    // Same as `draw`, but for the 16x16 sprites of SUPER-CHIP `Dxy0`.
+    // Each row is made of two consecutive bytes.
+    #[must_use]
+    pub fn draw_large(&mut self, (x, y): (u8, u8), sprites: &[u8], wrap: bool) -> bool {
+        let mut collision = false;
+        for (plane, sprites) in self.split_planes(sprites, 2) {
+            let rows = sprites.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+            collision |= self.blit((x, y), rows, LARGE_PIXELS_WIDE, plane, wrap);
+        }
+        collision
+    }
+
+    fn split_planes<'a>(
+        &self,
+        sprites: &'a [u8],
+        row_size: usize,
+    ) -> impl Iterator<Item = (u8, &'a [u8])> {
+        let planes = self.planes;
+        let len = match self.selected_planes() {
+            0 => sprites.len(),
+            n => sprites.len() / usize::from(n) / row_size * row_size,
+        };
+        let selected = PLANES.into_iter().filter(move |&p| planes & p != 0);
+        selected.zip(sprites.chunks(len.max(1)))
+    }
+
+    fn blit<I>(&mut self, (x, y): (u8, u8), rows: I, wide: u16, plane: u8, wrap: bool) -> bool
+    where
+        I: ExactSizeIterator<Item = u16>,
+    {
+        let (width, height) = (self.width(), self.height());
+        let mut collision = false;
+        let (x, y) = (u16::from(x) % width, u16::from(y) % height);
+        let (x_end, y_end) = match wrap {
+            true => (x + wide, y + rows.len() as u16),
+            false => ((x + wide).min(width), height),
+        };
+        for (y, row) in (y..y_end).zip(rows) {
+            let [hi, lo] = row.to_be_bytes();
+            let bits = BitIter::new(hi).chain(BitIter::new(lo));
+            for (x, b) in (x..x_end).zip(bits) {
+                let coord = usize::from(x % width + y % height * width);
+                if b {
+                    collision |= self.vram[coord] & plane != 0;
+                    self.vram[coord] ^= plane;
+                }
             }
         }
         collision
     }
 
+    /// Clears the selected bitplanes.
     pub fn clear_screen(&mut self) {
-        self.vram.fill(false);
+        let planes = self.planes;
+        self.vram.iter_mut().for_each(|p| *p &= !planes);
+    }
+
+    /// Scrolls the selected bitplanes down by `n` pixels.
+    pub fn scroll_down(&mut self, n: u8) {
+        let height = self.height();
+        let n = u16::from(n).min(height);
+        self.shift_pixels(|x, y| if y >= n { Some((x, y - n)) } else { None });
+    }
+
+    /// Scrolls the selected bitplanes up by `n` pixels.
+    pub fn scroll_up(&mut self, n: u8) {
+        let height = self.height();
+        let n = u16::from(n).min(height);
+        self.shift_pixels(|x, y| if y + n < height { Some((x, y + n)) } else { None });
+    }
+
+    /// Scrolls the selected bitplanes right by `n` pixels.
+    pub fn scroll_right(&mut self, n: u8) {
+        let width = self.width();
+        let n = u16::from(n).min(width);
+        self.shift_pixels(|x, y| if x >= n { Some((x - n, y)) } else { None });
+    }
+
+    /// Scrolls the selected bitplanes left by `n` pixels.
+    pub fn scroll_left(&mut self, n: u8) {
+        let width = self.width();
+        let n = u16::from(n).min(width);
+        self.shift_pixels(|x, y| if x + n < width { Some((x + n, y)) } else { None });
+    }
+
+    // Moves the selected bitplanes around: each pixel of the screen takes
+    // the value of the pixel at `from(x, y)`, or gets cleared if `None`.
+    fn shift_pixels<F>(&mut self, from: F)
+    where
+        F: Fn(u16, u16) -> Option<(u16, u16)>,
+    {
+        let (width, height) = (self.width(), self.height());
+        let planes = self.planes;
+        let old = self.vram.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let coord = usize::from(x + y * width);
+                let moved = match from(x, y) {
+                    Some((x, y)) => old[usize::from(x + y * width)] & planes,
+                    None => 0,
+                };
+                self.vram[coord] = (self.vram[coord] & !planes) | moved;
+            }
+        }
     }
 }
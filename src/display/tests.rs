@@ -3,8 +3,8 @@ use super::*;
 #[test]
 fn test_display() {
     let mut display = Display::new();
-    let f = display.draw((0, 0), &crate::memory::FONTS_SET[..5]);
-    assert_eq!(f, false);
+    let f = display.draw((0, 0), &crate::memory::FONTS_SET[..5], false);
+    assert!(!f);
     let expect = [
         true, true, true, true, false, false, false, false, // 0xF0
         true, false, false, true, false, false, false, false, // 0x90
@@ -14,11 +14,61 @@ fn test_display() {
     ];
     for y in 0..5 {
         for x in 0..8 {
-            assert_eq!(display.vram[x + y * usize::from(WIDTH)], expect[x + y * 8]);
+            assert_eq!(display.vram[x + y * usize::from(WIDTH)], u8::from(expect[x + y * 8]));
         }
     }
 
-    let f = display.draw((0, 0), &crate::memory::FONTS_SET[..5]);
-    assert_eq!(f, true);
-    assert!(display.vram.iter().all(|&o| o == false));
+    let f = display.draw((0, 0), &crate::memory::FONTS_SET[..5], false);
+    assert!(f);
+    assert!(display.vram.iter().all(|&o| o == 0));
+}
+
+#[test]
+fn test_hires_large_sprite() {
+    let mut display = Display::new();
+    display.set_hires(true);
+    assert_eq!(display.get_buf().len(), usize::from(HIRES_WIDTH * HIRES_HEIGHT));
+    let sprite = [0xFF; 32];
+    let f = display.draw_large((120, 60), &sprite, false);
+    assert!(!f);
+    // Clipped to the bottom right 8x4 corner.
+    assert_eq!(display.get_buf().iter().filter(|&&o| o != 0).count(), 8 * 4);
+
+    display.clear_screen();
+    let f = display.draw_large((120, 60), &sprite, true);
+    assert!(!f);
+    assert_eq!(display.get_buf().iter().filter(|&&o| o != 0).count(), 16 * 16);
+}
+
+#[test]
+fn test_scroll() {
+    let mut display = Display::new();
+    let _ = display.draw((0, 0), &[0x80], false);
+    display.scroll_down(3);
+    assert_eq!(display.vram[3 * usize::from(WIDTH)], 1);
+    display.scroll_right(4);
+    assert_eq!(display.vram[3 * usize::from(WIDTH) + 4], 1);
+    display.scroll_left(4);
+    assert_eq!(display.vram[3 * usize::from(WIDTH)], 1);
+    display.scroll_left(4);
+    assert!(display.get_buf().iter().all(|&o| o == 0));
+}
+
+#[test]
+fn test_bitplanes() {
+    let mut display = Display::new();
+    display.select_planes(0b11);
+    assert_eq!(display.selected_planes(), 2);
+    // First plane gets the left column, second plane the right one.
+    let f = display.draw((0, 0), &[0x80, 0x40], false);
+    assert!(!f);
+    assert_eq!(&display.get_buf()[..3], &[1, 2, 0]);
+
+    display.select_planes(0b10);
+    let f = display.draw((0, 0), &[0xC0], false);
+    assert!(f);
+    assert_eq!(&display.get_buf()[..3], &[3, 0, 0]);
+
+    display.clear_screen();
+    assert_eq!(&display.get_buf()[..3], &[1, 0, 0]);
 }
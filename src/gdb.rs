@@ -0,0 +1,442 @@
+#[cfg(test)]
+mod tests;
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cpu::{Cpu, CpuError, CpuState};
+use crate::debugger::{Access, Debugger, StopReason};
+
+const REGISTERS: usize = 16;
+const STACK_SIZE: usize = 16;
+// Registers as GDB numbers them: V0 to VF, I, PC, the stack depth,
+// then the 16 stack entries.
+const REG_I: usize = REGISTERS;
+const REG_PC: usize = REG_I + 1;
+const REG_SP: usize = REG_PC + 1;
+const REG_STACK: usize = REG_SP + 1;
+const REG_COUNT: usize = REG_STACK + STACK_SIZE;
+
+const CYCLES_PER_FRAME: u32 = 9;
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const INTERRUPT: u8 = 0x03;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Server of the GDB remote serial protocol, to debug a CHIP-8 program
+/// from GDB or any frontend speaking the protocol.
+///
+/// GDB sees the registers V0 to VF, `i`, `pc`, the stack depth `sp` and
+/// the stack entries `s0` to `s15`, and the memory of the `Cpu` starting
+/// at address 0. The stack can be read but not written. Software
+/// breakpoints, watchpoints, single-step, continue and Ctrl-C are
+/// supported.
+///
+/// A continued program runs 60 frames per second of 9 instructions and a
+/// timer tick each, and checks for a Ctrl-C between frames.
+pub struct GdbStub {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    debugger: Debugger,
+    no_ack: bool,
+}
+
+enum Action {
+    Reply(String),
+    // Replies, then ends the session.
+    ReplyAndClose(String),
+    Close,
+}
+
+impl GdbStub {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let writer = stream.try_clone()?;
+        Ok(Self {
+            reader: BufReader::new(stream),
+            writer,
+            debugger: Debugger::new(),
+            no_ack: false,
+        })
+    }
+
+    /// Answers GDB until it detaches, kills the program or disconnects.
+    pub fn serve(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(cpu, &packet)? {
+                Action::Reply(reply) => self.send_packet(&reply)?,
+                Action::ReplyAndClose(reply) => return self.send_packet(&reply),
+                Action::Close => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, cpu: &mut Cpu, packet: &str) -> io::Result<Action> {
+        let reply = match packet.as_bytes().first().copied().unwrap_or(0) {
+            b'?' => format!("S{:02x}", SIGTRAP),
+            b'g' => (0..REG_COUNT).map(|n| read_register(cpu, n)).collect(),
+            b'G' => match write_registers(cpu, &packet[1..]) {
+                Some(()) => "OK".into(),
+                None => "E01".into(),
+            },
+            b'p' => match usize::from_str_radix(&packet[1..], 16) {
+                Ok(n) if n < REG_COUNT => read_register(cpu, n),
+                _ => "E01".into(),
+            },
+            b'P' => {
+                let written = packet[1..].split_once('=').and_then(|(n, value)| {
+                    let n = usize::from_str_radix(n, 16).ok()?;
+                    write_register(cpu, n, &decode_hex(value)?)
+                });
+                if written.is_some() {
+                    "OK".into()
+                } else {
+                    "E01".into()
+                }
+            }
+            b'm' => match parse_range(&packet[1..]) {
+                Some((addr, len)) => {
+                    let bytes = addr.checked_add(len).and_then(|end| cpu.memory().get(addr..end));
+                    match bytes {
+                        Some(bytes) => encode_hex(bytes),
+                        None => "E01".into(),
+                    }
+                }
+                None => "E01".into(),
+            },
+            b'M' => {
+                let written = packet[1..].split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = parse_range(range)?;
+                    let data = decode_hex(data).filter(|d| d.len() == len)?;
+                    let end = addr.checked_add(len)?;
+                    cpu.memory_mut().get_mut(addr..end)?.copy_from_slice(&data);
+                    Some(())
+                });
+                if written.is_some() {
+                    "OK".into()
+                } else {
+                    "E01".into()
+                }
+            }
+            b'Z' | b'z' => self.set_breakpoint(packet),
+            b's' | b'c' => {
+                if let Ok(addr) = u16::from_str_radix(&packet[1..], 16) {
+                    cpu.set_pc(addr);
+                }
+                if packet.starts_with('s') {
+                    self.step(cpu)
+                } else {
+                    self.resume(cpu)?
+                }
+            }
+            b'H' => "OK".into(),
+            b'k' => return Ok(Action::Close),
+            b'D' => return Ok(Action::ReplyAndClose("OK".into())),
+            b'q' | b'Q' => return self.query(packet),
+            _ => String::new(),
+        };
+        Ok(Action::Reply(reply))
+    }
+
+    fn query(&mut self, packet: &str) -> io::Result<Action> {
+        let reply = if packet.starts_with("qSupported") {
+            "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+".into()
+        } else if packet == "QStartNoAckMode" {
+            // The acknowledgment of this very packet has already been sent.
+            self.no_ack = true;
+            "OK".into()
+        } else if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_range(args) {
+                Some((offset, len)) => {
+                    let xml = target_xml();
+                    let chunk = xml.get(offset..).unwrap_or("");
+                    match chunk.get(..len) {
+                        Some(part) if part.len() < chunk.len() => format!("m{}", part),
+                        _ => format!("l{}", chunk),
+                    }
+                }
+                None => "E01".into(),
+            }
+        } else {
+            match packet {
+                "qAttached" => "1".into(),
+                "qC" => "QC1".into(),
+                "qfThreadInfo" => "m1".into(),
+                "qsThreadInfo" => "l".into(),
+                _ => String::new(),
+            }
+        };
+        Ok(Action::Reply(reply))
+    }
+
+    // `Z<type>,<addr>,<kind>` inserts, `z` removes.
+    fn set_breakpoint(&mut self, packet: &str) -> String {
+        let insert = packet.starts_with('Z');
+        let mut fields = packet[1..].split(',');
+        let (kind, addr, len) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(kind), Some(addr), Some(len)) => (kind, addr, len),
+            _ => return "E01".into(),
+        };
+        let (addr, len) = match (u16::from_str_radix(addr, 16), u16::from_str_radix(len, 16)) {
+            (Ok(addr), Ok(len)) => (addr, len),
+            _ => return "E01".into(),
+        };
+        let access = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(addr);
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return "OK".into();
+            }
+            "2" => Access::Write,
+            "3" => Access::Read,
+            "4" => Access::ReadWrite,
+            _ => return String::new(),
+        };
+        if insert {
+            self.debugger.watch_memory(addr, len, access);
+        } else {
+            self.debugger.unwatch_memory(addr);
+        }
+        "OK".into()
+    }
+
+    fn step(&mut self, cpu: &mut Cpu) -> String {
+        match self.debugger.step(cpu) {
+            Ok(stop) => stop_reply(cpu, stop.as_ref().unwrap_or(&StopReason::Step)),
+            Err(e) => error_reply(&e),
+        }
+    }
+
+    fn resume(&mut self, cpu: &mut Cpu) -> io::Result<String> {
+        let mut next_frame = Instant::now();
+        loop {
+            match cpu.state {
+                CpuState::Halted => return Ok("W00".into()),
+                // No key can be pressed from GDB, only a Ctrl-C ends the wait.
+                CpuState::Paused => {
+                    self.wait_interrupt()?;
+                    return Ok(format!("S{:02x}", SIGINT));
+                }
+                CpuState::Running | CpuState::Step => {}
+            }
+            match self.debugger.run_frame(cpu, CYCLES_PER_FRAME) {
+                Ok(Some(stop)) => return Ok(stop_reply(cpu, &stop)),
+                Ok(None) => {}
+                Err(e) => return Ok(error_reply(&e)),
+            }
+            if self.interrupted()? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+            next_frame += FRAME;
+            match next_frame.checked_duration_since(Instant::now()) {
+                Some(wait) => thread::sleep(wait),
+                // Too slow to keep up, the next frames start from now.
+                None => next_frame = Instant::now(),
+            }
+        }
+    }
+
+    // Blocks until a Ctrl-C or the end of the connection.
+    fn wait_interrupt(&mut self) -> io::Result<()> {
+        loop {
+            match self.reader.fill_buf()?.first() {
+                None => return Ok(()),
+                Some(&INTERRUPT) => {
+                    self.reader.consume(1);
+                    return Ok(());
+                }
+                Some(_) => self.reader.consume(1),
+            }
+        }
+    }
+
+    // Looks for a Ctrl-C without waiting for one.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.reader.get_ref().set_nonblocking(true)?;
+        let polled = match self.reader.fill_buf() {
+            Ok(buf) => Ok(buf.first() == Some(&INTERRUPT)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        };
+        self.reader.get_ref().set_nonblocking(false)?;
+        if polled? {
+            self.reader.consume(1);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Reads the next `$<data>#<checksum>` packet, skipping anything else.
+    /// Returns `None` when the connection is closed.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut byte = [0];
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] != b'$' {
+                continue;
+            }
+            let mut data = Vec::new();
+            self.reader.read_until(b'#', &mut data)?;
+            if data.pop() != Some(b'#') {
+                return Ok(None);
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .is_some_and(|c| c == checksum_of(&data));
+            if !self.no_ack {
+                self.writer.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        loop {
+            self.writer.write_all(packet.as_bytes())?;
+            if self.no_ack {
+                return Ok(());
+            }
+            // Resends until GDB acknowledges the packet.
+            let mut ack = [0];
+            loop {
+                if self.reader.read(&mut ack)? == 0 {
+                    return Ok(());
+                }
+                match ack[0] {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn stop_reply(cpu: &Cpu, stop: &StopReason) -> String {
+    if let CpuState::Halted = cpu.state {
+        return "W00".into();
+    }
+    match stop {
+        StopReason::Breakpoint { .. } => format!("T{:02x}swbreak:;", SIGTRAP),
+        StopReason::Watchpoint { addr, write: true } | StopReason::CodeWritten { addr, .. } => {
+            format!("T{:02x}watch:{:x};", SIGTRAP, addr)
+        }
+        StopReason::Watchpoint { addr, write: false } => {
+            format!("T{:02x}rwatch:{:x};", SIGTRAP, addr)
+        }
+        StopReason::RegisterChanged { .. } | StopReason::Step => format!("S{:02x}", SIGTRAP),
+    }
+}
+
+fn error_reply(e: &CpuError) -> String {
+    match e {
+        CpuError::InvalidOpcode { .. } => format!("S{:02x}", SIGILL),
+        _ => format!("S{:02x}", SIGSEGV),
+    }
+}
+
+// Multi-byte registers are little endian.
+fn read_register(cpu: &Cpu, n: usize) -> String {
+    let stack = cpu.stack();
+    match n {
+        0..=15 => encode_hex(&cpu.registers()[n..=n]),
+        REG_I => encode_hex(&cpu.i().to_le_bytes()),
+        REG_PC => encode_hex(&cpu.pc().to_le_bytes()),
+        REG_SP => encode_hex(&[stack.len() as u8]),
+        _ => encode_hex(&stack.get(n - REG_STACK).copied().unwrap_or(0).to_le_bytes()),
+    }
+}
+
+fn register_size(n: usize) -> usize {
+    match n {
+        0..=15 | REG_SP => 1,
+        _ => 2,
+    }
+}
+
+fn write_register(cpu: &mut Cpu, n: usize, value: &[u8]) -> Option<()> {
+    if value.len() != register_size(n) {
+        return None;
+    }
+    let word = || u16::from_le_bytes([value[0], value[1]]);
+    match n {
+        0..=15 => cpu.registers_mut()[n] = value[0],
+        REG_I => cpu.set_i(word()),
+        REG_PC => cpu.set_pc(word()),
+        _ => return None,
+    }
+    Some(())
+}
+
+// The stack can't be written, it is skipped.
+fn write_registers(cpu: &mut Cpu, hex: &str) -> Option<()> {
+    let bytes = decode_hex(hex)?;
+    if bytes.len() < REGISTERS + 4 {
+        return None;
+    }
+    let mut offset = 0;
+    for n in 0..REG_SP {
+        let size = register_size(n);
+        write_register(cpu, n, &bytes[offset..offset + size])?;
+        offset += size;
+    }
+    Some(())
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\
+        <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+        <target version=\"1.0\"><feature name=\"org.chip8emu.cpu\">",
+    );
+    for x in 0..REGISTERS {
+        let _ = write!(xml, "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>", x);
+    }
+    xml.push_str("<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>");
+    xml.push_str("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>");
+    xml.push_str("<reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>");
+    for x in 0..STACK_SIZE {
+        let _ = write!(xml, "<reg name=\"s{}\" bitsize=\"16\" type=\"code_ptr\"/>", x);
+    }
+    xml.push_str("</feature></target>");
+    xml
+}
+
+// `<addr>,<len>` in hexadecimal.
+fn parse_range(s: &str) -> Option<(usize, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((usize::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
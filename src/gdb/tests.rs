@@ -0,0 +1,142 @@
+use std::net::TcpListener;
+use std::thread;
+
+use super::*;
+use crate::quirks::Platform;
+
+struct Client {
+    stream: BufReader<TcpStream>,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) -> String {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.get_mut().write_all(packet.as_bytes()).unwrap();
+        let mut ack = [0];
+        self.stream.read_exact(&mut ack).unwrap();
+        assert_eq!(ack[0], b'+');
+        self.receive()
+    }
+
+    fn receive(&mut self) -> String {
+        let reply = self.receive_no_ack();
+        self.stream.get_mut().write_all(b"+").unwrap();
+        reply
+    }
+
+    fn receive_no_ack(&mut self) -> String {
+        let mut reply = Vec::new();
+        self.stream.read_until(b'$', &mut reply).unwrap();
+        reply.clear();
+        self.stream.read_until(b'#', &mut reply).unwrap();
+        reply.pop();
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(checksum, checksum_of(&reply));
+        String::from_utf8(reply).unwrap()
+    }
+}
+
+// Runs `rom` behind a stub, and returns the `Cpu` once the session is over.
+fn session(rom: &[u8], script: impl FnOnce(&mut Client)) -> Cpu {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut cpu = Cpu::with_seed(Platform::Chip48.quirks(), 0);
+    cpu.load_game(rom).unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        GdbStub::new(stream).unwrap().serve(&mut cpu).unwrap();
+        cpu
+    });
+    let mut client = Client { stream: BufReader::new(TcpStream::connect(addr).unwrap()) };
+    script(&mut client);
+    drop(client);
+    server.join().unwrap()
+}
+
+// ADD V0, 0x1; LD I, 0x300; LD [I], V0; JP 0x200
+const ROM: [u8; 8] = [0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00];
+
+#[test]
+fn test_registers_and_memory() {
+    let cpu = session(&ROM, |client| {
+        assert!(client.send("qSupported:swbreak+").contains("qXfer:features:read+"));
+        assert_eq!(client.send("?"), "S05");
+        let xml = client.send("qXfer:features:read:target.xml:0,fff");
+        assert!(xml.starts_with("l<?xml"));
+        assert!(xml.contains("name=\"pc\""));
+
+        let regs = client.send("g");
+        assert_eq!(regs.len(), 2 * (16 + 2 + 2 + 1 + 2 * 16));
+        assert_eq!(&regs[32..40], "50000002");
+        assert_eq!(client.send("p11"), "0002");
+        assert_eq!(client.send("P3=2a"), "OK");
+        assert_eq!(client.send("P10=3412"), "OK");
+        assert_eq!(client.send("P12=01"), "E01");
+        assert_eq!(client.send("p3"), "2a");
+
+        assert_eq!(client.send("m200,4"), "7001a300");
+        assert_eq!(client.send("M400,2:beef"), "OK");
+        assert_eq!(client.send("m400,2"), "beef");
+        assert_eq!(client.send("m10000,1"), "E01");
+        assert_eq!(client.send("mffffffffffffffff,1"), "E01");
+        assert_eq!(client.send("Mffffffffffffffff,1:00"), "E01");
+        assert_eq!(client.send("vMustReplyEmpty"), "");
+        assert_eq!(client.send("D"), "OK");
+    });
+    assert_eq!(cpu.registers()[3], 0x2A);
+    assert_eq!(cpu.i(), 0x1234);
+    assert_eq!(cpu.memory()[0x400..0x402], [0xBE, 0xEF]);
+}
+
+#[test]
+fn test_breakpoints_and_stepping() {
+    let cpu = session(&ROM, |client| {
+        assert_eq!(client.send("QStartNoAckMode"), "OK");
+        let mut client = NoAck(client);
+        assert_eq!(client.send("s"), "S05");
+        assert_eq!(client.send("p11"), "0202");
+
+        assert_eq!(client.send("Z0,206,2"), "OK");
+        assert_eq!(client.send("c"), "T05swbreak:;");
+        assert_eq!(client.send("p11"), "0602");
+        assert_eq!(client.send("z0,206,2"), "OK");
+
+        assert_eq!(client.send("Z2,300,1"), "OK");
+        assert_eq!(client.send("c"), "T05watch:300;");
+        assert_eq!(client.send("p0"), "02");
+        assert_eq!(client.send("z2,300,1"), "OK");
+
+        // Ctrl-C stops the endless loop.
+        client.0.stream.get_mut().write_all(b"$c#63\x03").unwrap();
+        assert_eq!(client.0.receive_no_ack(), "S02");
+        client.0.stream.get_mut().write_all(b"$k#6b").unwrap();
+    });
+    assert!((0x200..0x208).contains(&cpu.pc()));
+}
+
+#[test]
+fn test_interrupt_key_wait() {
+    // LD V0, K
+    let cpu = session(&[0xF0, 0x0A], |client| {
+        assert_eq!(client.send("QStartNoAckMode"), "OK");
+        client.stream.get_mut().write_all(b"$c#63").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        client.stream.get_mut().write_all(b"\x03").unwrap();
+        assert_eq!(client.receive_no_ack(), "S02");
+        client.stream.get_mut().write_all(b"$k#6b").unwrap();
+    });
+    assert!(matches!(cpu.state, CpuState::Paused));
+}
+
+// After `QStartNoAckMode`, neither side sends acknowledgments.
+struct NoAck<'a>(&'a mut Client);
+
+impl NoAck<'_> {
+    fn send(&mut self, data: &str) -> String {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.0.stream.get_mut().write_all(packet.as_bytes()).unwrap();
+        self.0.receive_no_ack()
+    }
+}
@@ -0,0 +1,380 @@
+//! Pictures of the screen, as returned by [`Cpu::get_vram`](crate::Cpu::get_vram).
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Colours of the four values an XO-CHIP pixel can take.
+pub const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [0, 255, 0], [255, 102, 0], [255, 255, 255]];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// Biggest block that deflate stores without compression.
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+// 2 bits per pixel, the smallest LZW code size GIF allows.
+const GIF_CODE_SIZE: u8 = 2;
+// GIF limits LZW codes to 12 bits, giflib clears the table one code earlier.
+const GIF_MAX_CODE: u16 = 4095;
+// Browsers show frames shorter than 2 hundredths of a second for 10.
+const GIF_MIN_DELAY: u64 = 2;
+
+/// Writes an animated GIF of the screens of a running program, looping
+/// forever.
+///
+/// Screens in another resolution than the one given to [`GifWriter::new`]
+/// are stretched to fill the GIF, like the interpreter window does.
+pub struct GifWriter<W: Write> {
+    w: W,
+    palette: [[u8; 3]; 4],
+    // Size of the GIF, scaled.
+    size: (u16, u16),
+    fps: u64,
+    started: bool,
+    // The screen not written yet, stretched to `size`, and the frame it
+    // appeared at.
+    pending: Option<(Vec<u8>, u64)>,
+    frames: u64,
+}
+
+/// Writes a binary PBM, where lit pixels are black.
+pub fn write_pbm<W: Write>(mut w: W, (width, height): (u16, u16), pixels: &[u8]) -> io::Result<()> {
+    write!(w, "P4\n{} {}\n", width, height)?;
+    for row in pixels.chunks(usize::from(width)).take(usize::from(height)) {
+        let mut packed = vec![0u8; row.len().div_ceil(8)];
+        for (i, _) in row.iter().enumerate().filter(|(_, &p)| p != 0) {
+            packed[i / 8] |= 0x80 >> (i % 8);
+        }
+        w.write_all(&packed)?;
+    }
+    Ok(())
+}
+
+/// Writes a PNG where each pixel is a `scale` by `scale` square coloured
+/// from `palette`.
+pub fn write_png<W: Write>(
+    mut w: W,
+    (width, height): (u16, u16),
+    pixels: &[u8],
+    palette: &[[u8; 3]; 4],
+    scale: u16,
+) -> io::Result<()> {
+    let scale = usize::from(scale.max(1));
+    let (width, height) = (usize::from(width), usize::from(height));
+
+    // One filter byte, then one palette index per pixel, on every row.
+    let mut raw = Vec::with_capacity((width * scale + 1) * height * scale);
+    for row in pixels.chunks(width).take(height) {
+        let start = raw.len();
+        raw.push(0);
+        for &pixel in row {
+            raw.extend(std::iter::repeat_n(pixel & 0b11, scale));
+        }
+        for _ in 1..scale {
+            raw.extend_from_within(start..start + width * scale + 1);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&((width * scale) as u32).to_be_bytes());
+    header.extend_from_slice(&((height * scale) as u32).to_be_bytes());
+    // Bit depth 8, indexed colours, no interlacing.
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    w.write_all(&PNG_SIGNATURE)?;
+    write_chunk(&mut w, b"IHDR", &header)?;
+    write_chunk(&mut w, b"PLTE", palette.concat().as_slice())?;
+    write_chunk(&mut w, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(&mut w, b"IEND", &[])
+}
+
+/// Writes an 8-bit RGBA PNG of `rgba`, 4 bytes per pixel, row by row.
+pub fn write_rgba_png<W: Write>(
+    mut w: W,
+    (width, height): (u32, u32),
+    rgba: &[u8],
+) -> io::Result<()> {
+    let row_len = width as usize * 4;
+    let mut raw = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in rgba.chunks(row_len).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, RGBA, no interlacing.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    w.write_all(&PNG_SIGNATURE)?;
+    write_chunk(&mut w, b"IHDR", &header)?;
+    write_chunk(&mut w, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(&mut w, b"IEND", &[])
+}
+
+/// Colours the pixels from `palette`, each one becoming a `scale` by
+/// `scale` square of opaque RGBA pixels. The image is `width * scale`
+/// pixels wide.
+pub fn to_rgba(
+    (width, height): (u16, u16),
+    pixels: &[u8],
+    palette: &[[u8; 3]; 4],
+    scale: u16,
+) -> Vec<u8> {
+    let scale = usize::from(scale.max(1));
+    let (width, height) = (usize::from(width), usize::from(height));
+    let row_len = width * scale * 4;
+    let mut out = Vec::with_capacity(row_len * height * scale);
+    for row in pixels.chunks(width).take(height) {
+        let start = out.len();
+        for &pixel in row {
+            let [r, g, b] = palette[usize::from(pixel & 0b11)];
+            for _ in 0..scale {
+                out.extend_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+        for _ in 1..scale {
+            out.extend_from_within(start..start + row_len);
+        }
+    }
+    out
+}
+
+/// Draws the pixels with `.`, `#`, `+` and `@`, one line per row.
+pub fn ascii_art((width, height): (u16, u16), pixels: &[u8]) -> String {
+    let mut out = String::with_capacity((usize::from(width) + 1) * usize::from(height));
+    for row in pixels.chunks(usize::from(width)).take(usize::from(height)) {
+        out.extend(row.iter().map(|&p| ['.', '#', '+', '@'][usize::from(p & 0b11)]));
+        out.push('\n');
+    }
+    out
+}
+
+impl<W: Write> GifWriter<W> {
+    /// The GIF is `width * scale` by `height * scale` pixels, which must
+    /// fit in 65535 by 65535. `fps` is how many frames
+    /// [`GifWriter::add_frame`] and [`GifWriter::skip_frame`] are called for
+    /// per second.
+    pub fn new(
+        w: W,
+        (width, height): (u16, u16),
+        palette: &[[u8; 3]; 4],
+        scale: u16,
+        fps: u32,
+    ) -> io::Result<Self> {
+        let scale = scale.max(1);
+        let size = match (width.checked_mul(scale), height.checked_mul(scale)) {
+            (Some(width), Some(height)) => (width, height),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the scaled GIF is bigger than 65535 pixels",
+                ))
+            }
+        };
+        Ok(Self {
+            w,
+            palette: *palette,
+            size,
+            fps: u64::from(fps.max(1)),
+            started: false,
+            pending: None,
+            frames: 0,
+        })
+    }
+
+    /// Adds the screen of one frame, where the program drew something.
+    ///
+    /// A screen that didn't change after all makes the previous GIF frame
+    /// last longer. Screens replaced before 2 hundredths of a second are
+    /// dropped, browsers slowing down shorter frames.
+    pub fn add_frame(&mut self, size: (u16, u16), pixels: &[u8]) -> io::Result<()> {
+        let screen = stretch(size, pixels, self.size);
+        let frame = self.frames;
+        self.frames += 1;
+        let shown = self.pending.as_ref().map(|&(_, start)| self.delay(start, frame));
+        match (&mut self.pending, shown) {
+            (Some((pending, _)), _) if *pending == screen => {}
+            (Some((pending, _)), Some(shown)) if shown < GIF_MIN_DELAY => *pending = screen,
+            _ => {
+                self.flush(frame)?;
+                self.pending = Some((screen, frame));
+            }
+        }
+        Ok(())
+    }
+
+    /// A frame where nothing was drawn, the previous screen lasts longer.
+    pub fn skip_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Writes the last screen and the end of the GIF.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush(self.frames)?;
+        self.start()?;
+        self.w.write_all(&[0x3B])?;
+        Ok(self.w)
+    }
+
+    // Hundredths of a second between two frames.
+    fn delay(&self, start: u64, end: u64) -> u64 {
+        let centis = |frame: u64| (frame * 100 + self.fps / 2) / self.fps;
+        centis(end) - centis(start)
+    }
+
+    // Writes the header, once.
+    fn start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        self.w.write_all(b"GIF89a")?;
+        self.w.write_all(&self.size.0.to_le_bytes())?;
+        self.w.write_all(&self.size.1.to_le_bytes())?;
+        // A global table of 4 colours, no background, square pixels.
+        self.w.write_all(&[0x91, 0, 0])?;
+        self.w.write_all(&self.palette.concat())?;
+        // Loops forever.
+        self.w.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")
+    }
+
+    // Writes the pending screen, shown until `frame`.
+    fn flush(&mut self, frame: u64) -> io::Result<()> {
+        let (screen, start) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        self.start()?;
+        let delay = self.delay(start, frame).min(u64::from(u16::MAX)) as u16;
+        self.w.write_all(&[0x21, 0xF9, 4, 0])?;
+        self.w.write_all(&delay.to_le_bytes())?;
+        self.w.write_all(&[0, 0])?;
+        self.w.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.w.write_all(&self.size.0.to_le_bytes())?;
+        self.w.write_all(&self.size.1.to_le_bytes())?;
+        self.w.write_all(&[0, GIF_CODE_SIZE])?;
+        for block in lzw_encode(&screen).chunks(255) {
+            self.w.write_all(&[block.len() as u8])?;
+            self.w.write_all(block)?;
+        }
+        self.w.write_all(&[0])
+    }
+}
+
+// Resizes the pixels to `to` by repeating or skipping pixels.
+fn stretch(from: (u16, u16), pixels: &[u8], to: (u16, u16)) -> Vec<u8> {
+    let (from_width, from_height) = (usize::from(from.0), usize::from(from.1));
+    let (to_width, to_height) = (usize::from(to.0), usize::from(to.1));
+    let mut out = Vec::with_capacity(to_width * to_height);
+    for y in 0..to_height {
+        let row = y * from_height / to_height * from_width;
+        for x in 0..to_width {
+            let pixel = pixels.get(row + x * from_width / to_width).copied().unwrap_or(0);
+            out.push(pixel & 0b11);
+        }
+    }
+    out
+}
+
+// The LZW codes of GIF image data, packed from the lowest bit.
+fn lzw_encode(indices: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << GIF_CODE_SIZE;
+    let end = clear + 1;
+    let mut out = Vec::new();
+    let (mut bits, mut nbits) = (0u32, 0u8);
+    let mut emit = |code: u16, width: u8| {
+        bits |= u32::from(code) << nbits;
+        nbits += width;
+        while nbits >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            nbits -= 8;
+        }
+    };
+
+    let mut table = HashMap::new();
+    let (mut next, mut width) = (end + 1, GIF_CODE_SIZE + 1);
+    emit(clear, width);
+    let mut prefix = None;
+    for &index in indices {
+        let code = match prefix {
+            None => {
+                prefix = Some(u16::from(index));
+                continue;
+            }
+            Some(code) => code,
+        };
+        if let Some(&longer) = table.get(&(code, index)) {
+            prefix = Some(longer);
+            continue;
+        }
+        emit(code, width);
+        if next >= GIF_MAX_CODE {
+            emit(clear, width);
+            table.clear();
+            (next, width) = (end + 1, GIF_CODE_SIZE + 1);
+        } else {
+            table.insert((code, index), next);
+            next += 1;
+            if next > 1 << width {
+                width += 1;
+            }
+        }
+        prefix = Some(u16::from(index));
+    }
+    if let Some(code) = prefix {
+        emit(code, width);
+    }
+    emit(end, width);
+    if nbits > 0 {
+        out.push(bits as u8);
+    }
+    out
+}
+
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    let crc = crc32(crc32(!0, kind), data);
+    w.write_all(&(!crc).to_be_bytes())
+}
+
+// A zlib stream of uncompressed deflate blocks, which every decoder reads.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+// Updates `crc`, without the final inversion.
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
@@ -0,0 +1,181 @@
+use super::*;
+
+// A 10x2 screen with the corners lit.
+const SIZE: (u16, u16) = (10, 2);
+const PIXELS: [u8; 20] = [1, 0, 0, 0, 0, 0, 0, 0, 0, 2, 3, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+
+#[test]
+fn test_pbm() {
+    let mut out = Vec::new();
+    write_pbm(&mut out, SIZE, &PIXELS).unwrap();
+    assert_eq!(out, b"P4\n10 2\n\x80\x40\x80\x40");
+}
+
+#[test]
+fn test_ascii_art() {
+    assert_eq!(ascii_art(SIZE, &PIXELS), "#........+\n@........#\n");
+}
+
+#[test]
+fn test_png() {
+    let mut out = Vec::new();
+    write_png(&mut out, SIZE, &PIXELS, &PALETTE, 2).unwrap();
+    assert_eq!(out[..8], PNG_SIGNATURE);
+    // IHDR: 20x4, 8-bit indexed.
+    assert_eq!(&out[12..16], b"IHDR");
+    assert_eq!(out[16..29], [0, 0, 0, 20, 0, 0, 0, 4, 8, 3, 0, 0, 0]);
+    assert_eq!(out[out.len() - 12..], [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+
+    // The stored deflate block holds the scaled rows.
+    let idat = out.windows(4).position(|w| w == b"IDAT").unwrap() + 4;
+    let raw = &out[idat + 7..idat + 7 + 4 * 21];
+    assert_eq!(raw[..3], [0, 1, 1]);
+    assert_eq!(raw[21..24], [0, 1, 1]);
+    assert_eq!(raw[42 + 19..42 + 21], [1, 1]);
+    assert_eq!(raw[42 + 1], 3);
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(!crc32(!0, b"123456789"), 0xCBF4_3926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+}
+
+#[test]
+fn test_rgba() {
+    let rgba = to_rgba(SIZE, &PIXELS, &PALETTE, 3);
+    assert_eq!(rgba.len(), 30 * 6 * 4);
+    assert_eq!(rgba[..12], [0, 255, 0, 255, 0, 255, 0, 255, 0, 255, 0, 255]);
+    assert_eq!(rgba[12..16], [0, 0, 0, 255]);
+    // The last pixel of the third row still belongs to the first row.
+    assert_eq!(rgba[3 * 120 - 4..3 * 120], [255, 102, 0, 255]);
+    assert_eq!(rgba[3 * 120..3 * 120 + 4], [255, 255, 255, 255]);
+
+    let mut out = Vec::new();
+    write_rgba_png(&mut out, (30, 6), &rgba).unwrap();
+    assert_eq!(out[16..29], [0, 0, 0, 30, 0, 0, 0, 6, 8, 6, 0, 0, 0]);
+}
+
+// Decodes GIF image data, growing the codes like every decoder does.
+fn lzw_decode(data: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << GIF_CODE_SIZE;
+    let mut table: Vec<Vec<u8>> = Vec::new();
+    let mut width = GIF_CODE_SIZE + 1;
+    let (mut pos, mut out, mut prev): (usize, Vec<u8>, Option<Vec<u8>>) = (0, Vec::new(), None);
+    loop {
+        let code = (0..width).fold(0u16, |code, i| {
+            let bit = pos + usize::from(i);
+            code | u16::from(data[bit / 8] >> (bit % 8) & 1) << i
+        });
+        pos += usize::from(width);
+        if code == clear {
+            table = (0..clear).map(|i| vec![i as u8]).collect();
+            table.extend([Vec::new(), Vec::new()]);
+            width = GIF_CODE_SIZE + 1;
+            prev = None;
+            continue;
+        }
+        if code == clear + 1 {
+            return out;
+        }
+        let entry = match (table.get(usize::from(code)), &prev) {
+            (Some(entry), _) => entry.clone(),
+            (None, Some(prev)) => [&prev[..], &prev[..1]].concat(),
+            (None, None) => panic!("bad code {}", code),
+        };
+        if let Some(prev) = prev {
+            table.push([&prev[..], &entry[..1]].concat());
+            if table.len() == 1 << width && width < 12 {
+                width += 1;
+            }
+        }
+        out.extend_from_slice(&entry);
+        prev = Some(entry);
+    }
+}
+
+#[test]
+fn test_lzw() {
+    let inputs = [
+        vec![],
+        vec![3],
+        vec![1; 10000],
+        (0..20000u32).map(|i| (i * i / 7 % 4) as u8).collect::<Vec<_>>(),
+    ];
+    for input in inputs {
+        assert_eq!(lzw_decode(&lzw_encode(&input)), input);
+    }
+}
+
+// The delay and the pixels of each image of a GIF.
+fn gif_images(gif: &[u8]) -> Vec<(u16, Vec<u8>)> {
+    let sub_blocks = |pos: &mut usize| {
+        let mut data = Vec::new();
+        while gif[*pos] != 0 {
+            let len = usize::from(gif[*pos]);
+            data.extend_from_slice(&gif[*pos + 1..*pos + 1 + len]);
+            *pos += 1 + len;
+        }
+        *pos += 1;
+        data
+    };
+    let (mut pos, mut delay, mut images) = (13 + 12, 0, Vec::new());
+    loop {
+        match gif[pos] {
+            0x21 => {
+                let label = gif[pos + 1];
+                pos += 2;
+                let data = sub_blocks(&mut pos);
+                if label == 0xF9 {
+                    delay = u16::from_le_bytes([data[1], data[2]]);
+                }
+            }
+            0x2C => {
+                assert_eq!(gif[pos + 9..pos + 11], [0, GIF_CODE_SIZE]);
+                pos += 11;
+                images.push((delay, lzw_decode(&sub_blocks(&mut pos))));
+            }
+            0x3B => return images,
+            b => panic!("unexpected block {:#X}", b),
+        }
+    }
+}
+
+#[test]
+fn test_gif() {
+    // At 100 fps, a frame lasts a hundredth of a second.
+    let mut gif = GifWriter::new(Vec::new(), SIZE, &PALETTE, 2, 100).unwrap();
+    let blank = [0; 20];
+    // The blank screen shows for 1 frame and is dropped.
+    gif.add_frame(SIZE, &PIXELS).unwrap();
+    gif.add_frame(SIZE, &PIXELS).unwrap();
+    gif.skip_frame();
+    gif.add_frame(SIZE, &blank).unwrap();
+    gif.add_frame(SIZE, &PIXELS).unwrap();
+    // Twice as big, it fills the GIF all the same.
+    for _ in 0..5 {
+        gif.add_frame((20, 4), &[1; 80]).unwrap();
+    }
+    let out = gif.finish().unwrap();
+
+    assert_eq!(out[..13], *b"GIF89a\x14\x00\x04\x00\x91\x00\x00");
+    assert_eq!(out[13..25], PALETTE.concat());
+    let images = gif_images(&out);
+    let delays = images.iter().map(|(delay, _)| *delay).collect::<Vec<_>>();
+    assert_eq!(delays, [3, 2, 5]);
+    assert_eq!(images[0].1, images[1].1);
+    assert_eq!(images[2].1, [1; 80]);
+
+    // The first screen is scaled twice.
+    let indices = &images[0].1;
+    assert_eq!(indices[..3], [1, 1, 0]);
+    assert_eq!(indices[18..20], [2, 2]);
+    assert_eq!(indices[20..22], [1, 1]);
+    assert_eq!(indices[40..42], [3, 3]);
+
+    let empty = GifWriter::new(Vec::new(), SIZE, &PALETTE, 1, 60).unwrap().finish().unwrap();
+    assert!(gif_images(&empty).is_empty());
+
+    let too_big = GifWriter::new(Vec::new(), (128, 64), &PALETTE, 512, 60);
+    assert_eq!(too_big.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+}
@@ -6,7 +6,7 @@ const KEYCODE_SIZE: usize = 16;
 
 #[rustfmt::skip]
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum KeyCode {
     K0 = 0, K1, K2, K3,
     K4, K5, K6, K7,
@@ -27,16 +27,23 @@ impl KeyState {
 
     #[must_use]
     pub fn any(&self) -> Option<u8> {
-        self.0.iter().position(|&k| k != false).map(|x| x as u8)
+        self.0.iter().position(|&k| k).map(|x| x as u8)
     }
 
+    // Like the COSMAC VIP, only the low nibble of `x` selects the key.
     #[must_use]
     pub fn key_down(&self, x: u8) -> bool {
-        let kc = KeyCode::try_from(x).unwrap();
+        let kc = KeyCode::try_from(x & 0xF).unwrap();
         self[kc]
     }
 }
 
+impl Default for KeyState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PartialEq<u8> for KeyCode {
     fn eq(&self, other: &u8) -> bool {
         *self as u8 == *other
@@ -47,7 +54,7 @@ impl TryFrom<u8> for KeyCode {
     type Error = ();
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         if usize::from(value) < KEYCODE_SIZE {
-            Ok(unsafe { mem::transmute(value) })
+            Ok(unsafe { mem::transmute::<u8, KeyCode>(value) })
         } else {
             Err(())
         }
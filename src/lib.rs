@@ -1,16 +1,50 @@
 mod alloc;
+mod asm;
+mod audio;
+mod coverage;
 mod cpu;
+mod debugger;
+mod disasm;
 mod display;
+mod gdb;
+mod image;
 mod keypad;
 mod memory;
+mod movie;
 mod num;
+mod octo;
 mod opcode;
+mod profile;
+mod quirks;
+mod random;
 mod register;
+mod rewind;
 mod stack;
 mod timer;
+mod trace;
 
-pub use cpu::{Cpu, CpuState};
+pub use asm::{assemble, assemble_file, AsmError, AsmErrorKind};
+pub use audio::{write_wav, Buzzer};
+pub use coverage::Coverage;
+pub use cpu::{CodeWrite, Cpu, CpuError, CpuState, StateError};
+pub use debugger::{Access, Condition, Debugger, StopReason};
+pub use disasm::{analyse, disassemble, Analysis, Instruction};
 pub use display::HEIGHT as DISPLAY_HEIGHT;
+pub use display::HIRES_HEIGHT as HIRES_DISPLAY_HEIGHT;
+pub use display::HIRES_WIDTH as HIRES_DISPLAY_WIDTH;
 pub use display::WIDTH as DISPLAY_WIDTH;
+pub use gdb::GdbStub;
+pub use image::{ascii_art, to_rgba, write_pbm, write_png, write_rgba_png, GifWriter, PALETTE};
 pub use keypad::{KeyCode, KeyState};
 pub use memory::LoadError;
+pub use movie::{InputEvent, Movie, MovieError};
+pub use octo::{compile_octo, OctoError, OctoErrorKind};
+pub use opcode::{Opcode, OpcodeKind};
+pub use profile::{AddressCount, Profile, Profiler, Subroutine};
+pub use quirks::{Platform, Quirks};
+pub use random::{Random, RandomSource};
+pub use rewind::Rewinder;
+pub use trace::{
+    read_binary_trace, BinaryTrace, TextTrace, TraceEntry, TraceError, TraceFormat, TraceRing,
+    TraceSink,
+};
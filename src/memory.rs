@@ -3,92 +3,284 @@ mod tests;
 
 use std::error::Error;
 use std::fmt;
+use std::ops::Range;
 
 use crate::alloc::boxed_zeroed_memory;
-use crate::opcode::Opcode;
+use crate::coverage::{Coverage, EXECUTED, MODIFIED, READ, WRITTEN};
+use crate::cpu::CpuError;
+use crate::opcode::{Opcode, LONG_INSTRUCTION_PREFIX};
 
 const ROM_START_ADDR: u16 = 0x200;
 pub(crate) const RAM_SIZE: u16 = 1 << 12;
+// XO-CHIP extends the address space to the whole 16 bits.
+pub(crate) const EXTENDED_RAM_SIZE: usize = 1 << 16;
 // const DISPLAY_REFRESH_START_ADDR: u16 = 0xF00;
 const CALL_STACK_START_ADDR: u16 = 0xEA0;
 const INSTRUCTION_SIZE: u16 = 2;
 const FONTS_SET_ADDR: u16 = 0x0050;
-const AVAILABLE_STORAGE: u16 = RAM_SIZE - ROM_START_ADDR;
+const BIG_FONTS_SET_ADDR: u16 = FONTS_SET_ADDR + FONTS_SET_LEN as u16;
 
 /* Definitions */
 
 pub struct Memory {
     pub pc: ProgramCounter,
     pub i: I,
-    ram: Box<[u8; RAM_SIZE as usize]>,
+    ram: Box<[u8; EXTENDED_RAM_SIZE]>,
+    extended: bool,
+    coverage: Option<Box<Coverage>>,
+    // Bit set for every byte fetched as an instruction since it was last
+    // written.
+    executed: Box<[u64; EXTENDED_RAM_SIZE / 64]>,
+    // Code overwritten by the last instruction: start and length.
+    code_write: Option<(u16, u16)>,
 }
 
 /// Memory addresses containing the data for a given sprite (graphics).
 pub struct I(u16);
 
+// How the bytes at the program counter were marked before a fetch, to undo
+// it when the instruction faults. Instructions are at most 4 bytes.
+pub(crate) struct FetchMarks {
+    pc: usize,
+    executed: [bool; 4],
+    coverage: [u8; 4],
+}
+
 #[derive(Copy, Clone)]
 pub struct ProgramCounter(u16);
 
 /* Implementations */
 
 pub enum LoadError {
-    RomTooBig(usize),
+    RomTooBig { size: usize, max: usize },
 }
 
 impl Memory {
-    pub fn new() -> Self {
+    pub fn new(extended: bool) -> Self {
         let mut ram = boxed_zeroed_memory();
-        const BEGIN: usize = FONTS_SET_ADDR as usize;
-        const END: usize = BEGIN + FONTS_SET_LEN;
-        ram[BEGIN..END].copy_from_slice(&FONTS_SET);
-        Self { pc: ProgramCounter(ROM_START_ADDR), i: I(FONTS_SET_ADDR), ram }
+        load_fonts(&mut ram[..]);
+        Self {
+            pc: ProgramCounter(ROM_START_ADDR),
+            i: I(FONTS_SET_ADDR),
+            ram,
+            extended,
+            coverage: None,
+            executed: Box::new([0; EXTENDED_RAM_SIZE / 64]),
+            code_write: None,
+        }
+    }
+
+    /// Switches between the 4 KiB CHIP-8 and the 64 KiB XO-CHIP address space.
+    pub fn set_extended(&mut self, extended: bool) {
+        self.extended = extended;
+    }
+
+    pub fn size(&self) -> usize {
+        if self.extended {
+            EXTENDED_RAM_SIZE
+        } else {
+            usize::from(RAM_SIZE)
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram[..self.size()]
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        let size = self.size();
+        &mut self.ram[..size]
     }
 
     pub fn reset(&mut self) {
         self.pc = ProgramCounter(ROM_START_ADDR);
         self.i = I(FONTS_SET_ADDR);
         self.ram.fill(0);
-        const BEGIN: usize = FONTS_SET_ADDR as usize;
-        const END: usize = BEGIN + FONTS_SET_LEN;
-        self.ram[BEGIN..END].copy_from_slice(&FONTS_SET);
+        load_fonts(&mut self.ram[..]);
+        self.executed.fill(0);
+        self.code_write = None;
+    }
+
+    /// Starts or stops recording how every byte is used. What was recorded
+    /// is kept across resets.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        match enabled {
+            true => self.coverage = self.coverage.take().or_else(|| Some(Box::default())),
+            false => self.coverage = None,
+        }
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_deref()
+    }
+
+    fn cover(&mut self, range: Range<usize>, access: u8) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.mark(range, access);
+        }
+    }
+
+    /// Code that the last instruction overwrote, as its first address and
+    /// length, if it wrote over bytes run as instructions since they were
+    /// last written.
+    pub fn take_code_write(&mut self) -> Option<(u16, u16)> {
+        self.code_write.take()
+    }
+
+    pub(crate) fn fetch_marks(&self) -> FetchMarks {
+        let pc = usize::from(self.pc.0);
+        let addr = |n: usize| (pc + n) % EXTENDED_RAM_SIZE;
+        FetchMarks {
+            pc,
+            executed: std::array::from_fn(|n| self.is_executed(addr(n))),
+            coverage: std::array::from_fn(|n| {
+                self.coverage.as_ref().map_or(0, |c| c.flags(addr(n)))
+            }),
+        }
+    }
+
+    pub(crate) fn restore_fetch_marks(&mut self, marks: &FetchMarks) {
+        for n in 0..marks.executed.len() {
+            let addr = (marks.pc + n) % EXTENDED_RAM_SIZE;
+            let bit = 1 << (addr % 64);
+            match marks.executed[n] {
+                true => self.executed[addr / 64] |= bit,
+                false => self.executed[addr / 64] &= !bit,
+            }
+            if let Some(coverage) = &mut self.coverage {
+                coverage.set_flags(addr, marks.coverage[n]);
+            }
+        }
+        self.code_write = None;
+    }
+
+    fn is_executed(&self, addr: usize) -> bool {
+        self.executed[addr / 64] & 1 << (addr % 64) != 0
+    }
+
+    // Bytes being written aren't code anymore, until they are run again.
+    fn write_over(&mut self, range: Range<usize>) {
+        let first = range.clone().find(|&addr| self.is_executed(addr));
+        let last = range.clone().rev().find(|&addr| self.is_executed(addr));
+        if let (Some(first), Some(last)) = (first, last) {
+            self.code_write = Some((first as u16, (last - first + 1) as u16));
+            self.cover(first..last + 1, MODIFIED);
+            for addr in range {
+                self.executed[addr / 64] &= !(1 << (addr % 64));
+            }
+        }
+    }
+
+    /// Whole addressable memory.
+    pub fn as_slice(&self) -> &[u8] {
+        self.ram()
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.ram_mut()
+    }
+
+    /// Restores the state saved from `pc`, `i` and `as_slice`.
+    pub fn restore(&mut self, pc: u16, i: u16, ram: &[u8]) {
+        self.pc = ProgramCounter(pc);
+        self.i = I(i);
+        self.ram.fill(0);
+        self.ram[..ram.len()].copy_from_slice(ram);
+        self.executed.fill(0);
+        self.code_write = None;
     }
 
-    pub fn store_bcd(&mut self, x: u8) {
-        let i = self.i.0 as usize;
+    pub fn store_bcd(&mut self, x: u8) -> Result<(), CpuError> {
         let abc = bcd(x);
-        self.ram[i..(i + 3)].copy_from_slice(&abc[..]);
+        let range = self.bounds(self.i.0, abc.len())?;
+        self.cover(range.clone(), WRITTEN);
+        self.write_over(range.clone());
+        self.ram_mut()[range].copy_from_slice(&abc[..]);
+        Ok(())
     }
 
-    pub fn read_bytes_from_i(&self, n: u8) -> &[u8] {
-        let i = self.i.0 as usize;
-        let (begin, end) = (i, i + n as usize);
-        &self.ram[begin..end]
+    pub fn read_bytes_from_i(&mut self, n: u8) -> Result<&[u8], CpuError> {
+        let range = self.bounds(self.i.0, usize::from(n))?;
+        self.cover(range.clone(), READ);
+        Ok(&self.ram()[range])
     }
 
-    pub fn save_bytes_to_i(&mut self, bytes: &[u8]) {
-        let i = self.i.0 as usize;
-        let (begin, end) = (i, i + bytes.len());
-        self.ram[begin..end].copy_from_slice(bytes);
+    pub fn save_bytes_to_i(&mut self, bytes: &[u8]) -> Result<(), CpuError> {
+        let range = self.bounds(self.i.0, bytes.len())?;
+        self.cover(range.clone(), WRITTEN);
+        self.write_over(range.clone());
+        self.ram_mut()[range].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn bounds(&self, addr: u16, len: usize) -> Result<Range<usize>, CpuError> {
+        let (begin, end) = (usize::from(addr), usize::from(addr) + len);
+        if end > self.size() {
+            Err(CpuError::MemoryOutOfBounds { addr, len })
+        } else {
+            Ok(begin..end)
+        }
     }
 
     pub fn load_program(&mut self, text: &[u8]) -> Result<(), LoadError> {
         let len = text.len();
-        if len >= AVAILABLE_STORAGE as usize {
-            Err(LoadError::RomTooBig(len))
+        let max = self.size() - usize::from(ROM_START_ADDR);
+        if len >= max {
+            Err(LoadError::RomTooBig { size: len, max })
         } else {
             let (begin, end) = (ROM_START_ADDR as usize, ROM_START_ADDR as usize + len);
-            self.ram[begin..end].copy_from_slice(text);
+            self.ram_mut()[begin..end].copy_from_slice(text);
             Ok(())
         }
     }
 
-    pub fn fetch(&mut self) -> Opcode {
+    pub fn fetch(&mut self) -> Result<Opcode, CpuError> {
+        let opcode = self.peek()?;
         let pc = usize::from(self.pc.0);
-        self.pc.0 += INSTRUCTION_SIZE;
+        self.cover(pc..pc + usize::from(opcode.size()), EXECUTED);
+        for addr in pc..pc + usize::from(opcode.size()) {
+            self.executed[addr / 64] |= 1 << (addr % 64);
+        }
+        self.pc.0 = self.pc.0.wrapping_add(opcode.size());
+        Ok(opcode)
+    }
+
+    /// Reads the instruction at the program counter without moving it.
+    pub fn peek(&self) -> Result<Opcode, CpuError> {
+        let pc = self.pc.0;
+        let op = self.read_word(pc).ok_or(CpuError::PcOutOfRange { addr: pc })?;
+        if op == LONG_INSTRUCTION_PREFIX {
+            let next = pc.wrapping_add(INSTRUCTION_SIZE);
+            let operand = self.read_word(next).ok_or(CpuError::PcOutOfRange { addr: next })?;
+            Ok(Opcode::with_operand(op, operand))
+        } else {
+            Ok(Opcode::new(op))
+        }
+    }
+
+    /// Jumps to `addr`, which must be inside the program area.
+    pub fn jump(&mut self, addr: u16) -> Result<(), CpuError> {
+        let end = if self.extended { u16::MAX } else { CALL_STACK_START_ADDR };
+        if addr < ROM_START_ADDR || addr > end {
+            return Err(CpuError::PcOutOfRange { addr });
+        }
+        self.pc.0 = addr;
+        Ok(())
+    }
+
+    /// Skips the next instruction, which may be the 4 bytes long `F000 NNNN`.
+    pub fn skip_next(&mut self) {
+        let size = match self.read_word(self.pc.0) {
+            Some(LONG_INSTRUCTION_PREFIX) => 2 * INSTRUCTION_SIZE,
+            _ => INSTRUCTION_SIZE,
+        };
+        self.pc.0 = self.pc.0.wrapping_add(size);
+    }
 
-        let mut op = [0; INSTRUCTION_SIZE as usize];
-        op.copy_from_slice(&self.ram[pc..(pc + INSTRUCTION_SIZE as usize)]);
-        Opcode::new(u16::from_be_bytes(op))
+    fn read_word(&self, addr: u16) -> Option<u16> {
+        let addr = usize::from(addr);
+        let bytes = self.ram().get(addr..(addr + INSTRUCTION_SIZE as usize))?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
     }
 }
 
@@ -97,16 +289,10 @@ impl ProgramCounter {
         self.0
     }
 
-    pub fn set(&mut self, addr: u16) {
-        assert!(addr >= ROM_START_ADDR);
-        assert!(addr <= CALL_STACK_START_ADDR);
+    pub fn store(&mut self, addr: u16) {
         self.0 = addr;
     }
 
-    pub fn skip_next(&mut self) {
-        self.0 += INSTRUCTION_SIZE;
-    }
-
     #[cfg(FALSE)]
     pub fn decrease(&mut self) {
         self.0 -= INSTRUCTION_SIZE;
@@ -114,30 +300,31 @@ impl ProgramCounter {
 }
 
 impl I {
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// Returns `true` if I now points past the addressable memory, of
+    /// `memory_size` bytes.
     #[must_use]
-    #[cfg(feature = "amiga")]
-    pub fn add_assign(&mut self, value: u8) -> bool {
-        self.0 += u16::from(value);
-        if cfg!(feature = "amiga") {
-            self.0 < RAM_SIZE
-        } else {
-            assert!(self.0 < RAM_SIZE);
-            false
-        }
+    pub fn add_assign(&mut self, value: u8, memory_size: usize) -> bool {
+        let sum = usize::from(self.0) + usize::from(value);
+        self.0 = sum as u16;
+        sum >= memory_size
     }
 
-    pub fn set_to_builtin_fonts_addr(&mut self, x: u8) {
-        let x = if cfg!(feature = "original") {
-            x & 0xF
-        } else {
-            assert!((x as u16) < TOTAL_HEXI);
-            x
-        };
+    // Without masking, digits past F point to whatever follows the fonts.
+    pub fn set_to_builtin_fonts_addr(&mut self, x: u8, masked: bool) {
+        let x = if masked { x & 0xF } else { x };
         self.0 = FONTS_SET_ADDR + EACH_FONT_SIZE * (x as u16);
     }
 
+    pub fn set_to_builtin_big_fonts_addr(&mut self, x: u8, masked: bool) {
+        let x = if masked { x & 0xF } else { x };
+        self.0 = BIG_FONTS_SET_ADDR + EACH_BIG_FONT_SIZE * (x as u16);
+    }
+
     pub fn store(&mut self, addr: u16) {
-        assert!(addr < RAM_SIZE);
         self.0 = addr;
     }
 }
@@ -146,8 +333,8 @@ impl Error for LoadError {}
 impl fmt::Debug for LoadError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LoadError::RomTooBig(n) => {
-                write!(f, "loaded rom is too big: {} > {}", n, AVAILABLE_STORAGE)
+            LoadError::RomTooBig { size, max } => {
+                write!(f, "loaded rom is too big: {} > {}", size, max)
             }
         }
     }
@@ -158,6 +345,13 @@ impl fmt::Display for LoadError {
     }
 }
 
+fn load_fonts(ram: &mut [u8]) {
+    let begin = usize::from(FONTS_SET_ADDR);
+    ram[begin..begin + FONTS_SET_LEN].copy_from_slice(&FONTS_SET);
+    let begin = usize::from(BIG_FONTS_SET_ADDR);
+    ram[begin..begin + BIG_FONTS_SET_LEN].copy_from_slice(&BIG_FONTS_SET);
+}
+
 fn bcd(x: u8) -> [u8; 3] {
     [x / 100, x / 10 % 10, x % 10]
 }
@@ -184,3 +378,25 @@ pub(crate) static FONTS_SET: [u8; FONTS_SET_LEN] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+
+const EACH_BIG_FONT_SIZE: u16 = 10;
+const BIG_FONTS_SET_LEN: usize = (EACH_BIG_FONT_SIZE * TOTAL_HEXI) as usize;
+// SUPER-CHIP 8x10 hexadecimal digits: 0-9A-F
+static BIG_FONTS_SET: [u8; BIG_FONTS_SET_LEN] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
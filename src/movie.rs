@@ -0,0 +1,221 @@
+//! Input movies: the key presses of a session, to play it back exactly.
+
+#[cfg(test)]
+mod tests;
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use crate::cpu::{Cpu, CpuState};
+use crate::keypad::KeyCode;
+use crate::num::{write_varint, ReadError, Reader};
+use crate::quirks::Quirks;
+
+const MAGIC: &[u8; 4] = b"C8MV";
+const VERSION: u8 = 1;
+
+/// Everything a session depends on besides the ROM: the quirks, the seed of
+/// the random numbers and every key press and release.
+///
+/// A session is recorded with a `Cpu` made by [`Movie::cpu`], calling
+/// [`Movie::record`] instead of [`Cpu::set_key_state`]. Playing it back
+/// means calling [`Movie::play`] at the start of every frame with another
+/// `Cpu` made by [`Movie::cpu`], running the frames the same way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    quirks: Quirks,
+    seed: u64,
+    events: Vec<InputEvent>,
+}
+
+/// A key pressed or released before the `frame`th frame ran, after
+/// `cycle` instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub cycle: u64,
+    pub key: KeyCode,
+    pub pressed: bool,
+}
+
+pub enum MovieError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Invalid(&'static str),
+    /// The `Cpu` playing the movie had run another number of instructions
+    /// than the recorded one when the event came.
+    Desync {
+        frame: u64,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl Movie {
+    pub fn new(quirks: Quirks, seed: u64) -> Self {
+        Self { quirks, seed, events: Vec::new() }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    /// A new `Cpu` in the state the movie starts from, waiting for the ROM.
+    pub fn cpu(&self) -> Cpu {
+        Cpu::with_seed(self.quirks, self.seed)
+    }
+
+    /// Presses or releases `key` on `cpu` and records it for `frame`.
+    ///
+    /// Like the frontends do, pressing a key resumes a `Cpu` paused by
+    /// `LD Vx, K`. Frames must be recorded in order.
+    pub fn record(&mut self, frame: u64, cpu: &mut Cpu, key: KeyCode, pressed: bool) {
+        debug_assert!(self.events.last().is_none_or(|e| e.frame <= frame));
+        press(cpu, key, pressed);
+        self.events.push(InputEvent { frame, cycle: cpu.cycles(), key, pressed });
+    }
+
+    /// Replays on `cpu` the keys recorded for `frame`.
+    ///
+    /// Fails when `cpu` didn't run the same instructions as the recording,
+    /// after which the rest of the movie can't be trusted.
+    pub fn play(&self, frame: u64, cpu: &mut Cpu) -> Result<(), MovieError> {
+        let start = self.events.partition_point(|e| e.frame < frame);
+        for event in self.events[start..].iter().take_while(|e| e.frame == frame) {
+            if event.cycle != cpu.cycles() {
+                let (expected, found) = (event.cycle, cpu.cycles());
+                return Err(MovieError::Desync { frame, expected, found });
+            }
+            press(cpu, event.key, event.pressed);
+        }
+        Ok(())
+    }
+
+    /// How many frames the movie lasts: up to its last event.
+    pub fn frames(&self) -> u64 {
+        self.events.last().map_or(0, |e| e.frame + 1)
+    }
+
+    /// Serializes the movie.
+    ///
+    /// # Format
+    ///
+    /// Varints are LEB128, other numbers little endian.
+    ///
+    /// | Size   | Content                                                   |
+    /// |--------|-----------------------------------------------------------|
+    /// | 4      | Magic header `C8MV`                                       |
+    /// | 1      | Format version, currently 1                               |
+    /// | 1      | Quirks, one bit each, in the order of `Quirks` fields     |
+    /// | 8      | Seed of the random numbers                                |
+    /// | varint | Number of events                                          |
+    ///
+    /// Then for each event:
+    ///
+    /// | Size   | Content                                                   |
+    /// |--------|-----------------------------------------------------------|
+    /// | varint | Frames since the previous event                           |
+    /// | varint | Instructions since the previous event                     |
+    /// | 1      | Key in the low nibble, bit 7 set when pressed             |
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(self.quirks.to_bits());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        write_varint(&mut out, self.events.len() as u64);
+        let (mut frame, mut cycle) = (0, 0);
+        for event in &self.events {
+            write_varint(&mut out, event.frame - frame);
+            write_varint(&mut out, event.cycle - cycle);
+            out.push(event.key as u8 | u8::from(event.pressed) << 7);
+            (frame, cycle) = (event.frame, event.cycle);
+        }
+        out
+    }
+
+    /// Reads a movie written by [`Movie::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieError> {
+        let mut r = Reader::new(bytes);
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let quirks = Quirks::from_bits(r.u8()?);
+        let seed = r.u64_le()?;
+        let count = r.varint()?;
+        // Each event takes at least 3 bytes.
+        if count > (r.bytes.len() / 3) as u64 {
+            return Err(MovieError::Truncated);
+        }
+        let mut events = Vec::with_capacity(count as usize);
+        let (mut frame, mut cycle) = (0u64, 0u64);
+        for _ in 0..count {
+            frame = frame.checked_add(r.varint()?).ok_or(MovieError::Invalid("frame"))?;
+            cycle = cycle.checked_add(r.varint()?).ok_or(MovieError::Invalid("cycle"))?;
+            let b = r.u8()?;
+            if b & 0x70 != 0 {
+                return Err(MovieError::Invalid("key"));
+            }
+            let key = KeyCode::try_from(b & 0xF).unwrap();
+            events.push(InputEvent { frame, cycle, key, pressed: b & 0x80 != 0 });
+        }
+        if !r.bytes.is_empty() {
+            return Err(MovieError::Invalid("length"));
+        }
+        Ok(Self { quirks, seed, events })
+    }
+}
+
+fn press(cpu: &mut Cpu, key: KeyCode, pressed: bool) {
+    if let (true, CpuState::Paused) = (pressed, &cpu.state) {
+        cpu.state = CpuState::Running;
+    }
+    cpu.set_key_state(key, pressed);
+}
+
+impl From<ReadError> for MovieError {
+    fn from(e: ReadError) -> Self {
+        match e {
+            ReadError::Truncated => MovieError::Truncated,
+            ReadError::Invalid(what) => MovieError::Invalid(what),
+        }
+    }
+}
+
+impl Error for MovieError {}
+impl fmt::Debug for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => f.write_str("not a movie: bad magic header"),
+            MovieError::UnsupportedVersion(v) => {
+                write!(f, "unsupported movie version: {} != {}", v, VERSION)
+            }
+            MovieError::Truncated => f.write_str("movie is truncated"),
+            MovieError::Invalid(what) => write!(f, "invalid movie: bad {}", what),
+            MovieError::Desync { frame, expected, found } => write!(
+                f,
+                "movie out of sync at frame {}: {} instructions ran instead of {}",
+                frame, found, expected
+            ),
+        }
+    }
+}
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
@@ -0,0 +1,85 @@
+use super::*;
+use crate::asm::assemble;
+use crate::quirks::Platform;
+
+// Waits for a key, then adds random numbers while a key is held.
+const SOURCE: &str = "
+    start:
+        LD V0, K
+    held:
+        RND V1, 0xFF
+        ADD V2, V1
+        LD F, V2
+        DRW V3, V3, 5
+        SKNP V0
+        JP held
+        ADD V3, 1
+        JP start
+";
+
+const KEYS: [(u64, KeyCode, bool); 6] = [
+    (3, KeyCode::K5, true),
+    (5, KeyCode::KA, true),
+    (5, KeyCode::K5, false),
+    (9, KeyCode::KA, false),
+    (14, KeyCode::K1, true),
+    (15, KeyCode::K1, false),
+];
+
+fn run(movie: &mut Movie, record: bool) -> Result<Cpu, MovieError> {
+    let mut cpu = movie.cpu();
+    cpu.load_game(&assemble(SOURCE).unwrap()).unwrap();
+    for frame in 0..20 {
+        if record {
+            for &(_, key, pressed) in KEYS.iter().filter(|k| k.0 == frame) {
+                movie.record(frame, &mut cpu, key, pressed);
+            }
+        } else {
+            movie.play(frame, &mut cpu)?;
+        }
+        cpu.run_frame(7).unwrap();
+    }
+    Ok(cpu)
+}
+
+#[test]
+fn test_record_and_play() {
+    let mut movie = Movie::new(Platform::Cosmac.quirks(), 42);
+    let recorded = run(&mut movie, true).unwrap().save_state();
+    assert_eq!(movie.events().len(), KEYS.len());
+    assert_eq!(movie.frames(), 16);
+
+    let mut played = Movie::from_bytes(&movie.to_bytes()).unwrap();
+    assert_eq!(played, movie);
+    assert_eq!(run(&mut played, false).unwrap().save_state(), recorded);
+
+    // Another seed changes the screen, the movie still plays.
+    let mut other = Movie { seed: 7, ..movie.clone() };
+    assert_ne!(run(&mut other, false).unwrap().save_state(), recorded);
+}
+
+#[test]
+fn test_desync() {
+    let mut movie = Movie::new(Platform::Cosmac.quirks(), 42);
+    run(&mut movie, true).unwrap();
+    movie.events[1].cycle += 1;
+    let error = run(&mut movie, false).err().unwrap();
+    assert!(matches!(error, MovieError::Desync { frame: 5, .. }), "{}", error);
+}
+
+#[test]
+fn test_bad_bytes() {
+    let mut movie = Movie::new(Platform::XoChip.quirks(), u64::MAX);
+    let mut cpu = movie.cpu();
+    movie.record(1000, &mut cpu, KeyCode::KF, true);
+    let bytes = movie.to_bytes();
+    assert_eq!(Movie::from_bytes(&bytes).unwrap(), movie);
+    assert!(matches!(Movie::from_bytes(b"C8SS"), Err(MovieError::BadMagic)));
+    assert!(matches!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(MovieError::Truncated)));
+    let mut bad_key = bytes.clone();
+    *bad_key.last_mut().unwrap() |= 0x10;
+    assert!(matches!(Movie::from_bytes(&bad_key), Err(MovieError::Invalid("key"))));
+    let mut bad_version = bytes;
+    bad_version[4] = 2;
+    assert!(matches!(Movie::from_bytes(&bad_version), Err(MovieError::UnsupportedVersion(2))));
+}
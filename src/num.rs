@@ -8,6 +8,17 @@ pub struct BitIter {
     nth: u8,
 }
 
+/// Cursor over the bytes of a binary format, savestates, movies and traces.
+pub(crate) struct Reader<'a> {
+    pub bytes: &'a [u8],
+}
+
+/// Why a [`Reader`] failed, which becomes the error of the format.
+pub(crate) enum ReadError {
+    Truncated,
+    Invalid(&'static str),
+}
+
 /* Implementations */
 
 pub const fn to_4_be_nibles(op: u16) -> [u8; 4] {
@@ -21,6 +32,63 @@ pub const fn to_4_be_nibles(op: u16) -> [u8; 4] {
     ]
 }
 
+/// Appends `n` as an LEB128 varint: 7 bits per byte, low bits first.
+pub fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], ReadError> {
+        if self.bytes.len() < n {
+            return Err(ReadError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ReadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16_le(&mut self) -> Result<u16, ReadError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u16_be(&mut self) -> Result<u16, ReadError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32_le(&mut self) -> Result<u32, ReadError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64_le(&mut self) -> Result<u64, ReadError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads what [`write_varint`] wrote.
+    pub fn varint(&mut self) -> Result<u64, ReadError> {
+        let mut n = 0;
+        for shift in (0..64).step_by(7) {
+            let b = self.u8()?;
+            n |= u64::from(b & 0x7F) << shift;
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(ReadError::Invalid("varint"))
+    }
+}
+
 impl BitIter {
     const BITS: usize = 8;
     pub fn new(value: u8) -> Self {
@@ -22,3 +22,19 @@ fn test_4_nibbles() {
     let abcd = to_4_be_nibles(0x1234);
     assert_eq!(abcd, [1, 2, 3, 4]);
 }
+
+#[test]
+fn test_varint() {
+    let mut out = Vec::new();
+    for &n in &[0, 0x7F, 0x80, 300, u64::MAX] {
+        write_varint(&mut out, n);
+    }
+    let mut r = Reader::new(&out);
+    for &n in &[0, 0x7F, 0x80, 300, u64::MAX] {
+        assert_eq!(r.varint().ok(), Some(n));
+    }
+    assert!(matches!(r.varint(), Err(ReadError::Truncated)));
+
+    let mut r = Reader::new(&[0x80; 11]);
+    assert!(matches!(r.varint(), Err(ReadError::Invalid("varint"))));
+}
@@ -0,0 +1,922 @@
+//! Compiler for Octo, the high level assembly language most community
+//! CHIP-8 programs are written in.
+//!
+//! ```text
+//! :alias counter v3
+//! :const SPEED 2
+//! : main
+//!     i := smile
+//!     loop
+//!         counter += SPEED
+//!         while counter != 10
+//!         if counter > 4 begin
+//!             sprite v0 v1 2
+//!         else
+//!             v0 += 1
+//!         end
+//!     again
+//! : smile
+//!     0x66 0x3C
+//! ```
+//!
+//! Execution starts at `: main`. When anything comes before it, the
+//! program starts with a jump to `main`. `:calc` expressions are evaluated
+//! right to left, with no operator precedence, like in Octo.
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::opcode::{Opcode, OpcodeKind};
+
+const ROM_START_ADDR: u16 = 0x200;
+// Guards against macros that expand to themselves.
+const MAX_MACRO_EXPANSIONS: usize = 1 << 16;
+// Parentheses and unary operators nest in `:calc`, but not forever.
+const MAX_CALC_DEPTH: u32 = 256;
+
+/// Where an error was found and why. Lines and columns start at 1.
+pub struct OctoError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: OctoErrorKind,
+}
+
+pub enum OctoErrorKind {
+    UnexpectedEnd,
+    Expected { what: &'static str, found: String },
+    Undefined(String),
+    Redefined(String),
+    OutOfRange { value: i64, min: i64, max: i64 },
+    DivisionByZero,
+    Unmatched(String),
+    NoMain,
+    TooManyExpansions,
+    TooDeep,
+    ProgramTooBig,
+}
+
+/// Compiles Octo `source` into a ROM loaded at 0x200.
+pub fn compile_octo(source: &str) -> Result<Vec<u8>, OctoError> {
+    let mut tokens = tokenize(source);
+    tokens.reverse();
+    let end = match tokens.first() {
+        Some(last) => Token { text: String::new(), ..last.clone() },
+        None => Token { text: String::new(), line: 1, column: 1 },
+    };
+    let mut compiler = Compiler {
+        tokens,
+        end,
+        rom: Vec::new(),
+        here: ROM_START_ADDR,
+        started: false,
+        labels: HashMap::new(),
+        consts: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        expansions: 0,
+    };
+    compiler.run()?;
+    Ok(compiler.rom)
+}
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+enum Block {
+    If { jump: u16, token: Token },
+    Else { jump: u16, token: Token },
+    Loop { start: u16, breaks: Vec<u16>, token: Token },
+}
+
+// A label used before it is defined, patched at the end.
+struct Fixup {
+    addr: u16,
+    kind: FixupKind,
+    name: Token,
+}
+
+#[derive(Clone, Copy)]
+enum FixupKind {
+    // The low 12 bits of the instruction at `addr`.
+    Nnn,
+    // The 16-bit word at `addr`.
+    Long,
+    // The bytes of `:unpack`, the first holding a nibble.
+    UnpackHigh(u8),
+    UnpackLow,
+}
+
+#[derive(Clone, Copy)]
+enum Cmp {
+    Eq,
+    Ne,
+    Key,
+    NotKey,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+struct Compiler {
+    // Reversed, so that the next token is popped and macros are pushed.
+    tokens: Vec<Token>,
+    end: Token,
+    rom: Vec<u8>,
+    here: u16,
+    started: bool,
+    labels: HashMap<String, u16>,
+    consts: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    expansions: usize,
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        for word in code.split_whitespace() {
+            let column = word.as_ptr() as usize - code.as_ptr() as usize + 1;
+            tokens.push(Token { text: word.to_string(), line: i + 1, column });
+        }
+    }
+    tokens
+}
+
+fn literal(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.get(..2) {
+        Some("0x" | "0X") => i64::from_str_radix(&digits[2..], 16).ok()?,
+        Some("0b" | "0B") => i64::from_str_radix(&digits[2..], 2).ok()?,
+        _ if digits.starts_with(|c: char| c.is_ascii_digit()) => digits.parse().ok()?,
+        _ => return None,
+    };
+    Some(if negative { -value } else { value } as f64)
+}
+
+fn encode(kind: OpcodeKind) -> Vec<u8> {
+    let opcode = Opcode::encode(kind);
+    let mut bytes = opcode.as_u16().to_be_bytes().to_vec();
+    if opcode.size() == 4 {
+        bytes.extend_from_slice(&opcode.operand().to_be_bytes());
+    }
+    bytes
+}
+
+impl Compiler {
+    fn run(&mut self) -> Result<(), OctoError> {
+        while let Some(token) = self.tokens.pop() {
+            self.statement(token)?;
+        }
+        if let Some(block) = self.blocks.pop() {
+            let (Block::If { token, .. } | Block::Else { token, .. } | Block::Loop { token, .. }) =
+                block;
+            return Err(token.error(OctoErrorKind::Unmatched(token.text.clone())));
+        }
+        if !self.labels.contains_key("main") {
+            return Err(self.end.error(OctoErrorKind::NoMain));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let target = match self.labels.get(&fixup.name.text) {
+                Some(&target) => target,
+                None => {
+                    let name = fixup.name.text.clone();
+                    return Err(fixup.name.error(OctoErrorKind::Undefined(name)));
+                }
+            };
+            let i = usize::from(fixup.addr - ROM_START_ADDR);
+            match fixup.kind {
+                FixupKind::Nnn => {
+                    check_range(&fixup.name, i64::from(target), 0, 0xFFF)?;
+                    self.rom[i] = self.rom[i] & 0xF0 | (target >> 8) as u8;
+                    self.rom[i + 1] = target as u8;
+                }
+                FixupKind::Long => self.rom[i..i + 2].copy_from_slice(&target.to_be_bytes()),
+                FixupKind::UnpackHigh(n) => {
+                    check_range(&fixup.name, i64::from(target), 0, 0xFFF)?;
+                    self.rom[i] = n << 4 | (target >> 8) as u8;
+                }
+                FixupKind::UnpackLow => self.rom[i] = target as u8,
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), OctoError> {
+        use OpcodeKind::*;
+
+        if self.macros.contains_key(&token.text) {
+            return self.expand(&token);
+        }
+        if let Some(x) = self.register(&token) {
+            return self.register_statement(x);
+        }
+        let kind = match token.text.as_str() {
+            ":" => {
+                let name = self.next()?;
+                let addr = if name.text == "main" { self.here } else { self.start() };
+                return self.define_label(name, addr);
+            }
+            ":next" => {
+                let name = self.next()?;
+                let addr = self.start() + 1;
+                return self.define_label(name, addr);
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.value()?;
+                return self.define_const(name, value);
+            }
+            ":calc" => {
+                let name = self.next()?;
+                let open = self.expect("{")?;
+                let value = self.calc(&open)?;
+                return self.define_const(name, value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let x = self.expect_register()?;
+                self.aliases.insert(name.text, x);
+                return Ok(());
+            }
+            ":macro" => return self.define_macro(),
+            ":byte" => {
+                let byte = self.byte()?;
+                return self.emit(&[byte]);
+            }
+            ":pointer" => {
+                let addr = self.address(FixupKind::Long, 0, 0xFFFF)?;
+                return self.emit(&addr.to_be_bytes());
+            }
+            ":org" => {
+                let token = self.peek()?;
+                let value = self.value()?;
+                self.start();
+                self.here = check_range(&token, value as i64, 0x200, 0xFFFF)? as u16;
+                return Ok(());
+            }
+            ":unpack" => {
+                let n = self.nibble()?;
+                let (fixups, name) = (self.fixups.len(), self.peek()?);
+                let target = self.address(FixupKind::UnpackHigh(n), 1, 0xFFF)?;
+                if self.fixups.len() > fixups {
+                    let addr = self.here + 3;
+                    self.fixups.push(Fixup { addr, kind: FixupKind::UnpackLow, name });
+                }
+                self.emit(&encode(LoadVxByte { x: 0, byte: n << 4 | (target >> 8) as u8 }))?;
+                return self.emit(&encode(LoadVxByte { x: 1, byte: target as u8 }));
+            }
+            ":call" => Call { addr: self.address(FixupKind::Nnn, 0, 0xFFF)? },
+            ":breakpoint" | ":proto" => {
+                self.next()?;
+                return Ok(());
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+                return Ok(());
+            }
+            ";" | "return" => Ret,
+            "clear" => Cls,
+            "bcd" => LoadBcd { x: self.expect_register()? },
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                let range = match self.tokens.last() {
+                    Some(next) if next.text == "-" => {
+                        self.next()?;
+                        Some(self.expect_register()?)
+                    }
+                    _ => None,
+                };
+                match (token.text.as_str(), range) {
+                    ("save", None) => PushRegs { x },
+                    (_, None) => PopRegs { x },
+                    ("save", Some(y)) => SaveRange { x, y },
+                    (_, Some(y)) => LoadRange { x, y },
+                }
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                Draw { x, y, n: self.nibble()? }
+            }
+            "jump" => JpAddr { addr: self.address(FixupKind::Nnn, 0, 0xFFF)? },
+            "jump0" => {
+                let addr = self.address(FixupKind::Nnn, 0, 0xFFF)?;
+                JpVxAddr { x: (addr >> 8) as u8, addr }
+            }
+            "native" => {
+                let addr = self.address(FixupKind::Nnn, 0, 0xFFF)?;
+                return self.emit(&addr.to_be_bytes());
+            }
+            "hires" => HighRes,
+            "lores" => LowRes,
+            "scroll-down" => ScrollDown { n: self.nibble()? },
+            "scroll-up" => ScrollUp { n: self.nibble()? },
+            "scroll-right" => ScrollRight,
+            "scroll-left" => ScrollLeft,
+            "exit" => Exit,
+            "plane" => Plane { n: self.nibble()? },
+            "audio" => LoadAudio,
+            "saveflags" => StoreFlags { x: self.expect_register()? },
+            "loadflags" => LoadFlags { x: self.expect_register()? },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                match token.text.as_str() {
+                    "delay" => StoreDT { x },
+                    "buzzer" => StoreST { x },
+                    _ => LoadPitch { x },
+                }
+            }
+            "i" => return self.i_statement(),
+            "if" => return self.if_statement(),
+            "else" => {
+                let (jump, if_token) = match self.blocks.pop() {
+                    Some(Block::If { jump, token }) => (jump, token),
+                    _ => return Err(token.error(OctoErrorKind::Unmatched(token.text.clone()))),
+                };
+                let end = self.emit_jump(0)?;
+                self.patch_jump(jump, self.here);
+                self.blocks.push(Block::Else { jump: end, token: if_token });
+                return Ok(());
+            }
+            "end" => {
+                match self.blocks.pop() {
+                    Some(Block::If { jump, .. } | Block::Else { jump, .. }) => {
+                        self.patch_jump(jump, self.here)
+                    }
+                    _ => return Err(token.error(OctoErrorKind::Unmatched(token.text.clone()))),
+                }
+                return Ok(());
+            }
+            "loop" => {
+                let start = self.start();
+                self.blocks.push(Block::Loop { start, breaks: Vec::new(), token });
+                return Ok(());
+            }
+            "while" => {
+                let (x, cmp, rhs) = self.condition()?;
+                if !self.blocks.iter().any(|b| matches!(b, Block::Loop { .. })) {
+                    return Err(token.error(OctoErrorKind::Unmatched(token.text.clone())));
+                }
+                self.skip_unless(x, cmp.negate(), rhs)?;
+                let jump = self.emit_jump(0)?;
+                for block in self.blocks.iter_mut().rev() {
+                    if let Block::Loop { breaks, .. } = block {
+                        breaks.push(jump);
+                        break;
+                    }
+                }
+                return Ok(());
+            }
+            "again" => {
+                let (start, breaks) = match self.blocks.pop() {
+                    Some(Block::Loop { start, breaks, .. }) => (start, breaks),
+                    _ => return Err(token.error(OctoErrorKind::Unmatched(token.text.clone()))),
+                };
+                self.emit_jump(start)?;
+                for jump in breaks {
+                    self.patch_jump(jump, self.here);
+                }
+                return Ok(());
+            }
+            _ if token.text == "{" || literal(&token.text).is_some() => {
+                self.tokens.push(token);
+                let byte = self.byte()?;
+                return self.emit(&[byte]);
+            }
+            // A bare name is a call.
+            _ => {
+                self.tokens.push(token);
+                Call { addr: self.address(FixupKind::Nnn, 0, 0xFFF)? }
+            }
+        };
+        self.emit(&encode(kind))
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), OctoError> {
+        use OpcodeKind::*;
+
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let y = self.register(&rhs);
+        let kind = match (op.text.as_str(), y) {
+            (":=", Some(y)) => LoadVxVy { x, y },
+            (":=", None) if rhs.text == "key" => LoadK { x },
+            (":=", None) if rhs.text == "delay" => LoadDT { x },
+            (":=", None) if rhs.text == "random" => Random { x, byte: self.byte()? },
+            (":=", None) => {
+                self.tokens.push(rhs);
+                LoadVxByte { x, byte: self.byte()? }
+            }
+            ("+=", Some(y)) => Add { x, y },
+            ("+=", None) => {
+                self.tokens.push(rhs);
+                AddVxByte { x, byte: self.byte()? }
+            }
+            ("-=", Some(y)) => Subtract { x_y: true, x, y },
+            ("-=", None) => {
+                self.tokens.push(rhs);
+                AddVxByte { x, byte: self.byte()?.wrapping_neg() }
+            }
+            ("=-", Some(y)) => Subtract { x_y: false, x, y },
+            ("|=", Some(y)) => Or { x, y },
+            ("&=", Some(y)) => And { x, y },
+            ("^=", Some(y)) => Xor { x, y },
+            (">>=", Some(y)) => ShiftRight { x, y },
+            ("<<=", Some(y)) => ShiftLeft { x, y },
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) => {
+                return Err(rhs.expected("a register"));
+            }
+            _ => return Err(op.expected("an assignment")),
+        };
+        self.emit(&encode(kind))
+    }
+
+    fn i_statement(&mut self) -> Result<(), OctoError> {
+        use OpcodeKind::*;
+
+        let op = self.next()?;
+        let kind = match op.text.as_str() {
+            "+=" => AddIVx { x: self.expect_register()? },
+            ":=" => match self.peek()?.text.as_str() {
+                "hex" => {
+                    self.next()?;
+                    LoadFont { x: self.expect_register()? }
+                }
+                "bighex" => {
+                    self.next()?;
+                    LoadBigFont { x: self.expect_register()? }
+                }
+                "long" => {
+                    self.next()?;
+                    LoadILong { addr: self.address(FixupKind::Long, 2, 0xFFFF)? }
+                }
+                _ => LoadI { addr: self.address(FixupKind::Nnn, 0, 0xFFF)? },
+            },
+            _ => return Err(op.expected("an assignment")),
+        };
+        self.emit(&encode(kind))
+    }
+
+    fn if_statement(&mut self) -> Result<(), OctoError> {
+        let (x, cmp, rhs) = self.condition()?;
+        let token = self.next()?;
+        match token.text.as_str() {
+            "then" => self.skip_unless(x, cmp, rhs),
+            "begin" => {
+                self.skip_unless(x, cmp.negate(), rhs)?;
+                let jump = self.emit_jump(0)?;
+                self.blocks.push(Block::If { jump, token });
+                Ok(())
+            }
+            _ => Err(token.expected("then or begin")),
+        }
+    }
+
+    fn condition(&mut self) -> Result<(u8, Cmp, Option<Operand>), OctoError> {
+        let x = self.expect_register()?;
+        let op = self.next()?;
+        let cmp = match op.text.as_str() {
+            "==" => Cmp::Eq,
+            "!=" => Cmp::Ne,
+            "key" => return Ok((x, Cmp::Key, None)),
+            "-key" => return Ok((x, Cmp::NotKey, None)),
+            "<" => Cmp::Lt,
+            ">" => Cmp::Gt,
+            "<=" => Cmp::Le,
+            ">=" => Cmp::Ge,
+            _ => return Err(op.expected("a comparison")),
+        };
+        let rhs = self.next()?;
+        let rhs = match self.register(&rhs) {
+            Some(y) => Operand::Register(y),
+            None => {
+                self.tokens.push(rhs);
+                Operand::Byte(self.byte()?)
+            }
+        };
+        Ok((x, cmp, Some(rhs)))
+    }
+
+    // Emits the instructions that skip the next one unless `x cmp rhs`.
+    fn skip_unless(&mut self, x: u8, cmp: Cmp, rhs: Option<Operand>) -> Result<(), OctoError> {
+        use OpcodeKind::*;
+
+        let rhs = match (cmp, rhs) {
+            (Cmp::Key, _) => return self.emit(&encode(SkipIfKey { eq: false, x })),
+            (Cmp::NotKey, _) => return self.emit(&encode(SkipIfKey { eq: true, x })),
+            (_, Some(rhs)) => rhs,
+            (_, None) => unreachable!("comparisons have a right-hand side"),
+        };
+        let kind = match (cmp, rhs) {
+            (Cmp::Eq, Operand::Byte(byte)) => SkipVxByte { eq: false, x, byte },
+            (Cmp::Eq, Operand::Register(y)) => SkipVxVy { eq: false, x, y },
+            (Cmp::Ne, Operand::Byte(byte)) => SkipVxByte { eq: true, x, byte },
+            (Cmp::Ne, Operand::Register(y)) => SkipVxVy { eq: true, x, y },
+            // VF is the carry of the subtraction of `x` and `rhs`.
+            _ => {
+                let load = match rhs {
+                    Operand::Byte(byte) => LoadVxByte { x: 0xF, byte },
+                    Operand::Register(y) => LoadVxVy { x: 0xF, y },
+                };
+                self.emit(&encode(load))?;
+                // VF is x >= rhs after SUBN, and rhs >= x after SUB.
+                let x_y = matches!(cmp, Cmp::Gt | Cmp::Le);
+                self.emit(&encode(Subtract { x_y, x: 0xF, y: x }))?;
+                let byte = if matches!(cmp, Cmp::Ge | Cmp::Le) { 0 } else { 1 };
+                SkipVxByte { eq: true, x: 0xF, byte }
+            }
+        };
+        self.emit(&encode(kind))
+    }
+
+    fn expand(&mut self, token: &Token) -> Result<(), OctoError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(token.error(OctoErrorKind::TooManyExpansions));
+        }
+        let count = self.macros[&token.text].args.len();
+        let mut args = HashMap::new();
+        for i in 0..count {
+            let arg = self.next()?;
+            args.insert(self.macros[&token.text].args[i].clone(), arg.text);
+        }
+        let body = &self.macros[&token.text].body;
+        let expanded = body.iter().rev().map(|t| match args.get(&t.text) {
+            Some(arg) => Token { text: arg.clone(), ..t.clone() },
+            None => t.clone(),
+        });
+        let expanded = expanded.collect::<Vec<_>>();
+        self.tokens.extend(expanded);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), OctoError> {
+        let name = self.next()?;
+        let mut args = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            args.push(token.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name.text, Macro { args, body });
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: Token, addr: u16) -> Result<(), OctoError> {
+        self.check_name(&name)?;
+        self.labels.insert(name.text, addr);
+        Ok(())
+    }
+
+    fn define_const(&mut self, name: Token, value: f64) -> Result<(), OctoError> {
+        self.check_name(&name)?;
+        self.consts.insert(name.text, value);
+        Ok(())
+    }
+
+    fn check_name(&self, name: &Token) -> Result<(), OctoError> {
+        let text = &name.text;
+        if self.labels.contains_key(text) || self.consts.contains_key(text) {
+            return Err(name.error(OctoErrorKind::Redefined(text.clone())));
+        }
+        if self.register(name).is_some() || literal(text).is_some() {
+            return Err(name.expected("a name"));
+        }
+        Ok(())
+    }
+
+    // Places the jump to `main` when code comes before it, and returns
+    // where the next byte goes.
+    fn start(&mut self) -> u16 {
+        if !self.started {
+            self.started = true;
+            if !self.labels.contains_key("main") {
+                let name = Token { text: "main".to_string(), ..self.end.clone() };
+                self.fixups.push(Fixup { addr: self.here, kind: FixupKind::Nnn, name });
+                self.rom.extend_from_slice(&encode(OpcodeKind::JpAddr { addr: 0 }));
+                self.here += 2;
+            }
+        }
+        self.here
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), OctoError> {
+        self.start();
+        for &byte in bytes {
+            let i = usize::from(self.here - ROM_START_ADDR);
+            if i >= self.rom.len() {
+                self.rom.resize(i + 1, 0);
+            }
+            self.rom[i] = byte;
+            self.here = match self.here.checked_add(1) {
+                Some(here) => here,
+                None => return Err(self.end.error(OctoErrorKind::ProgramTooBig)),
+            };
+        }
+        Ok(())
+    }
+
+    // Emits a jump to `target` and returns its address, to patch it later.
+    fn emit_jump(&mut self, target: u16) -> Result<u16, OctoError> {
+        let addr = self.start();
+        self.emit(&encode(OpcodeKind::JpAddr { addr: target }))?;
+        Ok(addr)
+    }
+
+    fn patch_jump(&mut self, addr: u16, target: u16) {
+        let i = usize::from(addr - ROM_START_ADDR);
+        self.rom[i..i + 2].copy_from_slice(&encode(OpcodeKind::JpAddr { addr: target }));
+    }
+
+    fn next(&mut self) -> Result<Token, OctoError> {
+        self.tokens.pop().ok_or_else(|| self.end.error(OctoErrorKind::UnexpectedEnd))
+    }
+
+    fn peek(&self) -> Result<Token, OctoError> {
+        self.tokens.last().cloned().ok_or_else(|| self.end.error(OctoErrorKind::UnexpectedEnd))
+    }
+
+    fn expect(&mut self, text: &'static str) -> Result<Token, OctoError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(token.expected(text));
+        }
+        Ok(token)
+    }
+
+    fn register(&self, token: &Token) -> Option<u8> {
+        if let Some(&x) = self.aliases.get(&token.text) {
+            return Some(x);
+        }
+        match token.text.as_bytes() {
+            [b'v' | b'V', x] => (*x as char).to_digit(16).map(|x| x as u8),
+            _ => None,
+        }
+    }
+
+    fn expect_register(&mut self) -> Result<u8, OctoError> {
+        let token = self.next()?;
+        self.register(&token).ok_or_else(|| token.expected("a register"))
+    }
+
+    fn byte(&mut self) -> Result<u8, OctoError> {
+        let token = self.peek()?;
+        let value = self.value()?;
+        Ok(check_range(&token, value as i64, -128, 0xFF)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, OctoError> {
+        let token = self.peek()?;
+        let value = self.value()?;
+        Ok(check_range(&token, value as i64, 0, 0xF)? as u8)
+    }
+
+    // An address, which may be a label defined later. Its field is
+    // `offset` bytes into what is emitted next.
+    fn address(&mut self, kind: FixupKind, offset: u16, max: i64) -> Result<u16, OctoError> {
+        let token = self.peek()?;
+        let is_forward = token.text != "{"
+            && literal(&token.text).is_none()
+            && !self.labels.contains_key(&token.text)
+            && !self.consts.contains_key(&token.text);
+        if is_forward {
+            self.next()?;
+            let addr = self.start() + offset;
+            self.fixups.push(Fixup { addr, kind, name: token });
+            return Ok(0);
+        }
+        let value = self.value()?;
+        Ok(check_range(&token, value as i64, 0, max)? as u16)
+    }
+
+    // A number, a constant, a label or a `{ ... }` expression.
+    fn value(&mut self) -> Result<f64, OctoError> {
+        let token = self.next()?;
+        if token.text == "{" {
+            return self.calc(&token);
+        }
+        self.name_value(&token)
+    }
+
+    fn name_value(&self, token: &Token) -> Result<f64, OctoError> {
+        if let Some(value) = literal(&token.text) {
+            return Ok(value);
+        }
+        if let Some(&value) = self.consts.get(&token.text) {
+            return Ok(value);
+        }
+        match self.labels.get(&token.text) {
+            Some(&addr) => Ok(f64::from(addr)),
+            None => Err(token.error(OctoErrorKind::Undefined(token.text.clone()))),
+        }
+    }
+
+    fn calc(&mut self, open: &Token) -> Result<f64, OctoError> {
+        let mut expr = Vec::new();
+        loop {
+            let token = self.tokens.pop();
+            match token {
+                Some(token) if token.text == "}" => break,
+                Some(token) => expr.push(token),
+                None => return Err(open.error(OctoErrorKind::Unmatched(open.text.clone()))),
+            }
+        }
+        let mut pos = 0;
+        let value = self.expr(&expr, &mut pos, open, 0)?;
+        match expr.get(pos) {
+            Some(token) => Err(token.expected("an operator")),
+            None => Ok(value),
+        }
+    }
+
+    // Right to left: `a - b - c` is `a - (b - c)`.
+    fn expr(
+        &self,
+        expr: &[Token],
+        pos: &mut usize,
+        open: &Token,
+        depth: u32,
+    ) -> Result<f64, OctoError> {
+        let mut terms = vec![self.term(expr, pos, open, depth)?];
+        let mut ops = Vec::new();
+        while let Some(op) = expr.get(*pos).filter(|op| is_binary_operator(&op.text)) {
+            *pos += 1;
+            ops.push(op);
+            terms.push(self.term(expr, pos, open, depth)?);
+        }
+        let mut rhs = terms.pop().unwrap_or_default();
+        while let (Some(op), Some(lhs)) = (ops.pop(), terms.pop()) {
+            rhs = binary(op, lhs, rhs)?;
+        }
+        Ok(rhs)
+    }
+
+    fn term(
+        &self,
+        expr: &[Token],
+        pos: &mut usize,
+        open: &Token,
+        depth: u32,
+    ) -> Result<f64, OctoError> {
+        let token = match expr.get(*pos) {
+            Some(token) => token,
+            None => return Err(open.error(OctoErrorKind::UnexpectedEnd)),
+        };
+        if depth >= MAX_CALC_DEPTH {
+            return Err(token.error(OctoErrorKind::TooDeep));
+        }
+        *pos += 1;
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expr(expr, pos, open, depth + 1)?;
+                match expr.get(*pos) {
+                    Some(close) if close.text == ")" => {
+                        *pos += 1;
+                        Ok(value)
+                    }
+                    Some(token) => Err(token.expected(")")),
+                    None => Err(open.error(OctoErrorKind::UnexpectedEnd)),
+                }
+            }
+            "-" => Ok(-self.term(expr, pos, open, depth + 1)?),
+            "~" => Ok(!(self.term(expr, pos, open, depth + 1)? as i64) as f64),
+            "!" => Ok(if self.term(expr, pos, open, depth + 1)? == 0.0 { 1.0 } else { 0.0 }),
+            "HERE" => Ok(f64::from(self.here)),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => self.name_value(token),
+        }
+    }
+}
+
+fn binary(op: &Token, lhs: f64, rhs: f64) -> Result<f64, OctoError> {
+    let (a, b) = (lhs as i64, rhs as i64);
+    if matches!(op.text.as_str(), "/" | "%") && rhs == 0.0 {
+        return Err(op.error(OctoErrorKind::DivisionByZero));
+    }
+    if matches!(op.text.as_str(), "<<" | ">>") {
+        check_range(op, b, 0, 63)?;
+    }
+    Ok(match op.text.as_str() {
+        "+" => lhs + rhs,
+        "-" => lhs - rhs,
+        "*" => lhs * rhs,
+        "/" => lhs / rhs,
+        "%" => lhs % rhs,
+        "&" => (a & b) as f64,
+        "|" => (a | b) as f64,
+        "^" => (a ^ b) as f64,
+        "<<" => (a << b) as f64,
+        ">>" => (a >> b) as f64,
+        "min" => lhs.min(rhs),
+        _ => lhs.max(rhs),
+    })
+}
+
+fn is_binary_operator(text: &str) -> bool {
+    matches!(text, "+" | "-" | "*" | "/" | "%" | "&" | "|" | "^" | "<<" | ">>" | "min" | "max")
+}
+
+fn check_range(token: &Token, value: i64, min: i64, max: i64) -> Result<i64, OctoError> {
+    if value < min || value > max {
+        return Err(token.error(OctoErrorKind::OutOfRange { value, min, max }));
+    }
+    Ok(value)
+}
+
+impl Cmp {
+    fn negate(self) -> Self {
+        match self {
+            Cmp::Eq => Cmp::Ne,
+            Cmp::Ne => Cmp::Eq,
+            Cmp::Key => Cmp::NotKey,
+            Cmp::NotKey => Cmp::Key,
+            Cmp::Lt => Cmp::Ge,
+            Cmp::Ge => Cmp::Lt,
+            Cmp::Gt => Cmp::Le,
+            Cmp::Le => Cmp::Gt,
+        }
+    }
+}
+
+impl Token {
+    fn error(&self, kind: OctoErrorKind) -> OctoError {
+        OctoError { line: self.line, column: self.column, kind }
+    }
+
+    fn expected(&self, what: &'static str) -> OctoError {
+        self.error(OctoErrorKind::Expected { what, found: self.text.clone() })
+    }
+}
+
+impl Error for OctoError {}
+impl fmt::Debug for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            OctoErrorKind::UnexpectedEnd => f.write_str("unexpected end of the source"),
+            OctoErrorKind::Expected { what, found } => {
+                write!(f, "expected {}, found {}", what, found)
+            }
+            OctoErrorKind::Undefined(name) => write!(f, "undefined name {}", name),
+            OctoErrorKind::Redefined(name) => write!(f, "{} is already defined", name),
+            OctoErrorKind::OutOfRange { value, min, max } => {
+                write!(f, "{} is out of range {}..={}", value, min, max)
+            }
+            OctoErrorKind::DivisionByZero => f.write_str("division by zero"),
+            OctoErrorKind::Unmatched(what) => write!(f, "unmatched {}", what),
+            OctoErrorKind::NoMain => f.write_str("the program has no main label"),
+            OctoErrorKind::TooManyExpansions => f.write_str("too many macro expansions"),
+            OctoErrorKind::TooDeep => f.write_str("expression is nested too deep"),
+            OctoErrorKind::ProgramTooBig => f.write_str("program is bigger than the memory"),
+        }
+    }
+}
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
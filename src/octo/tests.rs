@@ -0,0 +1,166 @@
+use super::*;
+use crate::disasm::disassemble;
+use crate::quirks::Platform;
+use crate::Cpu;
+
+// Writes the Octo statement of every word of `rom`.
+fn to_octo(rom: &[u8]) -> String {
+    use OpcodeKind::*;
+
+    let mut out = String::from(": main\n");
+    for instruction in disassemble(rom, ROM_START_ADDR) {
+        let line = match instruction.kind {
+            Some(kind) => match kind {
+                JpAddr { addr } => format!("jump {:#X}", addr),
+                JpVxAddr { addr, .. } => format!("jump0 {:#X}", addr),
+                Ret => "return".to_string(),
+                Call { addr } => format!(":call {:#X}", addr),
+                SkipVxByte { eq, x, byte } => {
+                    format!("if v{:X} {} {:#X} then", x, if eq { "!=" } else { "==" }, byte)
+                }
+                SkipVxVy { eq, x, y } => {
+                    format!("if v{:X} {} v{:X} then", x, if eq { "!=" } else { "==" }, y)
+                }
+                LoadVxByte { x, byte } => format!("v{:X} := {:#X}", x, byte),
+                AddVxByte { x, byte } => format!("v{:X} += {:#X}", x, byte),
+                LoadVxVy { x, y } => format!("v{:X} := v{:X}", x, y),
+                Or { x, y } => format!("v{:X} |= v{:X}", x, y),
+                And { x, y } => format!("v{:X} &= v{:X}", x, y),
+                Xor { x, y } => format!("v{:X} ^= v{:X}", x, y),
+                Add { x, y } => format!("v{:X} += v{:X}", x, y),
+                Subtract { x_y: true, x, y } => format!("v{:X} -= v{:X}", x, y),
+                Subtract { x_y: false, x, y } => format!("v{:X} =- v{:X}", x, y),
+                ShiftRight { x, y } => format!("v{:X} >>= v{:X}", x, y),
+                ShiftLeft { x, y } => format!("v{:X} <<= v{:X}", x, y),
+                Random { x, byte } => format!("v{:X} := random {:#X}", x, byte),
+                LoadDT { x } => format!("v{:X} := delay", x),
+                StoreDT { x } => format!("delay := v{:X}", x),
+                StoreST { x } => format!("buzzer := v{:X}", x),
+                LoadK { x } => format!("v{:X} := key", x),
+                SkipIfKey { eq, x } => {
+                    format!("if v{:X} {} then", x, if eq { "-key" } else { "key" })
+                }
+                LoadI { addr } => format!("i := {:#X}", addr),
+                AddIVx { x } => format!("i += v{:X}", x),
+                LoadBcd { x } => format!("bcd v{:X}", x),
+                PushRegs { x } => format!("save v{:X}", x),
+                PopRegs { x } => format!("load v{:X}", x),
+                Cls => "clear".to_string(),
+                Draw { x, y, n } => format!("sprite v{:X} v{:X} {}", x, y, n),
+                LoadFont { x } => format!("i := hex v{:X}", x),
+                ScrollDown { n } => format!("scroll-down {}", n),
+                ScrollRight => "scroll-right".to_string(),
+                ScrollLeft => "scroll-left".to_string(),
+                Exit => "exit".to_string(),
+                LowRes => "lores".to_string(),
+                HighRes => "hires".to_string(),
+                LoadBigFont { x } => format!("i := bighex v{:X}", x),
+                StoreFlags { x } => format!("saveflags v{:X}", x),
+                LoadFlags { x } => format!("loadflags v{:X}", x),
+                ScrollUp { n } => format!("scroll-up {}", n),
+                LoadILong { addr } => format!("i := long {:#X}", addr),
+                SaveRange { x, y } => format!("save v{:X} - v{:X}", x, y),
+                LoadRange { x, y } => format!("load v{:X} - v{:X}", x, y),
+                Plane { n } => format!("plane {}", n),
+                LoadAudio => "audio".to_string(),
+                LoadPitch { x } => format!("pitch := v{:X}", x),
+            },
+            None if instruction.size == 1 => format!("{:#X}", instruction.word),
+            None => format!("{:#X} {:#X}", instruction.word >> 8, instruction.word & 0xFF),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn test_compile_test_roms() {
+    let roms: [&[u8]; 6] = [
+        include_bytes!("../../IBM_Logo.ch8"),
+        include_bytes!("../../test_roms/BC_test/bc_test.ch8"),
+        include_bytes!("../../test_roms/corax89/test_opcode.ch8"),
+        include_bytes!("../../test_roms/metteo/chip8-test-rom.ch8"),
+        include_bytes!("../../test_roms/sctest/SCTEST.ch8"),
+        include_bytes!("../../test_roms/skosulor/c8_test.c8"),
+    ];
+    for rom in roms {
+        let source = to_octo(rom);
+        assert_eq!(compile_octo(&source).unwrap(), rom, "{}", source);
+    }
+}
+
+#[test]
+fn test_jump_to_main() {
+    assert_eq!(compile_octo(": main clear").unwrap(), [0x00, 0xE0]);
+    let rom = compile_octo(": data 0xAB : main i := data").unwrap();
+    assert_eq!(rom, [0x12, 0x03, 0xAB, 0xA2, 0x02]);
+    let rom = compile_octo(":const N 1 :alias x v2 : main x := N").unwrap();
+    assert_eq!(rom, [0x62, 0x01]);
+}
+
+#[test]
+fn test_structured_flow() {
+    let source = "
+        :alias sum v0
+        :alias n v1
+        :macro inc reg { reg += 1 }
+        :calc LIMIT { 2 * 5 }
+        : main
+            loop
+                inc n
+                sum += n
+                while n != LIMIT
+            again
+            if sum > 50 begin v2 := 1 else v2 := 2 end
+            if sum <= 54 then v3 := 1
+            if sum >= 55 then v4 := 1
+            if sum < 55 then v5 := 1
+            if sum == 55 begin v6 := 1 end
+            v7 := 10
+            v7 -= 3
+            :unpack 0xA later
+            jump forever
+        : forever
+            jump forever
+        : later
+    ";
+    let rom = compile_octo(source).unwrap();
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&rom).unwrap();
+    for _ in 0..200 {
+        cpu.execute_cycle().unwrap();
+    }
+    let later = ROM_START_ADDR + rom.len() as u16;
+    let v = cpu.registers();
+    assert_eq!(v[..8], [0xA0 | (later >> 8) as u8, later as u8, 1, 0, 1, 0, 1, 7]);
+}
+
+#[test]
+fn test_errors() {
+    let error = |source| compile_octo(source).unwrap_err().to_string();
+    assert_eq!(error(": main\n  v0 := 0x100"), "2:9: 256 is out of range -128..=255");
+    assert_eq!(error(": main\n  jump nowhere"), "2:8: undefined name nowhere");
+    assert_eq!(error("clear"), "1:1: the program has no main label");
+    assert_eq!(error(": main loop"), "1:8: unmatched loop");
+    assert_eq!(error(": main\nend"), "2:1: unmatched end");
+    assert_eq!(error(": main : main"), "1:10: main is already defined");
+    assert_eq!(error(": main v0 +="), "1:11: unexpected end of the source");
+    assert_eq!(error(": main v0 ?= 1"), "1:11: expected an assignment, found ?=");
+    assert_eq!(error(": main v0 := { 1 / 0 }"), "1:18: division by zero");
+    assert_eq!(error(": main v0 := { 5 % ( 2 - 2 ) }"), "1:18: division by zero");
+    assert_eq!(error(": main v0 := { 1 << 64 }"), "1:18: 64 is out of range 0..=63");
+    assert_eq!(error(": main v0 := { 8 >> -1 }"), "1:18: -1 is out of range 0..=63");
+    assert!(matches!(
+        compile_octo(":macro m { m } : main m").unwrap_err().kind,
+        OctoErrorKind::TooManyExpansions
+    ));
+
+    // Long chains are fine, deep nesting isn't.
+    let long = format!(": main :calc x {{ 5{} }} v0 := x", " * 1".repeat(300_000));
+    assert!(compile_octo(&long).unwrap().windows(2).any(|w| w == [0x60, 0x05]));
+    let deep = format!(": main v0 := {{ {}1{} }}", "( ".repeat(1000), " )".repeat(1000));
+    assert!(matches!(compile_octo(&deep).unwrap_err().kind, OctoErrorKind::TooDeep));
+    let negated = format!(": main v0 := {{ {}1 }}", "- ".repeat(1000));
+    assert!(matches!(compile_octo(&negated).unwrap_err().kind, OctoErrorKind::TooDeep));
+}
@@ -1,136 +1,152 @@
+#[cfg(test)]
+mod tests;
+
 use std::fmt;
 
-pub struct Opcode(u16);
+// First word of the XO-CHIP `F000 NNNN` instruction, which is 4 bytes long.
+pub(crate) const LONG_INSTRUCTION_PREFIX: u16 = 0xF000;
+
+/// A raw instruction word.
+///
+/// The second word is only used by the XO-CHIP `F000 NNNN` instruction.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Opcode(u16, u16);
 
+/// A decoded instruction. Its `Debug` output is the assembly mnemonic.
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum OpcodeKind {
-    JpAddr {
-        addr: u16,
-    },
-    JpVxAddr {
-        #[cfg(not(feature = "original"))]
-        x: u8,
-        addr: u16,
-    },
+    JpAddr { addr: u16 },
+    JpVxAddr { x: u8, addr: u16 },
     Ret,
-    Call {
-        addr: u16,
-    },
-    SkipVxByte {
-        eq: bool,
-        x: u8,
-        byte: u8,
-    },
-    SkipVxVy {
-        eq: bool,
-        x: u8,
-        y: u8,
-    },
-    LoadVxByte {
-        x: u8,
-        byte: u8,
-    },
-    AddVxByte {
-        x: u8,
-        byte: u8,
-    },
-    LoadVxVy {
-        x: u8,
-        y: u8,
-    },
-    Or {
-        x: u8,
-        y: u8,
-    },
-    And {
-        x: u8,
-        y: u8,
-    },
-    Xor {
-        x: u8,
-        y: u8,
-    },
-    Add {
-        x: u8,
-        y: u8,
-    },
-    Subtract {
-        x_y: bool,
-        x: u8,
-        y: u8,
-    },
-    ShiftRight {
-        x: u8,
-        y: u8,
-    },
-    ShiftLeft {
-        x: u8,
-        y: u8,
-    },
-    Random {
-        x: u8,
-        byte: u8,
-    },
-    LoadDT {
-        x: u8,
-    },
-    StoreDT {
-        x: u8,
-    },
-    StoreST {
-        x: u8,
-    },
-    LoadK {
-        x: u8,
-    },
-    SkipIfKey {
-        eq: bool,
-        x: u8,
-    },
-    LoadI {
-        addr: u16,
-    },
-    AddIVx {
-        x: u8,
-    },
-    LoadBcd {
-        x: u8,
-    },
-    PushRegs {
-        x: u8,
-    },
-    PopRegs {
-        x: u8,
-    },
+    Call { addr: u16 },
+    SkipVxByte { eq: bool, x: u8, byte: u8 },
+    SkipVxVy { eq: bool, x: u8, y: u8 },
+    LoadVxByte { x: u8, byte: u8 },
+    AddVxByte { x: u8, byte: u8 },
+    LoadVxVy { x: u8, y: u8 },
+    Or { x: u8, y: u8 },
+    And { x: u8, y: u8 },
+    Xor { x: u8, y: u8 },
+    Add { x: u8, y: u8 },
+    Subtract { x_y: bool, x: u8, y: u8 },
+    ShiftRight { x: u8, y: u8 },
+    ShiftLeft { x: u8, y: u8 },
+    Random { x: u8, byte: u8 },
+    LoadDT { x: u8 },
+    StoreDT { x: u8 },
+    StoreST { x: u8 },
+    LoadK { x: u8 },
+    SkipIfKey { eq: bool, x: u8 },
+    LoadI { addr: u16 },
+    AddIVx { x: u8 },
+    LoadBcd { x: u8 },
+    PushRegs { x: u8 },
+    PopRegs { x: u8 },
     Cls,
-    Draw {
-        x: u8,
-        y: u8,
-        n: u8,
-    },
-    LoadFont {
-        x: u8,
-    },
+    Draw { x: u8, y: u8, n: u8 },
+    LoadFont { x: u8 },
+    /* SUPER-CHIP */
+    ScrollDown { n: u8 },
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowRes,
+    HighRes,
+    LoadBigFont { x: u8 },
+    StoreFlags { x: u8 },
+    LoadFlags { x: u8 },
+    /* XO-CHIP */
+    ScrollUp { n: u8 },
+    LoadILong { addr: u16 },
+    SaveRange { x: u8, y: u8 },
+    LoadRange { x: u8, y: u8 },
+    Plane { n: u8 },
+    LoadAudio,
+    LoadPitch { x: u8 },
 }
 
 impl Opcode {
     pub fn new(op: u16) -> Self {
-        Self(op)
+        Self(op, 0)
+    }
+
+    pub fn with_operand(op: u16, operand: u16) -> Self {
+        Self(op, operand)
     }
 
-    pub fn decode(&self) -> OpcodeKind {
+    /// The inverse of [`Opcode::decode`].
+    ///
+    /// Fields wider than their place in the word are truncated.
+    /// `JpVxAddr` is encoded from `addr`, whose high nibble is `x`.
+    pub fn encode(kind: OpcodeKind) -> Self {
+        use OpcodeKind::*;
+        let xkk = |op: u16, x: u8, kk: u8| op << 12 | u16::from(x & 0xF) << 8 | u16::from(kk);
+        let xyn = |op: u16, x: u8, y: u8, n: u8| xkk(op, x, (y & 0xF) << 4 | (n & 0xF));
+        let nnn = |op: u16, addr: u16| op << 12 | (addr & 0x0FFF);
+
+        let word = match kind {
+            JpAddr { addr } => nnn(0x1, addr),
+            JpVxAddr { addr, .. } => nnn(0xB, addr),
+            Ret => 0x00EE,
+            Call { addr } => nnn(0x2, addr),
+            SkipVxByte { eq, x, byte } => xkk(if eq { 0x3 } else { 0x4 }, x, byte),
+            SkipVxVy { eq, x, y } => xyn(if eq { 0x5 } else { 0x9 }, x, y, 0x0),
+            LoadVxByte { x, byte } => xkk(0x6, x, byte),
+            AddVxByte { x, byte } => xkk(0x7, x, byte),
+            LoadVxVy { x, y } => xyn(0x8, x, y, 0x0),
+            Or { x, y } => xyn(0x8, x, y, 0x1),
+            And { x, y } => xyn(0x8, x, y, 0x2),
+            Xor { x, y } => xyn(0x8, x, y, 0x3),
+            Add { x, y } => xyn(0x8, x, y, 0x4),
+            Subtract { x_y, x, y } => xyn(0x8, x, y, if x_y { 0x5 } else { 0x7 }),
+            ShiftRight { x, y } => xyn(0x8, x, y, 0x6),
+            ShiftLeft { x, y } => xyn(0x8, x, y, 0xE),
+            Random { x, byte } => xkk(0xC, x, byte),
+            LoadDT { x } => xkk(0xF, x, 0x07),
+            StoreDT { x } => xkk(0xF, x, 0x15),
+            StoreST { x } => xkk(0xF, x, 0x18),
+            LoadK { x } => xkk(0xF, x, 0x0A),
+            SkipIfKey { eq, x } => xkk(0xE, x, if eq { 0x9E } else { 0xA1 }),
+            LoadI { addr } => nnn(0xA, addr),
+            AddIVx { x } => xkk(0xF, x, 0x1E),
+            LoadBcd { x } => xkk(0xF, x, 0x33),
+            PushRegs { x } => xkk(0xF, x, 0x55),
+            PopRegs { x } => xkk(0xF, x, 0x65),
+            Cls => 0x00E0,
+            Draw { x, y, n } => xyn(0xD, x, y, n),
+            LoadFont { x } => xkk(0xF, x, 0x29),
+            ScrollDown { n } => xkk(0x0, 0x0, 0xC0 | (n & 0xF)),
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowRes => 0x00FE,
+            HighRes => 0x00FF,
+            LoadBigFont { x } => xkk(0xF, x, 0x30),
+            StoreFlags { x } => xkk(0xF, x, 0x75),
+            LoadFlags { x } => xkk(0xF, x, 0x85),
+            ScrollUp { n } => xkk(0x0, 0x0, 0xD0 | (n & 0xF)),
+            LoadILong { addr } => return Self(LONG_INSTRUCTION_PREFIX, addr),
+            SaveRange { x, y } => xyn(0x5, x, y, 0x2),
+            LoadRange { x, y } => xyn(0x5, x, y, 0x3),
+            Plane { n } => xkk(0xF, n, 0x01),
+            LoadAudio => 0xF002,
+            LoadPitch { x } => xkk(0xF, x, 0x3A),
+        };
+        Self(word, 0)
+    }
+
+    /// Returns `None` for words that aren't a known instruction.
+    pub fn decode(&self) -> Option<OpcodeKind> {
         use OpcodeKind::*;
         let nibbles = crate::num::to_4_be_nibles(self.0);
         let addr = self.0 & 0x0FFF;
         let kk = (self.0 & 0x00FF) as u8;
 
-        match nibbles {
+        let kind = match nibbles {
             /* Jumps */
             // JP addr
             [1, ..] => JpAddr { addr },
             // JP V0, addr
-            #[cfg(feature = "original")]
-            [0xb, ..] => JpVxAddr { addr },
-            #[cfg(not(feature = "original"))]
             [0xb, x, ..] => JpVxAddr { x, addr },
 
             /* Subroutines */
@@ -219,9 +235,118 @@ impl Opcode {
             // LD F, Vx
             [0xf, x, 2, 9] => LoadFont { x },
 
-            // SYS addr
-            [0, ..] => panic!("0NNN - `SYS addr` deprecated"),
-            _ => unreachable!("no such instruction: {:X}", self),
+            /* SUPER-CHIP */
+            // SCD nibble
+            [0x0, 0x0, 0xC, n] => ScrollDown { n },
+            // SCR
+            [0x0, 0x0, 0xF, 0xB] => ScrollRight,
+            // SCL
+            [0x0, 0x0, 0xF, 0xC] => ScrollLeft,
+            // EXIT
+            [0x0, 0x0, 0xF, 0xD] => Exit,
+            // LOW
+            [0x0, 0x0, 0xF, 0xE] => LowRes,
+            // HIGH
+            [0x0, 0x0, 0xF, 0xF] => HighRes,
+            // LD HF, Vx
+            [0xf, x, 3, 0] => LoadBigFont { x },
+            // LD R, Vx
+            [0xf, x, 7, 5] => StoreFlags { x },
+            // LD Vx, R
+            [0xf, x, 8, 5] => LoadFlags { x },
+
+            /* XO-CHIP */
+            // SCU nibble
+            [0x0, 0x0, 0xD, n] => ScrollUp { n },
+            // LD I, long addr
+            [0xf, 0x0, 0x0, 0x0] => LoadILong { addr: self.1 },
+            // LD [I], Vx-Vy
+            [5, x, y, 2] => SaveRange { x, y },
+            // LD Vx-Vy, [I]
+            [5, x, y, 3] => LoadRange { x, y },
+            // PLANE n
+            [0xf, n, 0x0, 0x1] => Plane { n },
+            // LD AUDIO, [I]
+            [0xf, 0x0, 0x0, 0x2] => LoadAudio,
+            // LD PITCH, Vx
+            [0xf, x, 3, 0xa] => LoadPitch { x },
+
+            // SYS addr too: machine code routines can't be emulated.
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// The word following `F000`.
+    pub fn operand(&self) -> u16 {
+        self.1
+    }
+
+    /// Size in bytes, 4 for `F000 NNNN` and 2 otherwise.
+    pub fn size(&self) -> u16 {
+        if self.0 == LONG_INSTRUCTION_PREFIX {
+            4
+        } else {
+            2
+        }
+    }
+}
+
+impl OpcodeKind {
+    /// Name of the variant, like `"Draw"`.
+    pub fn name(&self) -> &'static str {
+        use OpcodeKind::*;
+        match self {
+            JpAddr { .. } => "JpAddr",
+            JpVxAddr { .. } => "JpVxAddr",
+            Ret => "Ret",
+            Call { .. } => "Call",
+            SkipVxByte { .. } => "SkipVxByte",
+            SkipVxVy { .. } => "SkipVxVy",
+            LoadVxByte { .. } => "LoadVxByte",
+            AddVxByte { .. } => "AddVxByte",
+            LoadVxVy { .. } => "LoadVxVy",
+            Or { .. } => "Or",
+            And { .. } => "And",
+            Xor { .. } => "Xor",
+            Add { .. } => "Add",
+            Subtract { .. } => "Subtract",
+            ShiftRight { .. } => "ShiftRight",
+            ShiftLeft { .. } => "ShiftLeft",
+            Random { .. } => "Random",
+            LoadDT { .. } => "LoadDT",
+            StoreDT { .. } => "StoreDT",
+            StoreST { .. } => "StoreST",
+            LoadK { .. } => "LoadK",
+            SkipIfKey { .. } => "SkipIfKey",
+            LoadI { .. } => "LoadI",
+            AddIVx { .. } => "AddIVx",
+            LoadBcd { .. } => "LoadBcd",
+            PushRegs { .. } => "PushRegs",
+            PopRegs { .. } => "PopRegs",
+            Cls => "Cls",
+            Draw { .. } => "Draw",
+            LoadFont { .. } => "LoadFont",
+            ScrollDown { .. } => "ScrollDown",
+            ScrollRight => "ScrollRight",
+            ScrollLeft => "ScrollLeft",
+            Exit => "Exit",
+            LowRes => "LowRes",
+            HighRes => "HighRes",
+            LoadBigFont { .. } => "LoadBigFont",
+            StoreFlags { .. } => "StoreFlags",
+            LoadFlags { .. } => "LoadFlags",
+            ScrollUp { .. } => "ScrollUp",
+            LoadILong { .. } => "LoadILong",
+            SaveRange { .. } => "SaveRange",
+            LoadRange { .. } => "LoadRange",
+            Plane { .. } => "Plane",
+            LoadAudio => "LoadAudio",
+            LoadPitch { .. } => "LoadPitch",
         }
     }
 }
@@ -237,9 +362,6 @@ impl fmt::Debug for OpcodeKind {
         use OpcodeKind::*;
         match self {
             JpAddr { addr } => write!(f, "JMP {:#X}", addr),
-            #[cfg(feature = "original")]
-            JpVxAddr { addr } => write!(f, "JMP V0, {:#X}", addr),
-            #[cfg(not(feature = "original"))]
             JpVxAddr { x, addr } => write!(f, "JMP V{:X}, {:#X}", x, addr),
             Ret => f.write_str("ret"),
             Call { addr } => write!(f, "CALL {:#X}", addr),
@@ -281,6 +403,22 @@ impl fmt::Debug for OpcodeKind {
             Cls => f.write_str("CLS"),
             Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {:#X}", x, y, n),
             LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            ScrollDown { n } => write!(f, "SCD {:#X}", n),
+            ScrollRight => f.write_str("SCR"),
+            ScrollLeft => f.write_str("SCL"),
+            Exit => f.write_str("EXIT"),
+            LowRes => f.write_str("LOW"),
+            HighRes => f.write_str("HIGH"),
+            LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            ScrollUp { n } => write!(f, "SCU {:#X}", n),
+            LoadILong { addr } => write!(f, "LD I, LONG {:#X}", addr),
+            SaveRange { x, y } => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            LoadRange { x, y } => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            Plane { n } => write!(f, "PLANE {:#X}", n),
+            LoadAudio => f.write_str("LD AUDIO, [I]"),
+            LoadPitch { x } => write!(f, "LD PITCH, V{:X}", x),
         }
     }
 }
@@ -0,0 +1,15 @@
+use super::*;
+
+#[test]
+fn test_encode_decode_roundtrip() {
+    for word in 0..=u16::MAX {
+        let opcode = Opcode::with_operand(word, 0xABCD);
+        if let Some(kind) = opcode.decode() {
+            let encoded = Opcode::encode(kind);
+            assert_eq!(encoded.as_u16(), word, "{:?}", kind);
+            assert_eq!(encoded.size(), opcode.size());
+            assert!(encoded.decode() == Some(kind));
+        }
+    }
+    assert_eq!(Opcode::encode(OpcodeKind::LoadILong { addr: 0x1234 }).operand(), 0x1234);
+}
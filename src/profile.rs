@@ -0,0 +1,235 @@
+//! Where a program spends its instructions, counted by a [`TraceSink`].
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+
+use crate::opcode::OpcodeKind;
+use crate::trace::{TraceEntry, TraceSink};
+
+/// Counts the instructions a [`Cpu`](crate::Cpu) runs once set as its
+/// tracer, see [`Cpu::set_tracer`](crate::Cpu::set_tracer).
+///
+/// Keep a clone to call [`Profiler::profile`] on, since the `Cpu` owns the
+/// one it was given.
+#[derive(Clone, Default)]
+pub struct Profiler {
+    counts: Arc<Mutex<Counts>>,
+}
+
+/// What a [`Profiler`] counted, hottest first.
+pub struct Profile {
+    pub instructions: u64,
+    /// Frames seen, that is timer ticks.
+    pub frames: u64,
+    /// `DRW` instructions run.
+    pub draws: u64,
+    /// Most `DRW` run in a single frame.
+    pub max_draws: u64,
+    pub addresses: Vec<AddressCount>,
+    /// Instructions run per [`OpcodeKind::name`].
+    pub kinds: Vec<(&'static str, u64)>,
+    pub subroutines: Vec<Subroutine>,
+}
+
+pub struct AddressCount {
+    pub pc: u16,
+    pub count: u64,
+    /// The last instruction run at `pc`.
+    pub kind: OpcodeKind,
+}
+
+pub struct Subroutine {
+    pub addr: u16,
+    pub calls: u64,
+    /// Instructions run from the `CALL` to the `RET` included, along with
+    /// those of nested calls.
+    pub cycles: u64,
+}
+
+#[derive(Default)]
+struct Counts {
+    instructions: u64,
+    frames: u64,
+    draws: u64,
+    frame_draws: u64,
+    max_draws: u64,
+    last_cycle: u64,
+    addresses: HashMap<u16, (u64, OpcodeKind)>,
+    kinds: HashMap<&'static str, u64>,
+    // Calls and cycles of the subroutines that returned
+    subroutines: HashMap<u16, (u64, u64)>,
+    // Subroutines called, with the cycle of their `CALL`
+    calls: Vec<(u16, u64)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What was counted so far.
+    ///
+    /// Subroutines which haven't returned yet count the cycles run until now.
+    pub fn profile(&self) -> Profile {
+        let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut addresses = counts
+            .addresses
+            .iter()
+            .map(|(&pc, &(count, kind))| AddressCount { pc, count, kind })
+            .collect::<Vec<_>>();
+        addresses.sort_by_key(|a| (u64::MAX - a.count, a.pc));
+        let mut kinds = counts.kinds.iter().map(|(&name, &n)| (name, n)).collect::<Vec<_>>();
+        kinds.sort_by_key(|&(name, n)| (u64::MAX - n, name));
+
+        let mut subroutines = counts.subroutines.clone();
+        for &(addr, start) in &counts.calls {
+            subroutines.entry(addr).or_default().1 += counts.last_cycle - start + 1;
+        }
+        let mut subroutines = subroutines
+            .into_iter()
+            .map(|(addr, (calls, cycles))| Subroutine { addr, calls, cycles })
+            .collect::<Vec<_>>();
+        subroutines.sort_by_key(|s| (u64::MAX - s.cycles, s.addr));
+
+        Profile {
+            instructions: counts.instructions,
+            frames: counts.frames,
+            draws: counts.draws,
+            max_draws: counts.max_draws.max(counts.frame_draws),
+            addresses,
+            kinds,
+            subroutines,
+        }
+    }
+}
+
+impl TraceSink for Profiler {
+    fn trace(&mut self, entry: &TraceEntry) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let counts = &mut *counts;
+        counts.instructions += 1;
+        // The `Cpu` was reset or loaded a savestate: its stack is gone.
+        if entry.cycle < counts.last_cycle {
+            counts.calls.clear();
+        }
+        counts.last_cycle = entry.cycle;
+        let address = counts.addresses.entry(entry.pc).or_insert((0, entry.kind));
+        *address = (address.0 + 1, entry.kind);
+        *counts.kinds.entry(entry.kind.name()).or_default() += 1;
+        match entry.kind {
+            OpcodeKind::Draw { .. } => {
+                counts.draws += 1;
+                counts.frame_draws += 1;
+            }
+            OpcodeKind::Call { addr } => {
+                counts.subroutines.entry(addr).or_default().0 += 1;
+                counts.calls.push((addr, entry.cycle));
+            }
+            // Returns without a call were called before profiling started.
+            OpcodeKind::Ret => {
+                if let Some((addr, start)) = counts.calls.pop() {
+                    counts.subroutines.entry(addr).or_default().1 += entry.cycle - start + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn frame(&mut self) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.frames += 1;
+        counts.max_draws = counts.max_draws.max(counts.frame_draws);
+        counts.frame_draws = 0;
+    }
+}
+
+impl Profile {
+    /// A text report, with at most `top` lines per table.
+    pub fn report(&self, top: usize) -> String {
+        let mut out = String::new();
+        let percent = |n: u64| 100.0 * n as f64 / self.instructions.max(1) as f64;
+        let _ = writeln!(out, "{} instructions in {} frames", self.instructions, self.frames);
+        let _ = match self.frames {
+            0 => writeln!(out, "{} draws", self.draws),
+            frames => writeln!(
+                out,
+                "{} draws, {:.2} per frame, at most {}",
+                self.draws,
+                self.draws as f64 / frames as f64,
+                self.max_draws
+            ),
+        };
+
+        let _ = writeln!(
+            out,
+            "\nHottest addresses:\n{:>12} {:>6}  {:<5}  instruction",
+            "count", "%", "pc"
+        );
+        for a in self.addresses.iter().take(top) {
+            let _ = writeln!(
+                out,
+                "{:>12} {:>6.2}  {:#05X}  {:?}",
+                a.count,
+                percent(a.count),
+                a.pc,
+                a.kind
+            );
+        }
+        let _ = writeln!(out, "\nHottest instructions:\n{:>12} {:>6}  kind", "count", "%");
+        for &(name, count) in self.kinds.iter().take(top) {
+            let _ = writeln!(out, "{:>12} {:>6.2}  {}", count, percent(count), name);
+        }
+        let _ = writeln!(out, "\nSubroutines:\n{:>12} {:>6} {:>8}  addr", "cycles", "%", "calls");
+        for s in self.subroutines.iter().take(top) {
+            let _ = writeln!(
+                out,
+                "{:>12} {:>6.2} {:>8}  {:#05X}",
+                s.cycles,
+                percent(s.cycles),
+                s.calls,
+                s.addr
+            );
+        }
+        out
+    }
+
+    /// The whole profile as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{{\"instructions\":{},\"frames\":{},\"draws\":{},\"max_draws\":{},\"addresses\":[",
+            self.instructions, self.frames, self.draws, self.max_draws
+        );
+        for (n, a) in self.addresses.iter().enumerate() {
+            // Instructions are printable ASCII, escaped the same in Rust and JSON.
+            let kind = format!("{:?}", a.kind);
+            let sep = if n == 0 { "" } else { "," };
+            let _ = write!(
+                out,
+                "{}{{\"pc\":{},\"count\":{},\"instruction\":{:?}}}",
+                sep, a.pc, a.count, kind
+            );
+        }
+        out.push_str("],\"kinds\":{");
+        for (n, (name, count)) in self.kinds.iter().enumerate() {
+            let sep = if n == 0 { "" } else { "," };
+            let _ = write!(out, "{}\"{}\":{}", sep, name, count);
+        }
+        out.push_str("},\"subroutines\":[");
+        for (n, s) in self.subroutines.iter().enumerate() {
+            let sep = if n == 0 { "" } else { "," };
+            let _ = write!(
+                out,
+                "{}{{\"addr\":{},\"calls\":{},\"cycles\":{}}}",
+                sep, s.addr, s.calls, s.cycles
+            );
+        }
+        out.push_str("]}");
+        out
+    }
+}
@@ -0,0 +1,122 @@
+use super::*;
+use crate::asm::assemble;
+use crate::cpu::Cpu;
+use crate::quirks::Platform;
+
+// Calls a drawing subroutine 3 times, then loops forever at 0x20A.
+const SOURCE: &str = "
+        LD V0, 0
+    loop:
+        CALL sub
+        ADD V0, 1
+        SE V0, 3
+        JP loop
+    end:
+        JP end
+    sub:
+        DRW V1, V1, 1
+        RET
+";
+
+fn run(profiler: &Profiler, frames: usize) {
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&assemble(SOURCE).unwrap()).unwrap();
+    cpu.set_tracer(Some(Box::new(profiler.clone())));
+    for _ in 0..frames {
+        cpu.run_frame(10).unwrap();
+    }
+}
+
+#[test]
+fn test_profile() {
+    let profiler = Profiler::new();
+    run(&profiler, 3);
+    let profile = profiler.profile();
+    assert_eq!((profile.instructions, profile.frames), (30, 3));
+    // Twice in the first frame, once in the second.
+    assert_eq!((profile.draws, profile.max_draws), (3, 2));
+
+    let addresses = profile.addresses.iter().map(|a| (a.pc, a.count)).collect::<Vec<_>>();
+    assert_eq!(
+        addresses,
+        [
+            (0x20A, 12),
+            (0x202, 3),
+            (0x204, 3),
+            (0x206, 3),
+            (0x20C, 3),
+            (0x20E, 3),
+            (0x208, 2),
+            (0x200, 1)
+        ]
+    );
+    assert_eq!(profile.addresses[4].kind, OpcodeKind::Draw { x: 1, y: 1, n: 1 });
+    assert_eq!(profile.kinds[0], ("JpAddr", 14));
+    assert_eq!(profile.kinds.iter().map(|k| k.1).sum::<u64>(), 30);
+
+    assert_eq!(profile.subroutines.len(), 1);
+    let sub = &profile.subroutines[0];
+    assert_eq!((sub.addr, sub.calls, sub.cycles), (0x20C, 3, 9));
+}
+
+#[test]
+fn test_unfinished_call() {
+    // Stops on the `DRW` of the second call.
+    let profiler = Profiler::new();
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&assemble(SOURCE).unwrap()).unwrap();
+    cpu.set_tracer(Some(Box::new(profiler.clone())));
+    for _ in 0..9 {
+        cpu.execute_cycle().unwrap();
+    }
+    let profile = profiler.profile();
+    let sub = &profile.subroutines[0];
+    assert_eq!((sub.calls, sub.cycles), (2, 3 + 2));
+    assert_eq!((profile.frames, profile.max_draws), (0, 2));
+}
+
+#[test]
+fn test_reset_during_call() {
+    let profiler = Profiler::new();
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&assemble(SOURCE).unwrap()).unwrap();
+    cpu.set_tracer(Some(Box::new(profiler.clone())));
+    // Stops after the second `CALL`, at cycle 7.
+    for _ in 0..8 {
+        cpu.execute_cycle().unwrap();
+    }
+    cpu.reset();
+    cpu.load_game(&assemble(SOURCE).unwrap()).unwrap();
+    cpu.execute_cycle().unwrap();
+    let sub = &profiler.profile().subroutines[0];
+    assert_eq!((sub.calls, sub.cycles), (2, 3));
+
+    // The first `RET` after the reset closes the new call only.
+    for _ in 0..3 {
+        cpu.execute_cycle().unwrap();
+    }
+    let sub = &profiler.profile().subroutines[0];
+    assert_eq!((sub.calls, sub.cycles), (3, 3 + 3));
+}
+
+#[test]
+fn test_report() {
+    let profiler = Profiler::new();
+    run(&profiler, 3);
+    let profile = profiler.profile();
+
+    let report = profile.report(2);
+    assert!(report.starts_with("30 instructions in 3 frames\n3 draws, 1.00 per frame, at most 2\n"));
+    assert!(report.contains("          12  40.00  0x20A  JMP 0x20A\n"));
+    assert!(report.contains("           3  10.00  0x202  CALL 0x20C\n"));
+    assert!(!report.contains("0x204"));
+    assert!(report.contains("           9  30.00        3  0x20C\n"));
+
+    let json = profile.to_json();
+    assert!(json.starts_with(
+        "{\"instructions\":30,\"frames\":3,\"draws\":3,\"max_draws\":2,\
+         \"addresses\":[{\"pc\":522,\"count\":12,\"instruction\":\"JMP 0x20A\"},"
+    ));
+    assert!(json.contains("\"kinds\":{\"JpAddr\":14,"));
+    assert!(json.ends_with(",\"subroutines\":[{\"addr\":524,\"calls\":3,\"cycles\":9}]}"));
+}
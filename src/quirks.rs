@@ -0,0 +1,144 @@
+use std::str::FromStr;
+
+/// Behaviours that differ between CHIP-8 interpreters.
+///
+/// Most ROMs only run correctly with the quirks of the platform they
+/// were written for. Use [`Platform::quirks`] for the common presets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy6`/`8xyE` shift VY and store the result into VX,
+    /// instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// `Fx55`/`Fx65` leave I pointing past the last register transferred.
+    pub load_store_increments_i: bool,
+    /// `Bnnn` jumps to `nnn + VX` (X being the high nibble of `nnn`)
+    /// instead of `nnn + V0`.
+    pub jump_uses_vx: bool,
+    /// `Fx1E` sets VF to 1 when I overflows past addressable memory.
+    pub add_i_sets_vf: bool,
+    /// `Fx29` only looks at the low nibble of VX.
+    pub font_index_masked: bool,
+    /// Sprites wrap around the screen edges instead of being clipped.
+    pub wrap_sprites: bool,
+    /// Memory is 64 KiB instead of 4 KiB.
+    pub extended_memory: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// COSMAC VIP chip8 interpreter.
+    Cosmac,
+    /// Chip-48 for the HP-48 calculators.
+    #[default]
+    Chip48,
+    /// SUPER-CHIP, based on Chip-48.
+    SuperChip,
+    /// CHIP-8 for the Amiga.
+    Amiga,
+    /// XO-CHIP, the extension of SUPER-CHIP made for Octo.
+    XoChip,
+}
+
+impl Quirks {
+    pub const fn new(platform: Platform) -> Self {
+        match platform {
+            Platform::Cosmac => Self {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                add_i_sets_vf: false,
+                font_index_masked: true,
+                wrap_sprites: false,
+                extended_memory: false,
+            },
+            Platform::Chip48 | Platform::SuperChip => Self {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                add_i_sets_vf: false,
+                font_index_masked: false,
+                wrap_sprites: false,
+                extended_memory: false,
+            },
+            Platform::Amiga => Self {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                add_i_sets_vf: true,
+                font_index_masked: false,
+                wrap_sprites: false,
+                extended_memory: false,
+            },
+            Platform::XoChip => Self {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                add_i_sets_vf: false,
+                font_index_masked: true,
+                wrap_sprites: true,
+                extended_memory: true,
+            },
+        }
+    }
+
+    /// One bit per quirk, in the order of the fields, as files store them.
+    pub(crate) fn to_bits(self) -> u8 {
+        [
+            self.shift_uses_vy,
+            self.load_store_increments_i,
+            self.jump_uses_vx,
+            self.add_i_sets_vf,
+            self.font_index_masked,
+            self.wrap_sprites,
+            self.extended_memory,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, &b)| bits | u8::from(b) << i)
+    }
+
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        let bit = |i: u8| bits & (1 << i) != 0;
+        Self {
+            shift_uses_vy: bit(0),
+            load_store_increments_i: bit(1),
+            jump_uses_vx: bit(2),
+            add_i_sets_vf: bit(3),
+            font_index_masked: bit(4),
+            wrap_sprites: bit(5),
+            extended_memory: bit(6),
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::new(Platform::default())
+    }
+}
+
+impl From<Platform> for Quirks {
+    fn from(platform: Platform) -> Self {
+        Self::new(platform)
+    }
+}
+
+impl Platform {
+    pub const fn quirks(self) -> Quirks {
+        Quirks::new(self)
+    }
+}
+
+impl FromStr for Platform {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cosmac" | "original" | "chip8" => Ok(Self::Cosmac),
+            "chip48" => Ok(Self::Chip48),
+            "superchip" | "schip" => Ok(Self::SuperChip),
+            "amiga" => Ok(Self::Amiga),
+            "xochip" | "xo-chip" => Ok(Self::XoChip),
+            _ => Err(()),
+        }
+    }
+}
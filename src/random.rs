@@ -0,0 +1,61 @@
+use nanorand::{Rng, WyRand};
+
+// What `WyRand::rand` adds to its state on every call.
+const WYRAND_INCREMENT: u64 = 0xa0761d6478bd642f;
+
+/// Where `CXNN` takes its random numbers from.
+///
+/// The state is what savestates store. Sources that can't be saved, like a
+/// scripted sequence in tests, can keep the default methods. Sources are
+/// `Send` so that a `Cpu` can move to another thread.
+pub trait RandomSource: Send {
+    fn generate(&mut self) -> u8;
+
+    fn state(&self) -> u64 {
+        0
+    }
+
+    fn set_state(&mut self, _state: u64) {}
+}
+
+/// WyRand whose state can be saved and restored.
+///
+/// `nanorand::WyRand` doesn't expose its state, so we keep it here and
+/// advance it the same way `WyRand::rand` does. The generated numbers are
+/// the same as WyRand's.
+pub struct Random {
+    seed: u64,
+}
+
+impl Random {
+    /// Seeded from the system entropy.
+    pub fn new() -> Self {
+        Self::with_seed(WyRand::new().generate())
+    }
+
+    pub const fn with_seed(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RandomSource for Random {
+    fn generate(&mut self) -> u8 {
+        let n = WyRand::new_seed(self.seed).generate::<u8>();
+        self.seed = self.seed.wrapping_add(WYRAND_INCREMENT);
+        n
+    }
+
+    fn state(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_state(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
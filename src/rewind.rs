@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::VecDeque;
+
+use crate::cpu::Cpu;
+use crate::num::{write_varint, Reader};
+
+/// Ring buffer of savestates to step a [`Cpu`] backwards in time.
+///
+/// Only the newest snapshot is kept as is. Older ones are stored as the
+/// run-length encoded XOR against the snapshot that follows them, which is
+/// tiny since little of the memory changes between two snapshots. The oldest
+/// snapshots are dropped when the buffer grows past its memory budget.
+pub struct Rewinder {
+    interval: u32,
+    budget: usize,
+    frames: u32,
+    head: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    used: usize,
+}
+
+impl Rewinder {
+    /// Takes a snapshot every `interval` frames, using at most about
+    /// `budget` bytes.
+    pub fn new(interval: u32, budget: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            frames: 0,
+            head: None,
+            deltas: VecDeque::new(),
+            used: 0,
+        }
+    }
+
+    /// To be called once per frame, while the emulator runs forward.
+    pub fn on_frame(&mut self, cpu: &Cpu) {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            self.push(cpu.save_state());
+        }
+    }
+
+    /// Stores `state` as the newest snapshot.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(head) = self.head.take() {
+            self.used -= head.len();
+            if head.len() == state.len() {
+                let delta = encode_delta(&head, &state);
+                self.used += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                // The memory size changed with the quirks, start over.
+                self.deltas.clear();
+                self.used = 0;
+            }
+        }
+        self.used += state.len();
+        self.head = Some(state);
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Restores the newest snapshot into `cpu` and forgets it, so that
+    /// the next call goes further back in time.
+    ///
+    /// Returns `false` when there is nothing left to rewind to.
+    pub fn rewind(&mut self, cpu: &mut Cpu) -> bool {
+        let mut state = match self.head.take() {
+            Some(state) => state,
+            None => return false,
+        };
+        self.used -= state.len();
+        let restored = cpu.load_state(&state).is_ok();
+        if let Some(delta) = self.deltas.pop_back() {
+            self.used -= delta.len();
+            apply_delta(&mut state, &delta);
+            self.used += state.len();
+            self.head = Some(state);
+        }
+        self.frames = 0;
+        restored
+    }
+
+    /// Number of snapshots that can be rewound to.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + usize::from(self.head.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    /// Bytes used by the snapshots.
+    pub fn memory_usage(&self) -> usize {
+        self.used
+    }
+
+    pub fn clear(&mut self) {
+        self.head = None;
+        self.deltas.clear();
+        self.used = 0;
+        self.frames = 0;
+    }
+}
+
+// The delta is a sequence of (zero run length, literal length, literals),
+// lengths being LEB128 varints, of the XOR between `old` and `new`.
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let xor = old.iter().zip(new).map(|(a, b)| a ^ b).collect::<Vec<_>>();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take_while(|&&b| b == 0).count();
+        i += zeros;
+        let literals = xor[i..].iter().take_while(|&&b| b != 0).count();
+        write_varint(&mut out, zeros as u64);
+        write_varint(&mut out, literals as u64);
+        out.extend_from_slice(&xor[i..i + literals]);
+        i += literals;
+    }
+    out
+}
+
+// Turns the newer snapshot into the older one.
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut r = Reader::new(delta);
+    let mut i = 0;
+    while let (Ok(zeros), Ok(literals)) = (r.varint(), r.varint()) {
+        let (zeros, literals) = (zeros as usize, literals as usize);
+        i += zeros;
+        let Ok(bytes) = r.take(literals) else { break };
+        for (s, d) in state[i..i + literals].iter_mut().zip(bytes) {
+            *s ^= d;
+        }
+        i += literals;
+    }
+}
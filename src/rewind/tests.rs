@@ -0,0 +1,52 @@
+use super::*;
+use crate::quirks::Platform;
+
+#[test]
+fn test_delta() {
+    let old = [0, 0, 1, 2, 0, 0, 0, 7];
+    let new = [0, 0, 1, 3, 0, 5, 0, 7];
+    let delta = encode_delta(&old, &new);
+    assert_eq!(delta, [3, 1, 1, 1, 1, 5, 2, 0]);
+    let mut state = new;
+    apply_delta(&mut state, &delta);
+    assert_eq!(state, old);
+}
+
+#[test]
+fn test_rewind() {
+    // ADD V0, 0x1; JP 0x200
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    let mut rewinder = Rewinder::new(1, usize::MAX);
+    assert!(!rewinder.rewind(&mut cpu));
+
+    for _ in 0..10 {
+        cpu.execute_cycle().unwrap();
+        cpu.execute_cycle().unwrap();
+        rewinder.on_frame(&cpu);
+    }
+    assert_eq!(rewinder.len(), 10);
+    assert_eq!(cpu.registers()[0], 10);
+    for v0 in (1..=10).rev() {
+        assert!(rewinder.rewind(&mut cpu));
+        assert_eq!(cpu.registers()[0], v0);
+    }
+    assert!(!rewinder.rewind(&mut cpu));
+    assert!(rewinder.is_empty());
+    assert_eq!(rewinder.memory_usage(), 0);
+}
+
+#[test]
+fn test_budget() {
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    let full = cpu.save_state().len();
+    let mut rewinder = Rewinder::new(1, full + 100);
+    for _ in 0..100 {
+        cpu.execute_cycle().unwrap();
+        rewinder.on_frame(&cpu);
+    }
+    assert!(rewinder.memory_usage() <= full + 100);
+    assert!(rewinder.len() > 1);
+    assert!(rewinder.len() < 100);
+}
@@ -29,9 +29,14 @@ impl Stack {
         }
     }
 
+    /// Return addresses, from the bottom of the stack to the top.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.storage[..self.len]
+    }
+
     #[must_use]
     pub fn pop(&mut self) -> Option<u16> {
-        if self.len > 0 && self.len < MAX_STACK {
+        if self.len > 0 {
             self.len -= 1;
             Some(self.storage[self.len])
         } else {
@@ -13,3 +13,14 @@ fn test_stack() {
     assert_eq!(stack.pop(), None);
     assert_eq!(stack.pop(), None);
 }
+
+#[test]
+fn test_full_stack() {
+    let mut stack = Stack::new();
+    for i in 0..MAX_STACK as u16 {
+        stack.push(i).unwrap();
+    }
+    assert_eq!(stack.push(0), None);
+    assert_eq!(stack.as_slice().len(), MAX_STACK);
+    assert_eq!(stack.pop(), Some(MAX_STACK as u16 - 1));
+}
@@ -0,0 +1,493 @@
+//! Execution traces: what every instruction did, for the [`TraceSink`] of
+//! a [`Cpu`](crate::Cpu).
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::cpu::CodeWrite;
+use crate::num::{write_varint, ReadError, Reader};
+use crate::opcode::{Opcode, OpcodeKind};
+
+const MAGIC: &[u8; 4] = b"C8TR";
+const VERSION: u8 = 1;
+const REGISTERS: usize = 16;
+// Set in the change count of a binary record when I changed.
+const I_CHANGED: u8 = 0x80;
+
+/// An instruction that ran.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Instructions run before this one, see [`Cpu::cycles`](crate::Cpu::cycles).
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: Opcode,
+    pub kind: OpcodeKind,
+    /// V0 to VF before and after the instruction.
+    pub v_before: [u8; REGISTERS],
+    pub v_after: [u8; REGISTERS],
+    pub i_before: u16,
+    pub i_after: u16,
+}
+
+/// Receives every instruction a [`Cpu`](crate::Cpu) runs, once set with
+/// [`Cpu::set_tracer`](crate::Cpu::set_tracer).
+pub trait TraceSink: Send {
+    fn trace(&mut self, entry: &TraceEntry);
+
+    /// Called when the timers tick, once per 60 Hz frame.
+    fn frame(&mut self) {}
+
+    /// Called after [`trace`](TraceSink::trace) when the instruction wrote
+    /// over code.
+    fn code_write(&mut self, _write: &CodeWrite) {}
+
+    /// Writes what is buffered, returning the first error met while
+    /// tracing, if any.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How [`TextTrace`] writes an entry: text where `{cycle}`, `{pc}`,
+/// `{opcode}`, `{kind}`, `{diff}`, `{v}` and `{i}` are replaced.
+///
+/// The default, `{cycle} {pc}  {opcode}  {kind}  {diff}`, gives lines like
+/// `42 0x20A  7001  ADD V0, 0x01  V0: 02->03`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TraceFormat {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Cycle,
+    Pc,
+    Opcode,
+    Kind,
+    Diff,
+    Registers,
+    I,
+}
+
+/// Writes one line of text per instruction.
+pub struct TextTrace<W: Write + Send> {
+    w: W,
+    format: TraceFormat,
+    line: String,
+    error: Option<io::Error>,
+}
+
+/// Writes the compact binary format read by [`read_binary_trace`].
+pub struct BinaryTrace<W: Write + Send> {
+    w: W,
+    cycle: Option<u64>,
+    record: Vec<u8>,
+    error: Option<io::Error>,
+}
+
+/// Keeps the last instructions, to show what led to an error.
+///
+/// Clones share the same entries, so one can go to the `Cpu` while the
+/// other reads them.
+#[derive(Clone)]
+pub struct TraceRing {
+    entries: Arc<Mutex<VecDeque<TraceEntry>>>,
+    capacity: usize,
+}
+
+pub enum TraceError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl TraceEntry {
+    /// Registers whose value changed: their number, value before and after.
+    pub fn changes(&self) -> impl Iterator<Item = (u8, u8, u8)> + '_ {
+        (0..REGISTERS as u8)
+            .map(|x| (x, self.v_before[usize::from(x)], self.v_after[usize::from(x)]))
+            .filter(|(_, before, after)| before != after)
+    }
+}
+
+impl TraceFormat {
+    /// Appends `entry` to `out` in this format.
+    pub fn write(&self, out: &mut String, entry: &TraceEntry) {
+        for part in &self.parts {
+            let _ = match part {
+                Part::Text(text) => out.write_str(text),
+                Part::Cycle => write!(out, "{}", entry.cycle),
+                Part::Pc => write!(out, "{:#05X}", entry.pc),
+                Part::Opcode if entry.opcode.size() == 4 => {
+                    write!(out, "{:04X} {:04X}", entry.opcode.as_u16(), entry.opcode.operand())
+                }
+                Part::Opcode => write!(out, "{:04X}", entry.opcode.as_u16()),
+                Part::Kind => write!(out, "{:?}", entry.kind),
+                Part::Diff => {
+                    let mut sep = "";
+                    for (x, before, after) in entry.changes() {
+                        let _ = write!(out, "{}V{:X}: {:02X}->{:02X}", sep, x, before, after);
+                        sep = " ";
+                    }
+                    match entry.i_before == entry.i_after {
+                        true => Ok(()),
+                        false => {
+                            write!(out, "{}I: {:#05X}->{:#05X}", sep, entry.i_before, entry.i_after)
+                        }
+                    }
+                }
+                Part::Registers => write!(out, "{:02X?}", entry.v_after),
+                Part::I => write!(out, "{:#05X}", entry.i_after),
+            };
+        }
+    }
+}
+
+impl Default for TraceFormat {
+    fn default() -> Self {
+        "{cycle} {pc}  {opcode}  {kind}  {diff}".parse().unwrap()
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or(())? + start;
+            parts.push(match &rest[start + 1..end] {
+                "cycle" => Part::Cycle,
+                "pc" => Part::Pc,
+                "opcode" => Part::Opcode,
+                "kind" => Part::Kind,
+                "diff" => Part::Diff,
+                "v" => Part::Registers,
+                "i" => Part::I,
+                _ => return Err(()),
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl<W: Write + Send> TextTrace<W> {
+    pub fn new(w: W, format: TraceFormat) -> Self {
+        Self { w, format, line: String::new(), error: None }
+    }
+}
+
+impl<W: Write + Send> TraceSink for TextTrace<W> {
+    fn trace(&mut self, entry: &TraceEntry) {
+        if self.error.is_some() {
+            return;
+        }
+        self.line.clear();
+        self.format.write(&mut self.line, entry);
+        // Nothing is left at the end of lines without a diff.
+        let line = self.line.trim_end();
+        if let Err(e) = writeln!(self.w, "{}", line) {
+            self.error = Some(e);
+        }
+    }
+
+    fn code_write(&mut self, write: &CodeWrite) {
+        if self.error.is_some() {
+            return;
+        }
+        let written = match write.len {
+            1 => writeln!(self.w, "; code at {:#05X} overwritten", write.addr),
+            len => {
+                let last = write.addr + (len - 1);
+                writeln!(self.w, "; code at {:#05X}-{:#05X} overwritten", write.addr, last)
+            }
+        };
+        if let Err(e) = written {
+            self.error = Some(e);
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.w.flush(),
+        }
+    }
+}
+
+impl<W: Write + Send> BinaryTrace<W> {
+    pub fn new(w: W) -> Self {
+        Self { w, cycle: None, record: Vec::new(), error: None }
+    }
+}
+
+impl<W: Write + Send> TraceSink for BinaryTrace<W> {
+    /// # Format
+    ///
+    /// A header of the magic `C8TR` and the format version, currently 1.
+    /// With the first record come V0 to VF and I (little endian) as they
+    /// were before it, 18 bytes, so a trace can start at any point. Then
+    /// one record per instruction, varints being LEB128.
+    ///
+    /// | Size   | Content                                                   |
+    /// |--------|-----------------------------------------------------------|
+    /// | varint | Cycle, minus the cycle of the previous record if any      |
+    /// | 2      | PC, little endian                                         |
+    /// | 2      | Opcode, big endian as in memory                           |
+    /// | 2      | Operand of `F000 NNNN`, only for that instruction         |
+    /// | 1      | Number N of registers changed, bit 7 set when I changed   |
+    /// | 3 * N  | Register number, value before and after                   |
+    /// | 4      | I before and after, little endian, only when it changed   |
+    fn trace(&mut self, entry: &TraceEntry) {
+        if self.error.is_some() {
+            return;
+        }
+        let record = &mut self.record;
+        record.clear();
+        if self.cycle.is_none() {
+            record.extend_from_slice(MAGIC);
+            record.push(VERSION);
+            record.extend_from_slice(&entry.v_before);
+            record.extend_from_slice(&entry.i_before.to_le_bytes());
+        }
+        write_varint(record, entry.cycle - self.cycle.unwrap_or(0));
+        self.cycle = Some(entry.cycle);
+        record.extend_from_slice(&entry.pc.to_le_bytes());
+        record.extend_from_slice(&entry.opcode.as_u16().to_be_bytes());
+        if entry.opcode.size() == 4 {
+            record.extend_from_slice(&entry.opcode.operand().to_be_bytes());
+        }
+        let i_changed = entry.i_before != entry.i_after;
+        let count = entry.changes().count() as u8;
+        record.push(count | if i_changed { I_CHANGED } else { 0 });
+        for (x, before, after) in entry.changes() {
+            record.extend_from_slice(&[x, before, after]);
+        }
+        if i_changed {
+            record.extend_from_slice(&entry.i_before.to_le_bytes());
+            record.extend_from_slice(&entry.i_after.to_le_bytes());
+        }
+        if let Err(e) = self.w.write_all(record) {
+            self.error = Some(e);
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        // An empty trace still has its header.
+        if self.cycle.is_none() {
+            self.w.write_all(MAGIC)?;
+            self.w.write_all(&[VERSION])?;
+            self.cycle = Some(0);
+        }
+        self.w.flush()
+    }
+}
+
+/// Reads the entries written by a [`BinaryTrace`].
+pub fn read_binary_trace(bytes: &[u8]) -> Result<Vec<TraceEntry>, TraceError> {
+    let mut r = Reader::new(bytes);
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(TraceError::BadMagic);
+    }
+    let version = r.u8()?;
+    if version != VERSION {
+        return Err(TraceError::UnsupportedVersion(version));
+    }
+    let mut entries = Vec::new();
+    if r.bytes.is_empty() {
+        return Ok(entries);
+    }
+    let mut v: [u8; REGISTERS] = r.take(REGISTERS)?.try_into().unwrap();
+    let mut i = r.u16_le()?;
+    let mut cycle = None;
+    while !r.bytes.is_empty() {
+        let delta = r.varint()?;
+        let this_cycle = match cycle {
+            Some(c) => u64::checked_add(c, delta).ok_or(TraceError::Invalid("cycle"))?,
+            None => delta,
+        };
+        cycle = Some(this_cycle);
+        let pc = r.u16_le()?;
+        let word = r.u16_be()?;
+        let opcode = match Opcode::new(word).size() {
+            4 => Opcode::with_operand(word, r.u16_be()?),
+            _ => Opcode::new(word),
+        };
+        let kind = opcode.decode().ok_or(TraceError::Invalid("opcode"))?;
+        let changed = r.u8()?;
+        let v_before = v;
+        let mut before = v;
+        for _ in 0..changed & !I_CHANGED {
+            let change = r.take(3)?;
+            let x = usize::from(change[0]);
+            if x >= REGISTERS {
+                return Err(TraceError::Invalid("register"));
+            }
+            before[x] = change[1];
+            v[x] = change[2];
+        }
+        if before != v_before {
+            return Err(TraceError::Invalid("register"));
+        }
+        let i_before = i;
+        if changed & I_CHANGED != 0 {
+            if r.u16_le()? != i {
+                return Err(TraceError::Invalid("I"));
+            }
+            i = r.u16_le()?;
+        }
+        entries.push(TraceEntry {
+            cycle: this_cycle,
+            pc,
+            opcode,
+            kind,
+            v_before,
+            v_after: v,
+            i_before,
+            i_after: i,
+        });
+    }
+    Ok(entries)
+}
+
+impl From<ReadError> for TraceError {
+    fn from(e: ReadError) -> Self {
+        match e {
+            ReadError::Truncated => TraceError::Truncated,
+            ReadError::Invalid(what) => TraceError::Invalid(what),
+        }
+    }
+}
+
+impl TraceRing {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))), capacity }
+    }
+
+    /// The last instructions, oldest first.
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).iter().copied().collect()
+    }
+}
+
+impl TraceSink for TraceRing {
+    fn trace(&mut self, entry: &TraceEntry) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        if self.capacity > 0 {
+            entries.push_back(*entry);
+        }
+    }
+}
+
+impl<T: TraceSink + ?Sized> TraceSink for Box<T> {
+    fn trace(&mut self, entry: &TraceEntry) {
+        (**self).trace(entry);
+    }
+
+    fn frame(&mut self) {
+        (**self).frame();
+    }
+
+    fn code_write(&mut self, write: &CodeWrite) {
+        (**self).code_write(write);
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+// Several sinks at once.
+impl<A: TraceSink, B: TraceSink> TraceSink for (A, B) {
+    fn trace(&mut self, entry: &TraceEntry) {
+        self.0.trace(entry);
+        self.1.trace(entry);
+    }
+
+    fn frame(&mut self) {
+        self.0.frame();
+        self.1.frame();
+    }
+
+    fn code_write(&mut self, write: &CodeWrite) {
+        self.0.code_write(write);
+        self.1.code_write(write);
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let first = self.0.finish();
+        self.1.finish().and(first)
+    }
+}
+
+impl<T: TraceSink> TraceSink for Vec<T> {
+    fn trace(&mut self, entry: &TraceEntry) {
+        self.iter_mut().for_each(|sink| sink.trace(entry));
+    }
+
+    fn frame(&mut self) {
+        self.iter_mut().for_each(|sink| sink.frame());
+    }
+
+    fn code_write(&mut self, write: &CodeWrite) {
+        self.iter_mut().for_each(|sink| sink.code_write(write));
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // All are finished, the first error is returned.
+        let mut first = Ok(());
+        for sink in self {
+            first = first.and(sink.finish());
+        }
+        first
+    }
+}
+
+impl fmt::Debug for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut line = String::new();
+        TraceFormat::default().write(&mut line, self);
+        f.write_str(line.trim_end())
+    }
+}
+
+impl Error for TraceError {}
+impl fmt::Debug for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::BadMagic => f.write_str("not a trace: bad magic header"),
+            TraceError::UnsupportedVersion(v) => {
+                write!(f, "unsupported trace version: {} != {}", v, VERSION)
+            }
+            TraceError::Truncated => f.write_str("trace is truncated"),
+            TraceError::Invalid(what) => write!(f, "invalid trace: bad {}", what),
+        }
+    }
+}
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
@@ -0,0 +1,134 @@
+use super::*;
+use crate::asm::assemble;
+use crate::cpu::Cpu;
+use crate::quirks::Platform;
+
+const SOURCE: &str = "
+        LD V0, 5
+        ADD V0, 3
+        LD I, LONG 0x1234
+        LD VF, V0
+    end:
+        JP end
+";
+
+fn run(tracer: TraceRing, cycles: usize) -> Vec<TraceEntry> {
+    let mut cpu = Cpu::new(Platform::XoChip.quirks());
+    cpu.load_game(&assemble(SOURCE).unwrap()).unwrap();
+    cpu.set_tracer(Some(Box::new(tracer.clone())));
+    for _ in 0..cycles {
+        cpu.execute_cycle().unwrap();
+    }
+    assert!(cpu.take_tracer().is_some());
+    tracer.entries()
+}
+
+#[test]
+fn test_cpu_trace() {
+    let entries = run(TraceRing::new(16), 6);
+    assert_eq!(entries.len(), 6);
+    assert_eq!(entries.iter().map(|e| e.cycle).collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+    assert_eq!(
+        entries.iter().map(|e| e.pc).collect::<Vec<_>>(),
+        [0x200, 0x202, 0x204, 0x208, 0x20A, 0x20A]
+    );
+    assert_eq!(entries[1].kind, OpcodeKind::AddVxByte { x: 0, byte: 3 });
+    assert_eq!(entries[1].changes().collect::<Vec<_>>(), [(0, 5, 8)]);
+    assert_eq!((entries[2].i_before, entries[2].i_after), (0x50, 0x1234));
+    assert!(entries[2].opcode == Opcode::with_operand(0xF000, 0x1234));
+    assert_eq!(entries[3].changes().collect::<Vec<_>>(), [(0xF, 0, 8)]);
+    assert_eq!(entries[4].changes().count(), 0);
+
+    // Only the last ones are kept.
+    let last = run(TraceRing::new(2), 6);
+    assert_eq!(last, entries[4..]);
+    assert!(run(TraceRing::new(0), 6).is_empty());
+}
+
+#[test]
+fn test_text_trace() {
+    let entries = run(TraceRing::new(16), 4);
+    let mut out = Vec::new();
+    let mut sink = TextTrace::new(&mut out, TraceFormat::default());
+    entries.iter().for_each(|e| sink.trace(e));
+    sink.finish().unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "0 0x200  6005  LD V0, 0x5  V0: 00->05\n\
+         1 0x202  7003  ADD V0, 0x3  V0: 05->08\n\
+         2 0x204  F000 1234  LD I, LONG 0x1234  I: 0x050->0x1234\n\
+         3 0x208  8F00  LD VF, V0  VF: 00->08\n"
+    );
+
+    let format = "[{pc}] I={i} {v}".parse().unwrap();
+    let mut out = Vec::new();
+    TextTrace::new(&mut out, format).trace(&entries[1]);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "[0x202] I=0x050 [08, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00, 00]\n"
+    );
+
+    assert!("{cycle".parse::<TraceFormat>().is_err());
+    assert!("{sp}".parse::<TraceFormat>().is_err());
+}
+
+#[test]
+fn test_binary_trace() {
+    let entries = run(TraceRing::new(16), 6);
+    let mut bytes = Vec::new();
+    let mut sink = BinaryTrace::new(&mut bytes);
+    entries.iter().for_each(|e| sink.trace(e));
+    sink.finish().unwrap();
+    assert_eq!(read_binary_trace(&bytes).unwrap(), entries);
+
+    // A record of `JP end`: cycle delta, PC, opcode, no change.
+    assert_eq!(bytes[bytes.len() - 6..], [1, 0x0A, 0x02, 0x12, 0x0A, 0]);
+
+    // A trace can start anywhere.
+    let mut tail = Vec::new();
+    let mut sink = BinaryTrace::new(&mut tail);
+    entries[4..].iter().for_each(|e| sink.trace(e));
+    assert_eq!(read_binary_trace(&tail).unwrap()[0].cycle, 4);
+
+    let mut empty = Vec::new();
+    BinaryTrace::new(&mut empty).finish().unwrap();
+    assert!(read_binary_trace(&empty).unwrap().is_empty());
+
+    assert!(matches!(read_binary_trace(b"C8MV\x01"), Err(TraceError::BadMagic)));
+    assert!(matches!(read_binary_trace(b"C8TR\x02"), Err(TraceError::UnsupportedVersion(2))));
+    assert!(matches!(read_binary_trace(&bytes[..bytes.len() - 1]), Err(TraceError::Truncated)));
+    let mut bad = bytes[..23].to_vec();
+    bad.extend_from_slice(&[0, 0x00, 0x02, 0x60, 0x05, 1, 16, 0, 5]);
+    assert!(matches!(read_binary_trace(&bad), Err(TraceError::Invalid("register"))));
+}
+
+#[test]
+fn test_code_write_trace() {
+    // LD I, 0x202; LD V0, 0x1; LD [I], V0
+    let rom = [0xA2, 0x02, 0x60, 0x01, 0xF0, 0x55];
+    let out = Arc::new(Mutex::new(Vec::new()));
+    let mut cpu = Cpu::new(Platform::Chip48.quirks());
+    cpu.load_game(&rom).unwrap();
+    cpu.set_tracer(Some(Box::new(TextTrace::new(
+        SharedBuffer(out.clone()),
+        "{pc}".parse().unwrap(),
+    ))));
+    for _ in 0..3 {
+        cpu.execute_cycle().unwrap();
+    }
+    cpu.take_tracer().unwrap().finish().unwrap();
+    let text = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+    assert_eq!(text, "0x200\n0x202\n0x204\n; code at 0x202 overwritten\n");
+}
+
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
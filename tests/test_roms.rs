@@ -0,0 +1,382 @@
+//! Runs the ROMs of `test_roms/` on every platform and compares the final
+//! screens with the goldens of `tests/golden/`.
+//!
+//! What each screen says about the opcodes must match the failures the
+//! ROM is expected to report, see [`Failure`].
+//!
+//! `CHIP8_BLESS=1 cargo test --test test_roms` writes the goldens again.
+//! Run with `--nocapture` to see what each screen says about the opcodes.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chip8emu::{ascii_art, Cpu, Platform};
+
+const FRAMES: u32 = 300;
+const CYCLES_PER_FRAME: u32 = 10;
+const SEED: u64 = 0;
+// Where the built-in 4x5 digits are in memory.
+const FONT_ADDR: usize = 0x50;
+
+const PLATFORMS: [(&str, Platform); 5] = [
+    ("cosmac", Platform::Cosmac),
+    ("chip48", Platform::Chip48),
+    ("superchip", Platform::SuperChip),
+    ("amiga", Platform::Amiga),
+    ("xochip", Platform::XoChip),
+];
+
+const OK: &[&str] = &["####.#..#", "#..#.#.#.", "#..#.##..", "#..#.#.#.", "####.#..#"];
+
+const ROMS: [RomTest; 5] = [
+    RomTest {
+        path: "corax89/test_opcode.ch8",
+        tests: &[
+            "3XNN", "00EE", "8XY5", "4XNN", "8XY0", "8XY6", "5XY0", "8XY1", "8XYE", "7XNN", "8XY2",
+            "FX55", "9XY0", "8XY3", "FX33", "ANNN", "8XY4", "FX1E",
+        ],
+        screen: Screen::Grid {
+            ok: &["###.#.#", "#.#.##.", "#.#.#.#", "###.#.#"],
+            columns: &[10, 32, 52],
+            top: 1,
+            height: 5,
+        },
+        failures: &[],
+    },
+    RomTest {
+        path: "BC_test/bc_test.ch8",
+        tests: &[
+            "3XNN",
+            "5XY0",
+            "4XNN",
+            "7XNN",
+            "8XY5 VF borrow",
+            "8XY5 VF no borrow",
+            "8XY7 VF borrow",
+            "8XY7 VF no borrow",
+            "8XY1",
+            "8XY2",
+            "8XY3",
+            "8XYE VF 1",
+            "8XYE VF 0",
+            "8XY6 VF 1",
+            "8XY6 VF 0",
+            "FX55/FX65",
+            "FX33",
+        ],
+        screen: Screen::Number {
+            pass: Some(&[
+                "####.....####...#....#",
+                "#...#...#....#..##...#",
+                "#...#...#....#..#.#..#",
+                "####....#....#..#..#.#",
+                "#...#...#....#..#...##",
+                "#...#...#....#..#....#",
+                "#...#...#....#..#....#",
+                "####.....####...#....#",
+            ]),
+            first: 1,
+        },
+        failures: &[
+            // `shift_uses_vy`: the ROM expects VX shifted in place. The
+            // next tests don't run, `FX55/FX65` would fail on
+            // `load_store_increments_i` as well.
+            Failure { test: "8XYE VF 1", platforms: &["cosmac", "xochip"] },
+        ],
+    },
+    RomTest {
+        path: "sctest/SCTEST.ch8",
+        tests: &[
+            "FX65",
+            "font",
+            "8XY4 VF 254+1",
+            "8XY4 254+1",
+            "8XY4 VF 255+1",
+            "8XY4 255+1",
+            "8XY5 VF 1-1",
+            "8XY5 1-1",
+            "8XY5 VF 0-1",
+            "8XY5 0-1",
+            "8XY7 VF 1-1",
+            "8XY7 1-1",
+            "8XY7 VF 0-1",
+            "8XY7 0-1",
+            "8XY6 VF 255",
+            "8XY6 255",
+            "8XY6 VF 64",
+            "8XY6 64",
+            "8XYE VF 32",
+            "8XYE 32",
+            "8XYE VF 250",
+            "8XYE 250",
+            "8XY3",
+            "FX75/FX85",
+            "FX1E VF",
+        ],
+        screen: Screen::Number { pass: Some(OK), first: 0 },
+        failures: &[
+            // Expects VF set when I goes past 0xFFF, which only
+            // `add_i_sets_vf` does.
+            Failure { test: "FX1E VF", platforms: &["cosmac", "chip48", "superchip", "xochip"] },
+        ],
+    },
+    RomTest {
+        path: "skosulor/c8_test.c8",
+        tests: &[
+            "3XNN",
+            "4XNN",
+            "5XY0",
+            "7XNN",
+            "8XY0",
+            "8XY1",
+            "8XY2",
+            "8XY3",
+            "8XY4",
+            "8XY5",
+            "8XY6",
+            "8XY7",
+            "8XYE",
+            "9XY0",
+            "BNNN",
+            "CXNN",
+            "FX07",
+            "FX33/FX65/ANNN",
+            "FX55/FX65",
+            "FX1E",
+        ],
+        // Its "OK" isn't seen on any platform, see `failures`, so any
+        // screen without a number passes.
+        screen: Screen::Number { pass: None, first: 0 },
+        failures: &[
+            // `jump_uses_vx`: `B2FC` jumps to 0x2FC + V2 instead of V0.
+            Failure { test: "BNNN", platforms: &["chip48", "superchip", "amiga"] },
+            // Not BNNN: `shift_uses_vy` fails 8XY6, whose failure jumps to
+            // 0x300 in the ROM, skipping to CXNN without counting. Then
+            // `FX55/FX65` fails on `load_store_increments_i`, and the number
+            // shown, 4 short, is that of BNNN.
+            Failure { test: "BNNN", platforms: &["cosmac", "xochip"] },
+        ],
+    },
+    RomTest {
+        path: "metteo/chip8-test-rom.ch8",
+        tests: &["chip8-test-rom"],
+        screen: Screen::Number { pass: Some(OK), first: 0 },
+        failures: &[],
+    },
+];
+
+struct RomTest {
+    path: &'static str,
+    // What the ROM tests, in the order it reports them.
+    tests: &'static [&'static str],
+    screen: Screen,
+    // Every other test passes, or doesn't run after a failure.
+    failures: &'static [Failure],
+}
+
+// A test reported as failed on `platforms`, as the ROM expects another
+// behaviour than theirs.
+struct Failure {
+    test: &'static str,
+    platforms: &'static [&'static str],
+}
+
+enum Screen {
+    // One cell per test, showing `ok` when it passed, row by row.
+    Grid { ok: &'static [&'static str], columns: &'static [usize], top: usize, height: usize },
+    // `pass` when every test passed, else the number of the failed test
+    // in the built-in font, the first test being `first`.
+    Number { pass: Option<&'static [&'static str]>, first: u32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Pass,
+    Fail,
+    Untested,
+}
+
+struct Pixels<'a> {
+    width: usize,
+    height: usize,
+    pixels: &'a [u8],
+}
+
+#[test]
+fn test_golden_screens() {
+    let bless = env::var_os("CHIP8_BLESS").is_some();
+    let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut mismatches = Vec::new();
+    for rom_test in &ROMS {
+        let rom_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_roms").join(rom_test.path);
+        let rom = fs::read(&rom_path).unwrap();
+        let stem = rom_path.file_stem().unwrap().to_str().unwrap().to_lowercase();
+        for (platform_name, platform) in PLATFORMS {
+            let (cpu, error) = run(&rom, platform);
+            let mut screen = ascii_art(cpu.resolution(), cpu.get_vram());
+            if let Some(error) = error {
+                screen.push_str(&format!("error: {}\n", error));
+            }
+
+            let report = report(rom_test, &cpu);
+            println!("{} on {}:", rom_test.path, platform_name);
+            for (name, outcome) in &report {
+                println!("    {:<16} {:?}", name, outcome);
+            }
+            let expected = expected_report(rom_test, platform_name);
+            if report != expected {
+                mismatches.push(format!(
+                    "{} on {} reports {:?}, expected {:?}",
+                    rom_test.path, platform_name, report, expected
+                ));
+            }
+
+            let golden_path = golden_dir.join(format!("{}.{}.txt", stem, platform_name));
+            if bless {
+                fs::create_dir_all(&golden_dir).unwrap();
+                fs::write(&golden_path, &screen).unwrap();
+                continue;
+            }
+            let golden = fs::read_to_string(&golden_path).unwrap_or_default();
+            if golden != screen {
+                let failed = report.iter().filter(|(_, o)| *o == Outcome::Fail);
+                let failed = failed.map(|(name, _)| *name).collect::<Vec<_>>();
+                mismatches.push(format!(
+                    "{} differs, failed: {:?}\n{}",
+                    golden_path.display(),
+                    failed,
+                    screen
+                ));
+            }
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+fn run(rom: &[u8], platform: Platform) -> (Cpu, Option<String>) {
+    let mut cpu = Cpu::with_seed(platform.quirks(), SEED);
+    cpu.load_game(rom).unwrap();
+    for _ in 0..FRAMES {
+        if let Err(e) = cpu.run_frame(CYCLES_PER_FRAME) {
+            return (cpu, Some(e.to_string()));
+        }
+    }
+    (cpu, None)
+}
+
+// What the screen must say on `platform`: the expected failures, and
+// with a number screen, the tests after a failure don't run.
+fn expected_report(rom_test: &RomTest, platform: &str) -> Vec<(&'static str, Outcome)> {
+    let failed = |name: &str| {
+        rom_test.failures.iter().any(|f| f.test == name && f.platforms.contains(&platform))
+    };
+    let stops = matches!(rom_test.screen, Screen::Number { .. });
+    let mut stopped = false;
+    rom_test
+        .tests
+        .iter()
+        .map(|&name| {
+            let outcome = if stopped {
+                Outcome::Untested
+            } else if failed(name) {
+                stopped = stops;
+                Outcome::Fail
+            } else {
+                Outcome::Pass
+            };
+            (name, outcome)
+        })
+        .collect()
+}
+
+// What the final screen says about each test of the ROM.
+fn report(rom_test: &RomTest, cpu: &Cpu) -> Vec<(&'static str, Outcome)> {
+    let (width, height) = cpu.resolution();
+    let pixels =
+        Pixels { width: usize::from(width), height: usize::from(height), pixels: cpu.get_vram() };
+    let tests = rom_test.tests.iter().copied();
+    match rom_test.screen {
+        Screen::Grid { ok, columns, top, height } => tests
+            .enumerate()
+            .map(|(i, name)| {
+                let (x, y) = (columns[i % columns.len()], top + height * (i / columns.len()));
+                let outcome = if pixels.matches(ok, x, y) { Outcome::Pass } else { Outcome::Fail };
+                (name, outcome)
+            })
+            .collect(),
+        Screen::Number { pass, first } => {
+            let failed = read_number(&pixels, &cpu.memory()[FONT_ADDR..FONT_ADDR + 5 * 16]);
+            let passed = match pass {
+                Some(glyph) => pixels.find(glyph),
+                None => failed.is_none(),
+            };
+            let failed = failed.and_then(|n| n.checked_sub(first)).map(|n| n as usize);
+            tests
+                .enumerate()
+                .map(|(i, name)| {
+                    let outcome = match failed {
+                        _ if passed => Outcome::Pass,
+                        Some(f) if i < f => Outcome::Pass,
+                        Some(f) if i == f => Outcome::Fail,
+                        _ => Outcome::Untested,
+                    };
+                    (name, outcome)
+                })
+                .collect()
+        }
+    }
+}
+
+// The digits drawn with the built-in font, left to right, on their own.
+fn read_number(pixels: &Pixels, font: &[u8]) -> Option<u32> {
+    let glyphs = font
+        .chunks(5)
+        .map(|rows| {
+            rows.iter().map(|r| format!("{:04b}", r >> 4).replace('0', ".").replace('1', "#"))
+        })
+        .map(|rows| rows.collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    for y in 0..pixels.height.saturating_sub(4) {
+        let mut digits = Vec::new();
+        for x in 0..pixels.width.saturating_sub(3) {
+            let blank_left = x == 0 || (y..y + 5).all(|y| !pixels.get(x - 1, y));
+            let blank_right = (y..y + 5).all(|y| !pixels.get(x + 4, y));
+            if !blank_left || !blank_right {
+                continue;
+            }
+            let rows = glyphs.iter().map(|g| g.iter().map(String::as_str).collect::<Vec<_>>());
+            if let Some(digit) = rows.take(10).position(|g| pixels.matches(&g, x, y)) {
+                digits.push(digit as u32);
+            }
+        }
+        if !digits.is_empty() {
+            return Some(digits.iter().fold(0, |n, d| n * 10 + d));
+        }
+    }
+    None
+}
+
+impl Pixels<'_> {
+    fn get(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.pixels[y * self.width + x] != 0
+    }
+
+    // Whether `glyph` is at `(x, y)`, with blank lines above and below.
+    fn matches<S: AsRef<str>>(&self, glyph: &[S], x: usize, y: usize) -> bool {
+        let width = glyph[0].as_ref().len();
+        let blank = |y: usize| (x..x + width).all(|x| !self.get(x, y));
+        (y == 0 || blank(y - 1))
+            && blank(y + glyph.len())
+            && glyph.iter().enumerate().all(|(dy, row)| {
+                row.as_ref()
+                    .bytes()
+                    .enumerate()
+                    .all(|(dx, b)| self.get(x + dx, y + dy) == (b == b'#'))
+            })
+    }
+
+    fn find(&self, glyph: &[&str]) -> bool {
+        (0..self.height).any(|y| (0..self.width).any(|x| self.matches(glyph, x, y)))
+    }
+}
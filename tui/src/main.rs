@@ -0,0 +1,343 @@
+//! Runs a ROM in the terminal, for when there is no window to open.
+
+use std::borrow::Cow;
+use std::env;
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chip8emu::{compile_octo, Cpu, CpuState, KeyCode, Platform, PALETTE};
+use crossterm::event::{
+    self, Event, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute};
+
+const FPS: u32 = 60;
+const FRAME_DURATION: Duration = Duration::from_nanos((10_u32.pow(9) / FPS) as u64);
+// 540 instructions per second, like the interpreter.
+const CYCLES_PER_FRAME: u32 = 9;
+// Most terminals only send key presses. Without a release, a key is let go
+// after this many frames, unless the key repeats in the meantime.
+const KEY_HOLD_FRAMES: u32 = 15;
+
+static IBM_LOGO: &[u8] = include_bytes!("../../IBM_Logo.ch8");
+
+struct Args {
+    rom_path: Option<OsString>,
+    platform: Platform,
+    seed: Option<u64>,
+    braille: bool,
+}
+
+// Puts the terminal back the way it was, even on panic.
+struct Terminal {
+    enhanced: bool,
+}
+
+fn main() {
+    let args = parse_args();
+    let bin: Cow<[u8]> = match &args.rom_path {
+        Some(path) if Path::new(path).extension().is_some_and(|ext| ext == "8o") => {
+            let source = fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("Cannot read {}: {}", path.to_string_lossy(), e);
+                process::exit(1);
+            });
+            match compile_octo(&source) {
+                Ok(rom) => rom.into(),
+                Err(e) => {
+                    eprintln!("{}:{}", Path::new(path).display(), e);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(path) => match fs::read(path) {
+            Ok(rom) => rom.into(),
+            Err(e) => {
+                eprintln!("Cannot read {}: {}", path.to_string_lossy(), e);
+                process::exit(1);
+            }
+        },
+        None => IBM_LOGO.into(),
+    };
+    let mut cpu: Cpu = match args.seed {
+        Some(seed) => Cpu::with_seed(args.platform.quirks(), seed),
+        None => Cpu::new(args.platform.quirks()),
+    };
+    if let Err(e) = cpu.load_game(&bin) {
+        eprintln!("Cannot load the ROM: {}", e);
+        process::exit(1);
+    }
+
+    let terminal = Terminal::enter().unwrap_or_else(|e| {
+        eprintln!("Cannot use the terminal: {}", e);
+        process::exit(1);
+    });
+    let result = run(&mut cpu, &args, terminal.enhanced);
+    drop(terminal);
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        eprintln!(
+            "PC: {:#05X}  I: {:#05X}  stack: {:03X?}\n\
+            V0-VF: {:02X?}",
+            cpu.pc(),
+            cpu.i(),
+            cpu.stack(),
+            cpu.registers(),
+        );
+        process::exit(1);
+    }
+}
+
+fn run(cpu: &mut Cpu, args: &Args, enhanced: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stdout = io::stdout().lock();
+    let mut screen = String::new();
+    let mut shown: Option<((u16, u16), Vec<u8>)> = None;
+    // Frames left before each key is let go, 0 when up.
+    let mut held = [0; 16];
+    let mut muted = false;
+    let mut beeping = false;
+    loop {
+        let start = Instant::now();
+        while event::poll(Duration::ZERO)? {
+            let (code, kind) = match event::read()? {
+                Event::Key(KeyEvent { code, modifiers, kind, .. }) => {
+                    let ctrl_c = modifiers.contains(KeyModifiers::CONTROL)
+                        && code == event::KeyCode::Char('c');
+                    if ctrl_c || code == event::KeyCode::Esc {
+                        return Ok(());
+                    }
+                    (code, kind)
+                }
+                // Draws everything again at the new size.
+                Event::Resize(..) => {
+                    shown = None;
+                    continue;
+                }
+                _ => continue,
+            };
+            if let (event::KeyCode::Char('m'), KeyEventKind::Press) = (code, kind) {
+                muted = !muted;
+                continue;
+            }
+            if let Some(kc) = keymap(code) {
+                let pressed = kind != KeyEventKind::Release;
+                if pressed && held[kc as usize] == 0 {
+                    if let CpuState::Paused = cpu.state {
+                        cpu.state = CpuState::Running;
+                    }
+                    cpu.set_key_state(kc, true);
+                }
+                held[kc as usize] = match (pressed, enhanced) {
+                    (false, _) => 0,
+                    (true, true) => u32::MAX,
+                    (true, false) => KEY_HOLD_FRAMES,
+                };
+                if !pressed {
+                    cpu.set_key_state(kc, false);
+                }
+            }
+        }
+        if !enhanced {
+            for (k, frames) in held.iter_mut().enumerate().filter(|(_, f)| **f > 0) {
+                *frames -= 1;
+                if *frames == 0 {
+                    cpu.set_key_state(KeyCode::try_from(k as u8).unwrap(), false);
+                }
+            }
+        }
+
+        if let CpuState::Halted = cpu.state {
+            return Ok(());
+        }
+        cpu.run_frame(CYCLES_PER_FRAME)?;
+
+        let size = cpu.resolution();
+        if shown.as_ref().is_none_or(|(s, pixels)| *s != size || pixels != cpu.get_vram()) {
+            if shown.as_ref().is_none_or(|(s, _)| *s != size) {
+                stdout.write_all(b"\x1b[2J")?;
+            }
+            screen.clear();
+            screen.push_str("\x1b[H");
+            if args.braille {
+                draw_braille(&mut screen, size, cpu.get_vram());
+            } else {
+                draw_half_blocks(&mut screen, size, cpu.get_vram());
+            }
+            screen.push_str("\x1b[0m Esc quits, M toggles the bell\x1b[K");
+            stdout.write_all(screen.as_bytes())?;
+            stdout.flush()?;
+            shown = Some((size, cpu.get_vram().to_vec()));
+        }
+        // The terminal bell rings once per beep.
+        if cpu.is_sound_active() && !beeping && !muted {
+            stdout.write_all(b"\x07")?;
+            stdout.flush()?;
+        }
+        beeping = cpu.is_sound_active();
+
+        if let Some(dur) = FRAME_DURATION.checked_sub(start.elapsed()) {
+            thread::sleep(dur);
+        }
+    }
+}
+
+// One character per two rows, `▀` having the top pixel as foreground
+// colour and the bottom one as background colour.
+fn draw_half_blocks(out: &mut String, (width, height): (u16, u16), pixels: &[u8]) {
+    let width = usize::from(width);
+    let rows = pixels.chunks(width).take(usize::from(height)).collect::<Vec<_>>();
+    for pair in rows.chunks(2) {
+        let mut colours = None;
+        for x in 0..width {
+            let top = pair[0][x] & 0b11;
+            let bottom = pair.get(1).map_or(0, |row| row[x] & 0b11);
+            if colours != Some((top, bottom)) {
+                let ([r, g, b], [br, bg, bb]) = (PALETTE[top as usize], PALETTE[bottom as usize]);
+                let _ = write!(out, "\x1b[38;2;{};{};{};48;2;{};{};{}m", r, g, b, br, bg, bb);
+                colours = Some((top, bottom));
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+}
+
+// One character per 2x4 pixels, in the colour of the brightest of them.
+fn draw_braille(out: &mut String, (width, height): (u16, u16), pixels: &[u8]) {
+    // Bit of the dot for each pixel of a cell, row by row.
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let (width, height) = (usize::from(width), usize::from(height));
+    let mut colour = None;
+    for y in (0..height).step_by(4) {
+        for x in (0..width).step_by(2) {
+            let (mut dots, mut brightest) = (0, 0);
+            for (dy, row) in DOTS.iter().enumerate().filter(|(dy, _)| y + dy < height) {
+                for (dx, dot) in row.iter().enumerate().filter(|(dx, _)| x + dx < width) {
+                    let pixel = pixels[(y + dy) * width + x + dx] & 0b11;
+                    if pixel != 0 {
+                        dots |= dot;
+                        brightest = brightest.max(pixel);
+                    }
+                }
+            }
+            if brightest != 0 && colour != Some(brightest) {
+                let [r, g, b] = PALETTE[usize::from(brightest)];
+                let _ = write!(out, "\x1b[38;2;{};{};{}m", r, g, b);
+                colour = Some(brightest);
+            }
+            out.push(char::from_u32(0x2800 + dots).unwrap());
+        }
+        out.push_str("\r\n");
+    }
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+        // Terminals with the kitty keyboard protocol report key releases.
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            let flags = KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+            execute!(stdout, PushKeyboardEnhancementFlags(flags))?;
+        }
+        Ok(Self { enhanced })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.enhanced {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn parse_args() -> Args {
+    let mut args =
+        Args { rom_path: None, platform: Platform::default(), seed: None, braille: false };
+    let mut argv = env::args_os().skip(1);
+    while let Some(arg) = argv.next() {
+        if arg == "--platform" {
+            let name = argv.next().unwrap_or_else(|| usage());
+            args.platform = match name.to_str().map(str::parse) {
+                Some(Ok(p)) => p,
+                _ => usage(),
+            };
+        } else if arg == "--seed" {
+            let seed = argv.next().unwrap_or_else(|| usage());
+            args.seed = match seed.to_str().map(str::parse) {
+                Some(Ok(n)) => Some(n),
+                _ => usage(),
+            };
+        } else if arg == "--braille" {
+            args.braille = true;
+        } else if args.rom_path.is_none() {
+            args.rom_path = Some(arg);
+        } else {
+            usage();
+        }
+    }
+    args
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: tui [--platform cosmac|chip48|superchip|amiga|xochip] [--seed N]\n\
+        \x20          [--braille] [ROM]\n\
+        \n\
+        Runs ROM in the terminal, the IBM logo ROM when no ROM is given. A ROM\n\
+        ending in .8o is compiled from Octo source first.\n\
+        Pixels are drawn as half blocks, two per character, or with --braille\n\
+        as braille dots, eight per character. The terminal needs 24-bit colours.\n\
+        \n\
+        The keys are those of the interpreter, 1234/QWER/ASDF/ZXCV and arrows.\n\
+        Esc or Ctrl+C quits, M toggles the bell."
+    );
+    process::exit(2);
+}
+
+// Same layout as the interpreter, on the characters typed.
+fn keymap(code: event::KeyCode) -> Option<KeyCode> {
+    use event::KeyCode::{Char, Down, Left, Right, Up};
+
+    let kc = match code {
+        Char(c) => match c.to_ascii_lowercase() {
+            '1' => KeyCode::K1,
+            '2' => KeyCode::K2,
+            '3' => KeyCode::K3,
+            '4' => KeyCode::KC,
+            'q' => KeyCode::K4,
+            'w' => KeyCode::K5,
+            'e' => KeyCode::K6,
+            'r' => KeyCode::KD,
+            'a' => KeyCode::K7,
+            's' => KeyCode::K8,
+            'd' => KeyCode::K9,
+            'f' => KeyCode::KE,
+            'z' => KeyCode::KA,
+            'x' => KeyCode::KB,
+            'c' => KeyCode::KC,
+            'v' => KeyCode::KF,
+            _ => return None,
+        },
+
+        Left => KeyCode::K4,
+        Up => KeyCode::K2,
+        Right => KeyCode::K6,
+        Down => KeyCode::K8,
+        _ => return None,
+    };
+    Some(kc)
+}